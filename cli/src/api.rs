@@ -0,0 +1,120 @@
+use aip_types::{
+    api::{CreateUserToken, GetAiPilotResponse, GetMatchResponse, PostAiPilotResponse},
+    model::NewUserToken,
+};
+use reqwest::{Method, RequestBuilder, Response, StatusCode, redirect};
+use serde::{Deserialize, de::DeserializeOwned};
+
+/// The site's JSON API, authenticated with a user token.
+pub struct Api {
+    client: reqwest::Client,
+    url: String,
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct ErrorMessage {
+    message: String,
+}
+
+impl Api {
+    pub fn new(url: &str, token: String) -> Self {
+        Api {
+            // Unauthenticated requests are redirected to the login page, which is no use here
+            client: reqwest::Client::builder()
+                .redirect(redirect::Policy::none())
+                .build()
+                .expect("Failed to build HTTP client"),
+            url: url.trim_end_matches('/').to_string(),
+            token,
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.client
+            .request(method, format!("{}/api{}", self.url, path))
+            .header("x-auth-token", &self.token)
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response, String> {
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Request to {} failed: {}", self.url, e))?;
+        let status = response.status();
+
+        if status.is_redirection() || status == StatusCode::UNAUTHORIZED {
+            return Err("Not signed in, the token is missing, invalid or expired".into());
+        }
+        if !status.is_success() {
+            let message = match response.json::<ErrorMessage>().await {
+                Ok(e) => e.message,
+                Err(_) => status.to_string(),
+            };
+            return Err(format!("{} ({})", message, status.as_u16()));
+        }
+
+        Ok(response)
+    }
+
+    async fn json<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, String> {
+        self.send(request)
+            .await?
+            .json()
+            .await
+            .map_err(|e| format!("Unexpected response: {}", e))
+    }
+
+    /// Whether the site accepts the token at all, regardless of its scopes.
+    pub async fn check_token(&self) -> Result<bool, String> {
+        let status = self
+            .request(Method::GET, "/aipilots")
+            .send()
+            .await
+            .map_err(|e| format!("Request to {} failed: {}", self.url, e))?
+            .status();
+
+        Ok(!status.is_redirection() && status != StatusCode::UNAUTHORIZED)
+    }
+
+    pub async fn pilots(&self) -> Result<GetAiPilotResponse, String> {
+        self.json(self.request(Method::GET, "/aipilots")).await
+    }
+
+    pub async fn matches(&self, query: &[(&str, String)]) -> Result<GetMatchResponse, String> {
+        self.json(self.request(Method::GET, "/matches").query(query))
+            .await
+    }
+
+    pub async fn upload(&self, name: &str, data: Vec<u8>) -> Result<PostAiPilotResponse, String> {
+        let request = self
+            .request(Method::POST, "/aipilot/upload")
+            .query(&[("name", name)])
+            .header("content-type", "application/octet-stream")
+            .body(data);
+        self.json(request).await
+    }
+
+    /// Queues a fight, returning the id of its match.
+    pub async fn fight(&self, pilot_a: &str, pilot_b: &str) -> Result<String, String> {
+        let request = self
+            .request(Method::POST, "/matches")
+            .query(&[("pilot_a", pilot_a), ("pilot_b", pilot_b)]);
+        self.send(request)
+            .await?
+            .text()
+            .await
+            .map_err(|e| format!("Unexpected response: {}", e))
+    }
+
+    pub async fn create_token(&self, body: &CreateUserToken) -> Result<NewUserToken, String> {
+        self.json(self.request(Method::POST, "/user_token").json(body))
+            .await
+    }
+
+    pub async fn delete_token(&self, token_id: i64) -> Result<(), String> {
+        self.send(self.request(Method::DELETE, &format!("/user_token/{}", token_id)))
+            .await
+            .map(|_| ())
+    }
+}
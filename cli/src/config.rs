@@ -0,0 +1,55 @@
+use std::{env, fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// What `aip login` stores, in `$AIP_CONFIG` or `~/.config/aip/config.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Config {
+    pub url: Option<String>,
+    pub token: Option<String>,
+}
+
+fn path() -> Result<PathBuf, String> {
+    if let Ok(path) = env::var("AIP_CONFIG") {
+        return Ok(PathBuf::from(path));
+    }
+
+    let config_dir = env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map_err(|_| "Cannot find a config directory, set AIP_CONFIG".to_string())?;
+    Ok(config_dir.join("aip").join("config.json"))
+}
+
+impl Config {
+    /// The stored config, empty if there is none yet.
+    pub fn load() -> Result<Config, String> {
+        let path = path()?;
+        match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("Malformed config {}: {}", path.display(), e)),
+            Err(_) => Ok(Config::default()),
+        }
+    }
+
+    pub fn save(&self) -> Result<PathBuf, String> {
+        let path = path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+
+        let content = serde_json::to_string_pretty(self).expect("Config is always serializable");
+        fs::write(&path, content)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+        // The token grants access to the account, keep it away from other users
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = fs::set_permissions(&path, fs::Permissions::from_mode(0o600));
+        }
+
+        Ok(path)
+    }
+}
@@ -0,0 +1,335 @@
+mod api;
+mod config;
+mod output;
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+
+use aip_types::{api::CreateUserToken, scope::Scope};
+use clap::{Parser, Subcommand};
+use client::models::{MatchResult, match_result::Winner};
+
+use crate::{api::Api, config::Config, output::Format};
+
+/// Upload AI pilots and queue matches from the command line.
+#[derive(Parser)]
+#[command(name = "aip", version)]
+struct Cli {
+    /// Base URL of the site, defaults to the one stored by `aip login`
+    #[arg(long, env = "AIP_URL", global = true)]
+    url: Option<String>,
+    /// User token, defaults to the one stored by `aip login`
+    #[arg(long, env = "AIP_TOKEN", global = true, hide_env_values = true)]
+    token: Option<String>,
+    #[arg(short, long, value_enum, default_value_t, global = true)]
+    output: Format,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Store a user token, created on the site's token page, for later commands
+    Login {
+        /// Read from stdin if left out
+        token: Option<String>,
+    },
+    /// Upload a new version of a pilot, creating it if it doesn't exist yet
+    Upload { name: String, file: String },
+    /// List every pilot
+    Pilots,
+    /// List matches, newest first
+    Matches {
+        /// Only matches of this pilot, by name or id
+        #[arg(long)]
+        pilot: Option<String>,
+        #[arg(long, default_value_t = 20)]
+        limit: u32,
+    },
+    /// Queue a match between two pilots, by name or id
+    Fight {
+        a: String,
+        b: String,
+        /// Wait for the result instead of returning once it is queued
+        #[arg(long)]
+        wait: bool,
+        /// Seconds to wait for the result
+        #[arg(long, default_value_t = 600)]
+        timeout: u64,
+    },
+    /// Manage user tokens
+    Tokens {
+        #[command(subcommand)]
+        command: TokensCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum TokensCommand {
+    /// Create a token, its secret is only shown once
+    Create {
+        name: String,
+        /// Scope to grant, such as `pilots:read` or `pilot:<name>:upload`, repeatable
+        #[arg(long = "scope", required = true)]
+        scopes: Vec<Scope>,
+        /// Days until the token expires, never if left out
+        #[arg(long)]
+        expires_in_days: Option<i64>,
+    },
+    /// Delete a token by id
+    Delete { id: i64 },
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), String> {
+    let mut config = Config::load()?;
+    let url = cli
+        .url
+        .or(config.url.clone())
+        .ok_or("No site configured, pass --url or run `aip login --url <url>`")?;
+
+    if let Command::Login { token } = cli.command {
+        return login(url, token, &mut config).await;
+    }
+
+    let token = cli
+        .token
+        .or(config.token)
+        .ok_or("Not signed in, run `aip login` or pass --token")?;
+    let api = Api::new(&url, token);
+    let format = cli.output;
+
+    match cli.command {
+        Command::Login { .. } => unreachable!("handled above"),
+        Command::Upload { name, file } => {
+            let data =
+                std::fs::read(&file).map_err(|e| format!("Failed to read {}: {}", file, e))?;
+            let res = api.upload(&name, data).await?;
+            output::print(
+                format,
+                &res,
+                &["NAME", "VERSION", "UPLOAD ID"],
+                vec![vec![
+                    name,
+                    res.version.to_string(),
+                    res.upload_id.to_string(),
+                ]],
+            );
+        }
+        Command::Pilots => {
+            let res = api.pilots().await?;
+            let rows = res
+                .pilots
+                .iter()
+                .map(|p| {
+                    vec![
+                        p.name.clone(),
+                        p.current.version.to_string(),
+                        p.creator
+                            .as_ref()
+                            .map(|c| c.username.clone())
+                            .unwrap_or_else(|| p.owner_id.clone()),
+                        p.id.to_string(),
+                    ]
+                })
+                .collect();
+            output::print(format, &res, &["NAME", "VERSION", "OWNER", "ID"], rows);
+        }
+        Command::Matches { pilot, limit } => {
+            let mut query = vec![("limit", limit.to_string())];
+            if let Some(pilot) = pilot {
+                query.push(("pilot", pilot));
+            }
+            let res = api.matches(&query).await?;
+            let names = pilot_names(&api).await?;
+            let rows = res.matches.iter().map(|m| match_row(m, &names)).collect();
+            output::print(format, &res, MATCH_HEADERS, rows);
+        }
+        Command::Fight {
+            a,
+            b,
+            wait,
+            timeout,
+        } => {
+            let match_id = api.fight(&a, &b).await?;
+            if !wait {
+                match format {
+                    Format::Json => println!("{}", serde_json::json!({ "matchId": match_id })),
+                    Format::Table => println!("Queued match {}", match_id),
+                }
+                return Ok(());
+            }
+
+            eprintln!("Queued match {}, waiting for the result", match_id);
+            let result =
+                wait_for_match(&api, &a, &b, &match_id, Duration::from_secs(timeout)).await?;
+            let names = pilot_names(&api).await?;
+            output::print(
+                format,
+                &result,
+                MATCH_HEADERS,
+                vec![match_row(&result, &names)],
+            );
+        }
+        Command::Tokens {
+            command:
+                TokensCommand::Create {
+                    name,
+                    scopes,
+                    expires_in_days,
+                },
+        } => {
+            let expires_at = expires_in_days
+                .map(|days| (chrono::Utc::now() + chrono::Duration::days(days)).timestamp());
+            let res = api
+                .create_token(&CreateUserToken {
+                    name,
+                    expires_at,
+                    scopes,
+                })
+                .await?;
+
+            output::print(
+                format,
+                &res,
+                &["ID", "NAME", "SCOPES", "EXPIRES", "SECRET"],
+                vec![vec![
+                    res.token.id.to_string(),
+                    res.token.name.clone(),
+                    res.token.scopes.to_string(),
+                    res.token
+                        .expires_at
+                        .as_ref()
+                        .map(output::format_date_time)
+                        .unwrap_or_else(|| "never".into()),
+                    res.secret.clone(),
+                ]],
+            );
+        }
+        Command::Tokens {
+            command: TokensCommand::Delete { id },
+        } => {
+            api.delete_token(id).await?;
+            if format == Format::Table {
+                println!("Deleted token {}", id);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn login(url: String, token: Option<String>, config: &mut Config) -> Result<(), String> {
+    let token = match token {
+        Some(token) => token,
+        None => {
+            eprint!("Token: ");
+            io::stderr().flush().ok();
+            let mut line = String::new();
+            io::stdin()
+                .lock()
+                .read_line(&mut line)
+                .map_err(|e| format!("Failed to read token: {}", e))?;
+            line.trim().to_string()
+        }
+    };
+
+    if !Api::new(&url, token.clone()).check_token().await? {
+        return Err(format!("{} does not accept this token", url));
+    }
+
+    config.url = Some(url.clone());
+    config.token = Some(token);
+    let path = config.save()?;
+    eprintln!("Signed in to {}, token stored in {}", url, path.display());
+
+    Ok(())
+}
+
+const MATCH_HEADERS: &[&str] = &["DATE", "PILOT A", "PILOT B", "WINNER", "MANUAL", "ID"];
+
+async fn pilot_names(api: &Api) -> Result<HashMap<String, String>, String> {
+    Ok(api
+        .pilots()
+        .await?
+        .pilots
+        .into_iter()
+        .map(|p| (p.id.to_string(), p.name))
+        .collect())
+}
+
+fn match_row(m: &MatchResult, names: &HashMap<String, String>) -> Vec<String> {
+    let name = |id: String| names.get(&id).cloned().unwrap_or(id);
+    let (a, b) = (
+        name(m.team_a.aip_id.to_string()),
+        name(m.team_b.aip_id.to_string()),
+    );
+
+    vec![
+        chrono::DateTime::from_timestamp(m.created_at / 1_000, 0)
+            .as_ref()
+            .map(output::format_date_time)
+            .unwrap_or_default(),
+        format!("{} v{}", a, m.team_a.version),
+        format!("{} v{}", b, m.team_b.version),
+        match m.winner {
+            Winner::TeamA => a,
+            Winner::TeamB => b,
+            Winner::Unknown => "-".into(),
+        },
+        if m.manual_run { "yes" } else { "no" }.into(),
+        m.id.to_string(),
+    ]
+}
+
+/// Polls the matches of `a` against `b` until `match_id` shows up.
+async fn wait_for_match(
+    api: &Api,
+    a: &str,
+    b: &str,
+    match_id: &str,
+    timeout: Duration,
+) -> Result<MatchResult, String> {
+    let started = Instant::now();
+    let query = [
+        ("pilot", a.to_string()),
+        ("opponent", b.to_string()),
+        ("manual", "true".to_string()),
+        ("limit", "50".to_string()),
+    ];
+
+    loop {
+        let res = api.matches(&query).await?;
+        if let Some(m) = res
+            .matches
+            .into_iter()
+            .find(|m| m.id.to_string() == match_id)
+        {
+            return Ok(m);
+        }
+        if started.elapsed() >= timeout {
+            return Err(format!(
+                "Match {} has no result yet, gave up waiting",
+                match_id
+            ));
+        }
+
+        tokio::time::sleep(Duration::from_secs(3)).await;
+    }
+}
@@ -0,0 +1,48 @@
+-- Local mirror of the upstream AIP submission server
+
+CREATE TABLE pilots (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    owner_id TEXT NOT NULL,
+    current_version INTEGER NOT NULL,
+    synced_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX idx_pilots_name ON pilots (name);
+CREATE INDEX idx_pilots_owner_id ON pilots (owner_id);
+
+CREATE TABLE pilot_versions (
+    pilot_id TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    upload_id TEXT NOT NULL,
+    PRIMARY KEY (pilot_id, version),
+    FOREIGN KEY (pilot_id) REFERENCES pilots(id) ON DELETE CASCADE
+);
+
+CREATE TABLE matches (
+    id TEXT PRIMARY KEY NOT NULL,
+    team_a_id TEXT NOT NULL,
+    team_a_version INTEGER NOT NULL,
+    team_b_id TEXT NOT NULL,
+    team_b_version INTEGER NOT NULL,
+    winner INTEGER NOT NULL,
+    manual_run BOOLEAN NOT NULL,
+    -- Milliseconds since epoch, as reported upstream
+    created_at INTEGER NOT NULL,
+    normalized_name TEXT NOT NULL,
+    replay_id TEXT
+);
+
+CREATE INDEX idx_matches_team_a ON matches (team_a_id, team_a_version);
+CREATE INDEX idx_matches_team_b ON matches (team_b_id, team_b_version);
+CREATE INDEX idx_matches_created_at ON matches (created_at);
+
+CREATE TABLE sync_state (
+    id INTEGER PRIMARY KEY NOT NULL CHECK (id = 1),
+    last_attempt_at TIMESTAMP,
+    last_success_at TIMESTAMP,
+    last_full_sync_at TIMESTAMP,
+    last_error TEXT
+);
+
+INSERT INTO sync_state (id) VALUES (1);
@@ -0,0 +1,49 @@
+-- Internal tournaments scheduled against the upstream fight server
+
+CREATE TABLE tournaments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    name TEXT NOT NULL,
+    -- round_robin, swiss or single_elimination
+    format TEXT NOT NULL,
+    -- pending, running or finished
+    status TEXT NOT NULL DEFAULT 'pending',
+    total_rounds INTEGER NOT NULL,
+    current_round INTEGER NOT NULL DEFAULT 0,
+    created_by INTEGER NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (created_by) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE TABLE tournament_entrants (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    tournament_id INTEGER NOT NULL,
+    pilot_id TEXT NOT NULL,
+    pilot_version INTEGER NOT NULL,
+    seed INTEGER NOT NULL,
+    FOREIGN KEY (tournament_id) REFERENCES tournaments(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_tournament_entrant ON tournament_entrants (tournament_id, pilot_id);
+
+CREATE TABLE tournament_games (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    tournament_id INTEGER NOT NULL,
+    round INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    entrant_a INTEGER NOT NULL,
+    -- NULL means entrant_a has a bye
+    entrant_b INTEGER,
+    match_id TEXT,
+    -- pending, scheduled, finished or void
+    status TEXT NOT NULL DEFAULT 'pending',
+    winner INTEGER,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    note TEXT,
+    FOREIGN KEY (tournament_id) REFERENCES tournaments(id) ON DELETE CASCADE,
+    FOREIGN KEY (entrant_a) REFERENCES tournament_entrants(id) ON DELETE CASCADE,
+    FOREIGN KEY (entrant_b) REFERENCES tournament_entrants(id) ON DELETE CASCADE,
+    FOREIGN KEY (winner) REFERENCES tournament_entrants(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_tournament_games_round ON tournament_games (tournament_id, round, position);
+CREATE INDEX idx_tournament_games_status ON tournament_games (status);
@@ -0,0 +1,6 @@
+-- Space separated list of scopes, see `Scope` in src/scope.rs
+ALTER TABLE user_tokens ADD COLUMN scopes TEXT NOT NULL DEFAULT '';
+
+-- Existing tokens keep the access they had before scopes existed
+UPDATE user_tokens
+SET scopes = 'pilots:read matches:read matches:create pilots:upload tournaments:read tournaments:manage tokens:manage';
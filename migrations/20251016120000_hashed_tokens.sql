@@ -0,0 +1,8 @@
+-- Tokens are stored as HMAC-SHA256(TOKEN_HASH_KEY, secret) and looked up by a short prefix.
+-- Plaintext tokens from before this migration are hashed on startup, which empties `token`.
+DROP INDEX idx_user_token;
+
+ALTER TABLE user_tokens ADD COLUMN token_prefix TEXT NOT NULL DEFAULT '';
+ALTER TABLE user_tokens ADD COLUMN token_hash TEXT NOT NULL DEFAULT '';
+
+CREATE INDEX idx_user_tokens_prefix ON user_tokens (token_prefix);
@@ -0,0 +1,17 @@
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'user';
+ALTER TABLE users ADD COLUMN banned_at TIMESTAMP;
+ALTER TABLE users ADD COLUMN ban_reason TEXT;
+
+ALTER TABLE user_tokens ADD COLUMN last_used_at TIMESTAMP;
+
+-- Uploads and manual matches per user, for spotting spam in the admin area
+CREATE TABLE usage_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    user_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    detail TEXT,
+    created_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_usage_events_user ON usage_events (user_id, created_at);
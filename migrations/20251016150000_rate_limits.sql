@@ -0,0 +1,10 @@
+-- Fixed window counters for rate limited actions, kept here so limits survive restarts
+CREATE TABLE rate_limits (
+    action TEXT NOT NULL,
+    -- user:<id> or ip:<address>
+    subject TEXT NOT NULL,
+    -- Unix seconds
+    window_start INTEGER NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (action, subject)
+);
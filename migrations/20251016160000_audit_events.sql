@@ -0,0 +1,19 @@
+-- Mutating requests by signed in users, successful or not. Events outlive their actor:
+-- the name is copied into each event and the id cleared when the user is deleted.
+CREATE TABLE audit_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    actor_id INTEGER,
+    actor_name TEXT NOT NULL,
+    action TEXT NOT NULL,
+    target TEXT NOT NULL,
+    request_id TEXT NOT NULL,
+    ip TEXT,
+    -- success or failure
+    result TEXT NOT NULL,
+    status INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (actor_id) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX idx_audit_events_actor ON audit_events (actor_id, created_at);
+CREATE INDEX idx_audit_events_created ON audit_events (created_at);
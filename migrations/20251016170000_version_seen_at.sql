@@ -0,0 +1,14 @@
+-- When the mirror first saw each pilot version, in milliseconds since epoch like matches.created_at.
+-- Upstream doesn't report upload times, existing versions get the time of their first match
+-- and stay NULL if they never played.
+ALTER TABLE pilot_versions ADD COLUMN seen_at INTEGER;
+
+UPDATE pilot_versions
+SET seen_at = (
+    SELECT MIN(created_at)
+    FROM matches
+    WHERE (team_a_id = pilot_versions.pilot_id AND team_a_version = pilot_versions.version)
+       OR (team_b_id = pilot_versions.pilot_id AND team_b_version = pilot_versions.version)
+);
+
+CREATE INDEX idx_pilot_versions_seen_at ON pilot_versions (seen_at);
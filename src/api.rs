@@ -1,10 +1,21 @@
-use std::env;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    env,
+    sync::Arc,
+};
 
-use client::models::{AiPilot, AipVersion, MatchResult};
+use client::models::{AiPilot, MatchResult, match_result::Winner};
 use lazy_static::lazy_static;
 use regex::Regex;
 use rocket::{
-    Data, Route, State, data::ToByteUnit, futures::future::join_all, http::Status,
+    Data, Route, State,
+    data::ToByteUnit,
+    futures::future::join_all,
+    http::{
+        Status,
+        uri::fmt::{Formatter, Query, UriDisplay},
+    },
     serde::json::Json,
 };
 use rocket_okapi::openapi;
@@ -15,9 +26,30 @@ use crate::{
     SqliteClient,
     api_client::ApiClient,
     api_error::ApiErrors,
-    cookie::ApiUser,
-    model::UserToken,
-    sso_client::{DiscordUserInfo, SSOClient},
+    audit::{self, Audit},
+    cookie::AdminUser,
+    events::Broadcaster,
+    export::{ExportFormat, MatchExport},
+    h2h::HeadToHead,
+    leaderboard::{Leaderboard, LeaderboardCache, LeaderboardQuery},
+    mirror::{self, MatchCursor, MatchFilter},
+    model::{
+        NewUserToken, Role, UsageEvent, UsageKind, User, UserId, UserToken, UserTokenId,
+        UserTokenStore, UserUsage,
+    },
+    rate_limit::{self, RateLimited},
+    rating::Rating,
+    scope::{self, Scope, Scoped, Scopes},
+    sso_client::SSOClient,
+    stats::{OwnerStats, PilotStats, Stats, StatsCache, VersionStats},
+    sync::SyncState,
+    tournament::{Tournament, TournamentDetail, TournamentFormat, TournamentId, TournamentStatus},
+    util::{discord_avatar_url, replay_url},
+    webhook::{self, Delivery, Webhook, WebhookEvents, WebhookId},
+};
+
+pub use aip_types::api::{
+    AiPilotWithCreator, CreateUserToken, GetAiPilotResponse, GetMatchResponse, PostAiPilotResponse,
 };
 
 #[openapi]
@@ -31,53 +63,22 @@ lazy_static! {
         Regex::new(r"^\w{3,32}$").expect("Failed to compile regex for name validation");
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
-#[serde(rename_all = "camelCase")]
-pub struct AiPilotWithCreator {
-    pub id: uuid::Uuid,
-    pub name: String,
-    pub owner_id: String,
-    pub current: AipVersion,
-    pub versions: Vec<AipVersion>,
-    pub creator: Option<DiscordUserInfo>,
-}
-
-impl AiPilotWithCreator {
-    pub fn with_creator(pilot: AiPilot, creator: Option<DiscordUserInfo>) -> Self {
-        AiPilotWithCreator {
-            id: pilot.id,
-            name: pilot.name,
-            owner_id: pilot.owner_id,
-            current: pilot.current,
-            versions: pilot.versions,
-            creator,
-        }
-    }
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
-#[serde(rename_all = "camelCase")]
-struct GetAiPilotResponse {
-    pilots: Vec<AiPilotWithCreator>,
-}
-
 #[openapi]
 #[get("/aipilots?<name>")]
 async fn api_get_ai_pilots(
-    _user: ApiUser,
+    _user: Scoped<scope::PilotsRead>,
     name: Option<&str>,
-    api_client: &State<ApiClient>,
+    client: &State<SqliteClient>,
     sso_client: &State<SSOClient>,
 ) -> Result<Json<GetAiPilotResponse>, ApiErrors> {
     let pilots = if let Some(name) = name {
         vec![
-            api_client
-                .get_pilot_by_name(name)
-                .await
+            mirror::get_pilot_by_name(name, client)
+                .await?
                 .ok_or_else(|| ApiErrors::NotFound("Pilot not found".into()))?,
         ]
     } else {
-        api_client.get_pilots().await
+        mirror::get_pilots(client).await?
     };
 
     let pilots = join_all(pilots.into_iter().map(async |p| {
@@ -89,19 +90,631 @@ async fn api_get_ai_pilots(
     Ok(Json(GetAiPilotResponse { pilots }))
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
-struct GetMatchResponse {
-    matches: Vec<MatchResult>,
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, FromFormField,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchSort {
+    #[default]
+    Newest,
+    Oldest,
+}
+
+impl UriDisplay<Query> for MatchSort {
+    fn fmt(&self, f: &mut Formatter<'_, Query>) -> std::fmt::Result {
+        f.write_value(match self {
+            MatchSort::Newest => "newest",
+            MatchSort::Oldest => "oldest",
+        })
+    }
+}
+
+/// Query parameters shared by `/api/matches` and the `/matches` page.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, FromForm)]
+pub struct MatchQuery {
+    /// Pilot name or id
+    pub pilot: Option<String>,
+    /// Version of `pilot`
+    pub version: Option<i32>,
+    /// Only matches against this pilot, name or id
+    pub opponent: Option<String>,
+    /// Only matches won by this pilot, name or id
+    pub winner: Option<String>,
+    pub manual: Option<bool>,
+    pub replay: Option<bool>,
+    /// Earliest match, `YYYY-MM-DD` or RFC 3339
+    pub from: Option<String>,
+    /// Latest match, `YYYY-MM-DD` (inclusive) or RFC 3339
+    pub to: Option<String>,
+    pub sort: Option<MatchSort>,
+    pub limit: Option<u32>,
+    /// `nextCursor` of the previous page
+    pub cursor: Option<String>,
+}
+
+impl MatchQuery {
+    pub const DEFAULT_LIMIT: u32 = 100;
+    pub const MAX_LIMIT: u32 = 500;
+
+    pub fn limit(&self) -> u32 {
+        self.limit
+            .unwrap_or(Self::DEFAULT_LIMIT)
+            .clamp(1, Self::MAX_LIMIT)
+    }
+
+    /// Resolves pilot names and parses dates and the cursor.
+    pub async fn resolve(
+        &self,
+        client: &SqliteClient,
+    ) -> Result<(MatchFilter, Option<MatchCursor>), ApiErrors> {
+        async fn pilot(
+            param: &str,
+            value: Option<&str>,
+            client: &SqliteClient,
+        ) -> Result<Option<Uuid>, ApiErrors> {
+            let Some(value) = value.filter(|v| !v.is_empty()) else {
+                return Ok(None);
+            };
+            mirror::resolve_pilot_id(value, client)
+                .await?
+                .map(Some)
+                .ok_or_else(|| ApiErrors::NotFound(format!("Unknown {} {}", param, value)))
+        }
+
+        let pilot_id = pilot("pilot", self.pilot.as_deref(), client).await?;
+        if self.version.is_some() && pilot_id.is_none() {
+            return Err(ApiErrors::BadRequest(
+                "version can only be used together with pilot".into(),
+            ));
+        }
+
+        let filter = MatchFilter {
+            pilot_id,
+            version: self.version,
+            opponent_id: pilot("opponent", self.opponent.as_deref(), client).await?,
+            winner_id: pilot("winner", self.winner.as_deref(), client).await?,
+            manual: self.manual,
+            has_replay: self.replay,
+            from: parse_match_date("from", self.from.as_deref(), false)?,
+            to: parse_match_date("to", self.to.as_deref(), true)?,
+            oldest_first: self.sort == Some(MatchSort::Oldest),
+        };
+
+        let cursor = self
+            .cursor
+            .as_deref()
+            .filter(|c| !c.is_empty())
+            .map(|c| c.parse::<MatchCursor>().map_err(ApiErrors::BadRequest))
+            .transpose()?;
+
+        Ok((filter, cursor))
+    }
+}
+
+/// Renders the set parameters, for links that keep the current filters.
+impl UriDisplay<Query> for MatchQuery {
+    fn fmt(&self, f: &mut Formatter<'_, Query>) -> std::fmt::Result {
+        let strings = [
+            ("pilot", &self.pilot),
+            ("opponent", &self.opponent),
+            ("winner", &self.winner),
+            ("from", &self.from),
+            ("to", &self.to),
+        ];
+        for (name, value) in strings {
+            if let Some(value) = value {
+                f.write_named_value(name, value)?;
+            }
+        }
+        if let Some(version) = self.version {
+            f.write_named_value("version", version)?;
+        }
+        if let Some(manual) = self.manual {
+            f.write_named_value("manual", manual)?;
+        }
+        if let Some(replay) = self.replay {
+            f.write_named_value("replay", replay)?;
+        }
+        if let Some(sort) = self.sort {
+            f.write_named_value("sort", sort)?;
+        }
+        if let Some(limit) = self.limit {
+            f.write_named_value("limit", limit)?;
+        }
+        if let Some(cursor) = &self.cursor {
+            f.write_named_value("cursor", cursor)?;
+        }
+        Ok(())
+    }
 }
 
+/// Parses a date filter into milliseconds. Plain dates cover the whole day when `end` is set.
+fn parse_match_date(param: &str, value: Option<&str>, end: bool) -> Result<Option<i64>, ApiErrors> {
+    let Some(value) = value.filter(|v| !v.is_empty()) else {
+        return Ok(None);
+    };
+
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let date = if end {
+            date.succ_opt().unwrap_or(date)
+        } else {
+            date
+        };
+        return Ok(Some(
+            date.and_time(chrono::NaiveTime::MIN)
+                .and_utc()
+                .timestamp_millis(),
+        ));
+    }
+
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|dt| Some(dt.timestamp_millis()))
+        .map_err(|_| ApiErrors::BadRequest(format!("Invalid {} date {}", param, value)))
+}
+
+/// Matches passing the given filters, newest first unless `sort=oldest`.
+/// Pages hold `limit` matches (default 100, max 500).
 #[openapi]
-#[get("/matches")]
+#[get("/matches?<query..>")]
 async fn api_get_matches(
-    _user: ApiUser,
-    api_client: &State<ApiClient>,
+    _user: Scoped<scope::MatchesRead>,
+    query: MatchQuery,
+    client: &State<SqliteClient>,
 ) -> Result<Json<GetMatchResponse>, ApiErrors> {
-    let matches = api_client.get_matches(None, None).await;
-    Ok(Json(GetMatchResponse { matches }))
+    let (filter, cursor) = query.resolve(client).await?;
+    let (matches, next) =
+        mirror::get_matches_page(&filter, cursor.as_ref(), query.limit(), client).await?;
+
+    Ok(Json(GetMatchResponse {
+        matches,
+        next_cursor: next.map(|c| c.to_string()),
+    }))
+}
+
+/// Every match passing the given filters as CSV (default) or JSON Lines, streamed in full.
+/// `limit` is ignored, `cursor` resumes an export from a `nextCursor` of `/api/matches`.
+#[openapi]
+#[get("/export/matches?<format>&<query..>")]
+async fn api_export_matches(
+    _user: Scoped<scope::MatchesRead>,
+    format: Option<ExportFormat>,
+    query: MatchQuery,
+    client: &State<SqliteClient>,
+    api_client: &State<Arc<dyn ApiClient>>,
+) -> Result<MatchExport, ApiErrors> {
+    export_matches(format, "matches".into(), query, client, api_client.inner()).await
+}
+
+/// Like `/api/export/matches`, limited to the matches of one pilot given by name or id.
+#[openapi]
+#[get("/export/pilots/<name>?<format>&<query..>")]
+async fn api_export_pilot_matches(
+    _user: Scoped<scope::MatchesRead>,
+    name: &str,
+    format: Option<ExportFormat>,
+    query: MatchQuery,
+    client: &State<SqliteClient>,
+    api_client: &State<Arc<dyn ApiClient>>,
+) -> Result<MatchExport, ApiErrors> {
+    let pilot = find_pilot(name, client).await?;
+    let query = MatchQuery {
+        pilot: Some(pilot.id.to_string()),
+        ..query
+    };
+    export_matches(format, pilot.name, query, client, api_client.inner()).await
+}
+
+async fn export_matches(
+    format: Option<ExportFormat>,
+    file_name: String,
+    query: MatchQuery,
+    client: &SqliteClient,
+    api_client: &Arc<dyn ApiClient>,
+) -> Result<MatchExport, ApiErrors> {
+    let (filter, cursor) = query.resolve(client).await?;
+
+    Ok(MatchExport {
+        filter,
+        cursor,
+        format: format.unwrap_or_default(),
+        file_name,
+        names: mirror::get_pilot_names(client).await?,
+        api_base_url: api_client.base_url().to_string(),
+        client: client.clone(),
+    })
+}
+
+/// Looks up a pilot by name or id for a query parameter.
+async fn find_pilot(name_or_id: &str, client: &SqliteClient) -> Result<AiPilot, ApiErrors> {
+    let pilot = match Uuid::parse_str(name_or_id) {
+        Ok(id) => mirror::get_pilot(&id, client).await?,
+        Err(_) => mirror::get_pilot_by_name(name_or_id, client).await?,
+    };
+    pilot.ok_or_else(|| ApiErrors::NotFound(format!("Pilot {} not found", name_or_id)))
+}
+
+/// Head-to-head record of two pilots, given by name or id.
+#[openapi]
+#[get("/h2h?<a>&<b>")]
+async fn api_get_h2h(
+    _user: Scoped<scope::MatchesRead>,
+    a: &str,
+    b: &str,
+    client: &State<SqliteClient>,
+) -> Result<Json<HeadToHead>, ApiErrors> {
+    let pilot_a = find_pilot(a, client).await?;
+    let pilot_b = find_pilot(b, client).await?;
+    if pilot_a.id == pilot_b.id {
+        return Err(ApiErrors::BadRequest(
+            "Cannot compare a pilot with itself".into(),
+        ));
+    }
+
+    let matches = mirror::get_matches(Some(&pilot_a.id), None, client).await?;
+    Ok(Json(HeadToHead::new(&pilot_a, &pilot_b, &matches)))
+}
+
+/// Pilots ranked by win rate or rating over a time window, with their rank in the window before.
+#[openapi]
+#[get("/leaderboard?<query..>")]
+async fn api_get_leaderboard(
+    _user: Scoped<scope::PilotsRead>,
+    query: LeaderboardQuery,
+    client: &State<SqliteClient>,
+    leaderboards: &State<LeaderboardCache>,
+) -> Result<Json<Leaderboard>, ApiErrors> {
+    Ok(Json(leaderboards.get(&query, client).await?))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+struct VersionRating {
+    version: i32,
+    rating: Rating,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+struct PilotRating {
+    id: Uuid,
+    name: String,
+    owner_id: String,
+    current_version: i32,
+    rating: Rating,
+    versions: Vec<VersionRating>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+struct GetRatingsResponse {
+    pilots: Vec<PilotRating>,
+}
+
+/// Glicko-2 ratings for every pilot and pilot version, highest rated pilot first.
+#[openapi]
+#[get("/ratings")]
+async fn api_get_ratings(
+    _user: Scoped<scope::PilotsRead>,
+    client: &State<SqliteClient>,
+    stats: &State<StatsCache>,
+) -> Result<Json<GetRatingsResponse>, ApiErrors> {
+    let stats = stats.get(client).await?;
+    let ratings = stats.ratings();
+
+    let mut pilots: Vec<_> = stats
+        .pilots()
+        .iter()
+        .map(|p| PilotRating {
+            id: p.pilot_id,
+            name: p.name.clone(),
+            owner_id: p.owner_id.clone(),
+            current_version: p.current_version,
+            rating: p.rating,
+            versions: ratings
+                .versions_of(&p.pilot_id)
+                .into_iter()
+                .map(|(version, rating)| VersionRating { version, rating })
+                .collect(),
+        })
+        .collect();
+    pilots.sort_by(|a, b| b.rating.rating.total_cmp(&a.rating.rating));
+
+    Ok(Json(GetRatingsResponse { pilots }))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchTeam {
+    pub pilot_id: Uuid,
+    /// Missing when the pilot no longer exists upstream
+    pub name: Option<String>,
+    pub version: i32,
+    pub winner: bool,
+}
+
+/// A match with pilot names and the replay download resolved. Neither team is the
+/// winner when the result is unknown.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchDetail {
+    pub id: Uuid,
+    pub team_a: MatchTeam,
+    pub team_b: MatchTeam,
+    pub manual: bool,
+    /// Milliseconds since epoch
+    pub created_at: i64,
+    pub replay_url: Option<String>,
+}
+
+impl MatchDetail {
+    pub fn new(m: &MatchResult, names: &HashMap<Uuid, String>, api_base_url: &str) -> Self {
+        let team = |id: Uuid, version: i32, winner: Winner| MatchTeam {
+            pilot_id: id,
+            name: names.get(&id).cloned(),
+            version,
+            winner: m.winner == winner,
+        };
+
+        MatchDetail {
+            id: m.id,
+            team_a: team(m.team_a.aip_id, m.team_a.version, Winner::TeamA),
+            team_b: team(m.team_b.aip_id, m.team_b.version, Winner::TeamB),
+            manual: m.manual_run,
+            created_at: m.created_at,
+            replay_url: replay_url(api_base_url, m.replay_id.as_deref()),
+        }
+    }
+}
+
+/// Match from the mirror, or from upstream when it finished after the last sync.
+/// Matches fetched from upstream are stored and announced like synced ones.
+pub async fn fetch_match(
+    match_id: &str,
+    api_client: &Arc<dyn ApiClient>,
+    broadcaster: &Broadcaster,
+    stats: &StatsCache,
+    client: &SqliteClient,
+) -> Result<Option<MatchResult>, ApiErrors> {
+    if let Some(m) = mirror::get_match(match_id, client).await? {
+        return Ok(Some(m));
+    }
+
+    let Some(m) = api_client.get_match(match_id).await? else {
+        return Ok(None);
+    };
+    let baseline = webhook::rating_baseline(stats, client).await;
+    match mirror::upsert_matches(std::slice::from_ref(&m), client).await {
+        Ok(new_matches) => {
+            webhook::dispatch_new_matches(&new_matches, baseline, stats, client).await;
+            broadcaster.matches_finished(&new_matches, client).await;
+        }
+        Err(e) => log::error!("Failed to store match: {}", e),
+    }
+    Ok(Some(m))
+}
+
+/// A match with resolved pilot names and replay URL, as shown on its page.
+#[openapi]
+#[get("/match/<match_id>")]
+async fn api_get_match(
+    _user: Scoped<scope::MatchesRead>,
+    match_id: &str,
+    api_client: &State<Arc<dyn ApiClient>>,
+    broadcaster: &State<Broadcaster>,
+    client: &State<SqliteClient>,
+    stats: &State<StatsCache>,
+) -> Result<Json<MatchDetail>, ApiErrors> {
+    let m = fetch_match(match_id, api_client.inner(), broadcaster, stats, client)
+        .await?
+        .ok_or_else(|| ApiErrors::NotFound("Match not found".into()))?;
+    let names = mirror::get_pilot_names(client).await?;
+
+    Ok(Json(MatchDetail::new(&m, &names, api_client.base_url())))
+}
+
+/// The latest `limit` of `matches`, which must be newest first.
+async fn recent_matches(
+    matches: &[MatchResult],
+    limit: usize,
+    api_base_url: &str,
+    client: &SqliteClient,
+) -> Result<Vec<MatchDetail>, ApiErrors> {
+    let names = mirror::get_pilot_names(client).await?;
+    Ok(matches
+        .iter()
+        .take(limit)
+        .map(|m| MatchDetail::new(m, &names, api_base_url))
+        .collect())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GetPilotStatsResponse {
+    #[serde(flatten)]
+    pub stats: PilotStats,
+    /// The last 10 matches, newest first
+    pub recent_matches: Vec<MatchDetail>,
+}
+
+/// Record of a pilot, given by name or id, overall and per version and opponent.
+#[openapi]
+#[get("/pilots/<name>/stats")]
+async fn api_get_pilot_stats(
+    _user: Scoped<scope::PilotsRead>,
+    name: &str,
+    stats: &State<StatsCache>,
+    api_client: &State<Arc<dyn ApiClient>>,
+    client: &State<SqliteClient>,
+) -> Result<Json<GetPilotStatsResponse>, ApiErrors> {
+    let pilot = find_pilot(name, client).await?;
+    let stats = stats.get(client).await?;
+    let pilot_stats = stats
+        .pilot(&pilot.id)
+        .cloned()
+        .ok_or_else(|| ApiErrors::NotFound(format!("Pilot {} not found", name)))?;
+
+    let matches = mirror::get_matches(Some(&pilot.id), None, client).await?;
+    Ok(Json(GetPilotStatsResponse {
+        stats: pilot_stats,
+        recent_matches: recent_matches(&matches, 10, api_client.base_url(), client).await?,
+    }))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GetVersionStatsResponse {
+    #[serde(flatten)]
+    pub stats: VersionStats,
+    /// The last 10 matches of this version, newest first
+    pub recent_matches: Vec<MatchDetail>,
+}
+
+/// Record of one version of a pilot, given by name or id, overall and per opponent.
+#[openapi]
+#[get("/pilots/<name>/versions/<version>/stats")]
+async fn api_get_version_stats(
+    _user: Scoped<scope::PilotsRead>,
+    name: &str,
+    version: i32,
+    stats: &State<StatsCache>,
+    api_client: &State<Arc<dyn ApiClient>>,
+    client: &State<SqliteClient>,
+) -> Result<Json<GetVersionStatsResponse>, ApiErrors> {
+    let pilot = find_pilot(name, client).await?;
+    let uploaded =
+        pilot.current.version == version || pilot.versions.iter().any(|v| v.version == version);
+    if !uploaded {
+        return Err(ApiErrors::NotFound(format!(
+            "Pilot {} has no version {}",
+            name, version
+        )));
+    }
+
+    let stats = stats.get(client).await?;
+    let version_stats = stats
+        .pilot(&pilot.id)
+        .and_then(|p| p.version(version))
+        .cloned()
+        .unwrap_or_else(|| VersionStats::unplayed(version));
+
+    let matches = mirror::get_matches(Some(&pilot.id), Some(version), client).await?;
+    Ok(Json(GetVersionStatsResponse {
+        stats: version_stats,
+        recent_matches: recent_matches(&matches, 10, api_client.base_url(), client).await?,
+    }))
+}
+
+/// A user's combined record, with their name and avatar from Discord.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSummary {
+    #[serde(flatten)]
+    pub stats: OwnerStats,
+    /// The owner id when the user can't be looked up
+    pub username: String,
+    pub avatar_url: Option<String>,
+}
+
+impl UserSummary {
+    pub async fn new(stats: OwnerStats, sso_client: &SSOClient) -> Self {
+        let user_info = sso_client.get_user(&stats.owner_id).await;
+        UserSummary {
+            username: user_info
+                .as_ref()
+                .map(|info| info.username.clone())
+                .unwrap_or_else(|| stats.owner_id.clone()),
+            avatar_url: user_info
+                .as_ref()
+                .map(|info| discord_avatar_url(&stats.owner_id, &info.avatar)),
+            stats,
+        }
+    }
+
+    /// Every user with pilots, most pilots first and then most matches.
+    pub async fn all(stats: &Stats, sso_client: &SSOClient) -> Vec<UserSummary> {
+        let mut owners: Vec<_> = stats.owners().to_vec();
+        owners.sort_by(|a, b| {
+            b.pilots
+                .len()
+                .cmp(&a.pilots.len())
+                .then(b.matches.cmp(&a.matches))
+        });
+
+        join_all(
+            owners
+                .into_iter()
+                .map(|owner| UserSummary::new(owner, sso_client)),
+        )
+        .await
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct GetUsersResponse {
+    pub users: Vec<UserSummary>,
+}
+
+/// Every user who uploaded a pilot, most pilots first.
+#[openapi]
+#[get("/users")]
+async fn api_get_users(
+    _user: Scoped<scope::PilotsRead>,
+    stats: &State<StatsCache>,
+    sso_client: &State<SSOClient>,
+    client: &State<SqliteClient>,
+) -> Result<Json<GetUsersResponse>, ApiErrors> {
+    let stats = stats.get(client).await?;
+
+    Ok(Json(GetUsersResponse {
+        users: UserSummary::all(&stats, sso_client).await,
+    }))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GetUserResponse {
+    #[serde(flatten)]
+    pub user: UserSummary,
+    /// Most played first
+    pub pilot_stats: Vec<PilotStats>,
+    /// The last 20 matches of any of the user's pilots, newest first
+    pub recent_matches: Vec<MatchDetail>,
+}
+
+/// Profile of a user who uploaded pilots, given by Discord id.
+#[openapi]
+#[get("/user/<owner_id>")]
+async fn api_get_user(
+    _user: Scoped<scope::PilotsRead>,
+    owner_id: &str,
+    stats: &State<StatsCache>,
+    sso_client: &State<SSOClient>,
+    api_client: &State<Arc<dyn ApiClient>>,
+    client: &State<SqliteClient>,
+) -> Result<Json<GetUserResponse>, ApiErrors> {
+    let stats = stats.get(client).await?;
+    let owner_stats = stats
+        .owner(owner_id)
+        .cloned()
+        .ok_or_else(|| ApiErrors::NotFound("User not found or has no pilots".into()))?;
+    let pilot_stats: Vec<_> = stats.pilots_of(owner_id).into_iter().cloned().collect();
+
+    // Matches between two of the user's pilots show up for both
+    let mut matches = Vec::new();
+    let mut seen = HashSet::new();
+    for pilot in &pilot_stats {
+        for m in mirror::get_matches(Some(&pilot.pilot_id), None, client).await? {
+            if seen.insert(m.id) {
+                matches.push(m);
+            }
+        }
+    }
+    matches.sort_by_key(|m| Reverse(m.created_at));
+
+    Ok(Json(GetUserResponse {
+        user: UserSummary::new(owner_stats, sso_client).await,
+        pilot_stats,
+        recent_matches: recent_matches(&matches, 20, api_client.base_url(), client).await?,
+    }))
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
@@ -110,65 +723,310 @@ pub struct CreateMatchRequest {
     pub pilot_b: String,
 }
 
+/// Usage is informational, so failing to record it doesn't fail the request.
+async fn record_usage(user_id: UserId, kind: UsageKind, detail: &str, client: &SqliteClient) {
+    if let Err(e) = UsageEvent::record(user_id, kind, Some(detail), client).await {
+        log::error!("Failed to record usage: {}", e);
+    }
+}
+
+/// Queues a manual match between two pilots, each given by name or id.
 #[openapi]
 #[post("/matches?<pilot_a>&<pilot_b>")]
+#[allow(clippy::too_many_arguments)]
 async fn api_post_match(
-    _user: ApiUser,
+    audit: Audit<audit::MatchCreate>,
+    user: Scoped<scope::MatchesCreate>,
+    _limit: RateLimited<rate_limit::MatchCreate>,
     pilot_a: &str,
     pilot_b: &str,
-    api_client: &State<ApiClient>,
+    api_client: &State<Arc<dyn ApiClient>>,
+    broadcaster: &State<Broadcaster>,
+    client: &State<SqliteClient>,
 ) -> Result<String, ApiErrors> {
-    api_client
-        .create_match(pilot_a, pilot_b)
-        .await
-        .map_err(|e| ApiErrors::InternalError(format!("Failed to create match: {}", e).into()))
-}
+    User::ensure_not_banned(user.id, client).await?;
 
-#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
-#[serde(rename_all = "camelCase")]
-struct PostAiPilotResponse {
-    upload_id: Uuid,
-    version: i32,
+    // Upstream only takes ids
+    let pilot_a = find_pilot(pilot_a, client).await?;
+    let pilot_b = find_pilot(pilot_b, client).await?;
+
+    let match_id = api_client
+        .create_match(&pilot_a.id.to_string(), &pilot_b.id.to_string())
+        .await?;
+
+    broadcaster.match_created(&match_id, pilot_a.name, pilot_b.name);
+
+    record_usage(user.id, UsageKind::MatchCreate, &match_id, client).await;
+    audit.target(&match_id);
+
+    Ok(match_id)
 }
 
 #[openapi]
 #[post("/aipilot/upload?<name>", data = "<data>")]
+#[allow(clippy::too_many_arguments)]
 async fn api_upload_ai_pilot(
-    user: ApiUser,
+    audit: Audit<audit::PilotUpload>,
+    user: Scoped<scope::PilotUpload>,
+    _limit: RateLimited<rate_limit::PilotUpload>,
     name: String,
     data: Data<'_>,
-    api_client: &State<ApiClient>,
+    api_client: &State<Arc<dyn ApiClient>>,
+    broadcaster: &State<Broadcaster>,
+    client: &State<SqliteClient>,
 ) -> Result<Json<PostAiPilotResponse>, ApiErrors> {
     if !NAME_REGEX.is_match(&name) {
         return Err(ApiErrors::BadRequest("Invalid name format".into()));
     }
+    User::ensure_not_banned(user.id, client).await?;
 
     let data = data.open(25.mebibytes()).into_bytes().await.map_err(|e| {
         log::error!("Failed to read data: {}", e);
         ApiErrors::InternalError("Failed to read data".into())
     })?;
 
-    let (upload_id, version) = api_client
+    let (upload_id, version, pilot) = api_client
         .upload_ai_pilot(&name, &user.discord_id, data.value)
         .await?;
 
+    // Make the new version visible before the next sync run
+    match mirror::upsert_pilots(&[pilot], client).await {
+        Ok(new_versions) => {
+            webhook::dispatch_new_versions(&new_versions, client).await;
+            broadcaster.versions_uploaded(&new_versions);
+        }
+        Err(e) => log::error!("Failed to store uploaded pilot: {}", e),
+    }
+
+    let uploaded = format!("{} v{}", name, version);
+    record_usage(user.id, UsageKind::Upload, &uploaded, client).await;
+    audit.target(uploaded);
+
     Ok(Json(PostAiPilotResponse { upload_id, version }))
 }
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
-struct CreateUserToken {
-    name: String,
-    expires_at: Option<i64>,
+/// When pilots and matches were last copied from the upstream server.
+#[openapi]
+#[get("/sync_status")]
+async fn api_get_sync_status(
+    _user: Scoped<scope::PilotsRead>,
+    client: &State<SqliteClient>,
+) -> Result<Json<SyncState>, ApiErrors> {
+    Ok(Json(SyncState::get(client).await?))
+}
+
+#[openapi]
+#[get("/tournaments")]
+async fn api_get_tournaments(
+    _user: Scoped<scope::TournamentsRead>,
+    client: &State<SqliteClient>,
+) -> Result<Json<Vec<Tournament>>, ApiErrors> {
+    let tournaments = Tournament::all(client).await.map_err(|e| {
+        log::error!("Failed to fetch tournaments: {}", e);
+        ApiErrors::InternalError("Failed to fetch tournaments".into())
+    })?;
+
+    Ok(Json(tournaments))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateTournamentEntrant {
+    /// Pilot name
+    pub pilot: String,
+    /// Version to pin, defaults to the current version
+    pub version: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateTournamentRequest {
+    pub name: String,
+    pub format: TournamentFormat,
+    /// Number of Swiss rounds, ignored for other formats
+    pub rounds: Option<i64>,
+    pub entrants: Vec<CreateTournamentEntrant>,
+}
+
+/// Creates a pending tournament. Entrants are seeded by the rating of their pinned version.
+#[openapi]
+#[post("/tournaments", data = "<body>")]
+async fn api_create_tournament(
+    audit: Audit<audit::TournamentCreate>,
+    user: Scoped<scope::TournamentsManage>,
+    body: Json<CreateTournamentRequest>,
+    client: &State<SqliteClient>,
+    stats: &State<StatsCache>,
+) -> Result<Json<Tournament>, ApiErrors> {
+    let CreateTournamentRequest {
+        name,
+        format,
+        rounds,
+        entrants,
+    } = body.into_inner();
+
+    User::ensure_not_banned(user.id, client).await?;
+
+    let name = name.trim();
+    if name.is_empty() || name.len() > 64 {
+        return Err(ApiErrors::BadRequest(
+            "Tournament name must be between 1 and 64 characters".into(),
+        ));
+    }
+    if entrants.len() < 2 {
+        return Err(ApiErrors::BadRequest(
+            "A tournament needs at least two entrants".into(),
+        ));
+    }
+    if let Some(rounds) = rounds
+        && format == TournamentFormat::Swiss
+        && (rounds < 1 || rounds >= entrants.len() as i64)
+    {
+        return Err(ApiErrors::BadRequest(format!(
+            "Swiss tournaments need between 1 and {} rounds",
+            entrants.len() - 1
+        )));
+    }
+
+    let mut pinned: Vec<(Uuid, i32)> = Vec::new();
+    for entrant in &entrants {
+        let pilot = mirror::get_pilot_by_name(&entrant.pilot, client)
+            .await?
+            .ok_or_else(|| ApiErrors::BadRequest(format!("Unknown pilot {}", entrant.pilot)))?;
+        let version = entrant.version.unwrap_or(pilot.current.version);
+
+        if !pilot.versions.iter().any(|v| v.version == version) && pilot.current.version != version {
+            return Err(ApiErrors::BadRequest(format!(
+                "Pilot {} has no version {}",
+                pilot.name, version
+            )));
+        }
+        if pinned.iter().any(|(id, _)| *id == pilot.id) {
+            return Err(ApiErrors::BadRequest(format!(
+                "Pilot {} is entered more than once",
+                pilot.name
+            )));
+        }
+
+        pinned.push((pilot.id, version));
+    }
+
+    let stats = stats.get(client).await?;
+    let ratings = stats.ratings();
+    pinned.sort_by(|(a, a_version), (b, b_version)| {
+        ratings
+            .version(b, *b_version)
+            .rating
+            .total_cmp(&ratings.version(a, *a_version).rating)
+    });
+
+    let tournament = Tournament::insert(
+        name,
+        format,
+        format.total_rounds(pinned.len(), rounds),
+        user.id,
+        &pinned,
+        client,
+    )
+    .await
+    .map_err(|e| {
+        log::error!("Failed to create tournament: {}", e);
+        ApiErrors::InternalError("Failed to create tournament".into())
+    })?;
+
+    record_usage(user.id, UsageKind::TournamentCreate, name, client).await;
+    audit.target(tournament.id);
+
+    Ok(Json(tournament))
+}
+
+/// Standings and every round played so far.
+#[openapi]
+#[get("/tournament/<tournament_id>")]
+async fn api_get_tournament(
+    _user: Scoped<scope::TournamentsRead>,
+    tournament_id: TournamentId,
+    client: &State<SqliteClient>,
+) -> Result<Json<TournamentDetail>, ApiErrors> {
+    Ok(Json(TournamentDetail::load(tournament_id, client).await?))
+}
+
+/// Pairs the first round. Games are queued upstream by the tournament runner.
+#[openapi]
+#[post("/tournament/<tournament_id>/start")]
+async fn api_start_tournament(
+    audit: Audit<audit::TournamentStart>,
+    user: Scoped<scope::TournamentsManage>,
+    tournament_id: TournamentId,
+    client: &State<SqliteClient>,
+) -> Result<Json<TournamentDetail>, ApiErrors> {
+    audit.target(tournament_id);
+    let tournament = Tournament::get_by_id(tournament_id, client)
+        .await
+        .map_err(|_| ApiErrors::NotFound("Tournament not found".into()))?;
+
+    if tournament.created_by != user.id {
+        return Err(ApiErrors::Forbidden(
+            "Only the creator can start a tournament".into(),
+        ));
+    }
+    if tournament.status != TournamentStatus::Pending {
+        return Err(ApiErrors::BadRequest("Tournament has already started".into()));
+    }
+    User::ensure_not_banned(user.id, client).await?;
+
+    let started = tournament.advance_round(client).await.map_err(|e| {
+        log::error!("Failed to start tournament: {}", e);
+        ApiErrors::InternalError("Failed to start tournament".into())
+    })?;
+    // Lost the race to another start request
+    if !started {
+        return Err(ApiErrors::BadRequest(
+            "Tournament has already started".into(),
+        ));
+    }
+
+    Ok(Json(TournamentDetail::load(tournament_id, client).await?))
 }
 
+/// Creates a token. The secret is only ever returned in this response.
 #[openapi]
 #[post("/user_token", data = "<body>")]
 async fn api_create_user_token(
-    user: ApiUser,
+    audit: Audit<audit::TokenCreate>,
+    user: Scoped<scope::TokensManage>,
     body: Json<CreateUserToken>,
     client: &State<SqliteClient>,
-) -> Result<Json<UserToken>, ApiErrors> {
-    let CreateUserToken { name, expires_at } = body.into_inner();
+) -> Result<Json<NewUserToken>, ApiErrors> {
+    let CreateUserToken {
+        name,
+        expires_at,
+        scopes,
+    } = body.into_inner();
+
+    if scopes.is_empty() {
+        return Err(ApiErrors::BadRequest(
+            "A token needs at least one scope".into(),
+        ));
+    }
+    // Tokens can't hand out more than they were given themselves
+    if let Some(missing) = scopes.iter().find(|s| !user.has_scope(s)) {
+        return Err(ApiErrors::BadRequest(format!(
+            "Cannot grant the {} scope",
+            missing
+        )));
+    }
+    if scopes.contains(&Scope::Admin) {
+        let role = User::get_by_id(user.id, client)
+            .await
+            .map(|u| u.role)
+            .unwrap_or_default();
+        if role != Role::Admin {
+            return Err(ApiErrors::Forbidden(
+                "Only admins can grant the admin scope".into(),
+            ));
+        }
+    }
 
     let expires_at = expires_at
         .map(|ts| {
@@ -179,12 +1037,13 @@ async fn api_create_user_token(
         })
         .transpose()?;
 
-    let token = UserToken::insert_user_token(name, user.id, expires_at, client)
+    let token = UserToken::insert_user_token(name, user.id, expires_at, &Scopes(scopes), client)
         .await
         .map_err(|e| {
             log::error!("Failed to create user token: {}", e);
             ApiErrors::InternalError("Failed to create user token".into())
         })?;
+    audit.target(token.token.id);
 
     Ok(Json(token))
 }
@@ -192,17 +1051,221 @@ async fn api_create_user_token(
 #[openapi]
 #[delete("/user_token/<token_id>")]
 async fn api_delete_user_token(
-    user: ApiUser,
+    audit: Audit<audit::TokenDelete>,
+    user: Scoped<scope::TokensManage>,
     token_id: i64,
     client: &State<SqliteClient>,
 ) -> Result<Status, ApiErrors> {
-    UserToken::delete_by_id_and_user_id(token_id, user.id, client)
+    audit.target(token_id);
+    let deleted = UserToken::delete_by_id_and_user_id(token_id, user.id, client)
         .await
         .map_err(|e| {
             log::error!("Failed to delete user token: {}", e);
             ApiErrors::InternalError("Failed to delete user token".into())
         })?;
 
+    // Someone else's token looks the same as a missing one
+    if !deleted {
+        return Err(ApiErrors::NotFound("User token not found".into()));
+    }
+
+    Ok(Status::NoContent)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+struct CreateWebhook {
+    url: String,
+    events: WebhookEvents,
+}
+
+#[openapi]
+#[get("/webhooks")]
+async fn api_get_webhooks(
+    user: Scoped<scope::WebhooksManage>,
+    client: &State<SqliteClient>,
+) -> Result<Json<Vec<Webhook>>, ApiErrors> {
+    let webhooks = Webhook::get_by_user_id(user.id, client)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to fetch webhooks: {}", e);
+            ApiErrors::InternalError("Failed to fetch webhooks".into())
+        })?;
+
+    Ok(Json(webhooks))
+}
+
+/// Registers a webhook. Requests are signed with the returned secret.
+#[openapi]
+#[post("/webhooks", data = "<body>")]
+async fn api_create_webhook(
+    audit: Audit<audit::WebhookCreate>,
+    user: Scoped<scope::WebhooksManage>,
+    body: Json<CreateWebhook>,
+    client: &State<SqliteClient>,
+) -> Result<Json<Webhook>, ApiErrors> {
+    let CreateWebhook { url, mut events } = body.into_inner();
+
+    let parsed = webhook::check_url(&url).await.map_err(ApiErrors::BadRequest)?;
+
+    events.0.sort_by_key(|e| e.to_string());
+    events.0.dedup();
+    if events.0.is_empty() {
+        return Err(ApiErrors::BadRequest(
+            "A webhook needs at least one event".into(),
+        ));
+    }
+
+    let webhook = Webhook::insert(user.id, parsed.as_str(), &events, client)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to create webhook: {}", e);
+            ApiErrors::InternalError("Failed to create webhook".into())
+        })?;
+    audit.target(format!("{} {}", webhook.id, webhook.url));
+
+    Ok(Json(webhook))
+}
+
+#[openapi]
+#[delete("/webhook/<webhook_id>")]
+async fn api_delete_webhook(
+    audit: Audit<audit::WebhookDelete>,
+    user: Scoped<scope::WebhooksManage>,
+    webhook_id: WebhookId,
+    client: &State<SqliteClient>,
+) -> Result<Status, ApiErrors> {
+    audit.target(webhook_id);
+    let deleted = Webhook::delete_by_id_and_user_id(webhook_id, user.id, client)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to delete webhook: {}", e);
+            ApiErrors::InternalError("Failed to delete webhook".into())
+        })?;
+
+    if !deleted {
+        return Err(ApiErrors::NotFound("Webhook not found".into()));
+    }
+
+    Ok(Status::NoContent)
+}
+
+/// Most recent deliveries across all of your webhooks.
+#[openapi]
+#[get("/webhook/deliveries?<limit>")]
+async fn api_get_webhook_deliveries(
+    user: Scoped<scope::WebhooksManage>,
+    limit: Option<i64>,
+    client: &State<SqliteClient>,
+) -> Result<Json<Vec<Delivery>>, ApiErrors> {
+    let limit = limit.unwrap_or(50).clamp(1, 500);
+    let deliveries = Delivery::get_by_user_id(user.id, limit, client)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to fetch webhook deliveries: {}", e);
+            ApiErrors::InternalError("Failed to fetch webhook deliveries".into())
+        })?;
+
+    Ok(Json(deliveries))
+}
+
+/// Every user with their upload and match counts.
+#[openapi]
+#[get("/admin/users")]
+async fn api_admin_get_users(
+    _admin: AdminUser,
+    client: &State<SqliteClient>,
+) -> Result<Json<Vec<UserUsage>>, ApiErrors> {
+    let users = UserUsage::all(client).await.map_err(|e| {
+        log::error!("Failed to fetch user usage: {}", e);
+        ApiErrors::InternalError("Failed to fetch user usage".into())
+    })?;
+
+    Ok(Json(users))
+}
+
+#[openapi]
+#[get("/admin/user/<user_id>/tokens")]
+async fn api_admin_get_user_tokens(
+    _admin: AdminUser,
+    user_id: UserId,
+    client: &State<SqliteClient>,
+) -> Result<Json<Vec<UserToken>>, ApiErrors> {
+    Ok(Json(UserToken::get_by_user_id(user_id, client).await?))
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+struct BanUserRequest {
+    reason: String,
+}
+
+/// Bans a user from uploading pilots and creating matches.
+#[openapi]
+#[post("/admin/user/<user_id>/ban", data = "<body>")]
+async fn api_admin_ban_user(
+    audit: Audit<audit::UserBan>,
+    admin: AdminUser,
+    user_id: UserId,
+    body: Json<BanUserRequest>,
+    client: &State<SqliteClient>,
+) -> Result<Json<User>, ApiErrors> {
+    let reason = body.reason.trim();
+    audit.target(format!("{}: {}", user_id, reason));
+
+    if user_id == admin.id {
+        return Err(ApiErrors::BadRequest("You can't ban yourself".into()));
+    }
+    if reason.is_empty() {
+        return Err(ApiErrors::BadRequest("A ban needs a reason".into()));
+    }
+
+    let user = User::set_ban(user_id, Some(reason), client)
+        .await
+        .map_err(|_| ApiErrors::NotFound("User not found".into()))?;
+    audit.target(format!("{} {}: {}", user.id, user.username, reason));
+
+    Ok(Json(user))
+}
+
+#[openapi]
+#[delete("/admin/user/<user_id>/ban")]
+async fn api_admin_unban_user(
+    audit: Audit<audit::UserUnban>,
+    _admin: AdminUser,
+    user_id: UserId,
+    client: &State<SqliteClient>,
+) -> Result<Json<User>, ApiErrors> {
+    audit.target(user_id);
+
+    let user = User::set_ban(user_id, None, client)
+        .await
+        .map_err(|_| ApiErrors::NotFound("User not found".into()))?;
+    audit.target(format!("{} {}", user.id, user.username));
+
+    Ok(Json(user))
+}
+
+/// Revokes any user's token.
+#[openapi]
+#[delete("/admin/user_token/<token_id>")]
+async fn api_admin_delete_user_token(
+    audit: Audit<audit::AdminTokenDelete>,
+    _admin: AdminUser,
+    token_id: UserTokenId,
+    client: &State<SqliteClient>,
+) -> Result<Status, ApiErrors> {
+    audit.target(token_id);
+
+    let owner = UserToken::delete_by_id(token_id, client).await.map_err(|e| {
+        log::error!("Failed to delete user token: {}", e);
+        ApiErrors::InternalError("Failed to delete user token".into())
+    })?;
+
+    let Some(owner) = owner else {
+        return Err(ApiErrors::NotFound("User token not found".into()));
+    };
+    audit.target(format!("{} of user {}", token_id, owner));
+
     Ok(Status::NoContent)
 }
 
@@ -211,9 +1274,33 @@ pub fn routes() -> Vec<Route> {
         api_health_check,
         api_get_ai_pilots,
         api_get_matches,
+        api_get_match,
+        api_export_matches,
+        api_export_pilot_matches,
+        api_get_h2h,
+        api_get_ratings,
+        api_get_pilot_stats,
+        api_get_version_stats,
+        api_get_leaderboard,
+        api_get_users,
+        api_get_user,
         api_post_match,
         api_upload_ai_pilot,
+        api_get_sync_status,
+        api_get_tournaments,
+        api_create_tournament,
+        api_get_tournament,
+        api_start_tournament,
         api_create_user_token,
         api_delete_user_token,
+        api_get_webhooks,
+        api_create_webhook,
+        api_delete_webhook,
+        api_get_webhook_deliveries,
+        api_admin_get_users,
+        api_admin_get_user_tokens,
+        api_admin_ban_user,
+        api_admin_unban_user,
+        api_admin_delete_user_token,
     ]
 }
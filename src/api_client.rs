@@ -1,150 +1,469 @@
-use std::env;
+use std::{
+    env, fmt,
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
 use client::{
-    apis::configuration::{ApiKey, Configuration},
+    apis::{
+        Error,
+        configuration::{ApiKey, Configuration},
+    },
     models::{AiPilot, MatchResult},
 };
-use moka::future::Cache;
-use rocket::futures::future::join_all;
+use rocket::tokio::time::{sleep, timeout};
 use uuid::Uuid;
 
-#[derive(Debug, Clone)]
-pub struct ApiClient {
+use crate::{api_error::ApiErrors, metrics};
+
+/// Why a call to the upstream AIP API failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UpstreamError {
+    /// No response within the call's timeout
+    Timeout,
+    /// Skipped since the upstream failed repeatedly, see [`CircuitBreaker`]
+    CircuitOpen,
+    /// Connecting failed or the response could not be read
+    Transport(String),
+    /// The upstream answered with an error status and body
+    Status(u16, String),
+}
+
+impl UpstreamError {
+    /// Whether the upstream itself is at fault rather than the request, such errors are
+    /// retried and count towards opening the circuit breaker.
+    fn is_outage(&self) -> bool {
+        match self {
+            UpstreamError::Status(status, _) => *status >= 500,
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for UpstreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpstreamError::Timeout => write!(f, "upstream timed out"),
+            UpstreamError::CircuitOpen => write!(f, "upstream is failing, circuit breaker open"),
+            UpstreamError::Transport(e) => write!(f, "upstream request failed: {}", e),
+            UpstreamError::Status(status, body) => {
+                write!(f, "upstream responded with {}: {}", status, body)
+            }
+        }
+    }
+}
+
+impl<T> From<Error<T>> for UpstreamError {
+    fn from(e: Error<T>) -> Self {
+        match e {
+            Error::ResponseError(res) => UpstreamError::Status(res.status.as_u16(), res.content),
+            Error::Reqwest(e) if e.is_timeout() => UpstreamError::Timeout,
+            e => UpstreamError::Transport(e.to_string()),
+        }
+    }
+}
+
+impl From<UpstreamError> for ApiErrors {
+    fn from(e: UpstreamError) -> Self {
+        match e {
+            UpstreamError::Status(404, _) => ApiErrors::NotFound("Not found upstream".into()),
+            UpstreamError::Status(400 | 409 | 413 | 422, body) => {
+                ApiErrors::BadRequest(format!("Rejected by the AI Pilot server: {}", body))
+            }
+            _ => ApiErrors::ServiceUnavailable(
+                "The AI Pilot server is unavailable, try again in a minute".into(),
+            ),
+        }
+    }
+}
+
+/// The upstream AIP API that runs the matches.
+#[async_trait]
+pub trait ApiClient: Send + Sync {
+    async fn get_match(&self, match_id: &str) -> Result<Option<MatchResult>, UpstreamError>;
+
+    async fn get_matches(
+        &self,
+        pilot_id: Option<&str>,
+        pilot_version: Option<i32>,
+    ) -> Result<Vec<MatchResult>, UpstreamError>;
+
+    async fn create_match(&self, pilot_a: &str, pilot_b: &str) -> Result<String, UpstreamError>;
+
+    async fn get_pilot(&self, pilot_id: &str) -> Result<Option<AiPilot>, UpstreamError>;
+
+    async fn get_pilot_by_name(&self, pilot_name: &str) -> Result<Option<AiPilot>, UpstreamError>;
+
+    async fn get_pilots(&self) -> Result<Vec<AiPilot>, UpstreamError>;
+
+    async fn upload_ai_pilot(
+        &self,
+        name: &str,
+        owner: &str,
+        data: Vec<u8>,
+    ) -> Result<(Uuid, i32, AiPilot), UpstreamError>;
+
+    /// Where replays are downloaded from.
+    fn base_url(&self) -> &str;
+}
+
+/// Consecutive failures that open the circuit breaker.
+const BREAKER_THRESHOLD: u32 = 5;
+/// How long an open circuit breaker rejects calls.
+const BREAKER_COOLDOWN: Duration = Duration::from_secs(30);
+/// Uploads carry up to 25 MiB, so they get longer than other calls.
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(200);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(10);
+/// More retries than this only keep a request hanging while the breaker opens anyway.
+const MAX_RETRIES: u32 = 8;
+
+/// Fails calls fast while the upstream is down instead of letting each wait for a timeout.
+/// Once the cooldown passes calls go through again, and the first failure reopens it.
+#[derive(Debug, Default)]
+pub struct CircuitBreaker {
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    fn check(&self) -> Result<(), UpstreamError> {
+        self.check_at(Instant::now())
+    }
+
+    fn check_at(&self, now: Instant) -> Result<(), UpstreamError> {
+        match self.state.lock().unwrap().open_until {
+            Some(until) if now < until => Err(UpstreamError::CircuitOpen),
+            _ => Ok(()),
+        }
+    }
+
+    fn record_success(&self) {
+        *self.state.lock().unwrap() = BreakerState::default();
+    }
+
+    fn record_failure(&self) {
+        self.record_failure_at(Instant::now())
+    }
+
+    fn record_failure_at(&self, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+
+        if state.consecutive_failures >= BREAKER_THRESHOLD {
+            if state.open_until.is_none() {
+                log::warn!(
+                    "Upstream failed {} times in a row, pausing calls for {}s",
+                    state.consecutive_failures,
+                    BREAKER_COOLDOWN.as_secs()
+                );
+            }
+            state.open_until = Some(now + BREAKER_COOLDOWN);
+        }
+    }
+}
+
+/// Longest delay before retry `attempt` (from 0): 200ms, 400ms, 800ms and so on, up to
+/// [`RETRY_MAX_DELAY`].
+fn max_backoff(attempt: u32) -> Duration {
+    RETRY_BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(RETRY_MAX_DELAY)
+}
+
+/// Random delay before retry `attempt`, up to [`max_backoff`].
+fn backoff(attempt: u32) -> Duration {
+    let max = max_backoff(attempt).as_millis() as u64;
+    Duration::from_millis(rand::random_range(0..=max))
+}
+
+/// [`ApiClient`] over HTTP, with a timeout per call, retries of reads and a [`CircuitBreaker`].
+#[derive(Debug)]
+pub struct HttpApiClient {
     configuration: Configuration,
-    pilot_name_cache: Cache<String, String>,
+    /// `AIP_API_TIMEOUT_SECS`, 10 by default
+    timeout: Duration,
+    /// `AIP_API_RETRIES`, how often failed reads are retried, 2 by default and at most 8
+    retries: u32,
+    breaker: CircuitBreaker,
 }
 
-impl ApiClient {
+impl HttpApiClient {
     pub fn new() -> Self {
+        let mut client = Self::with_base_url(
+            env::var("AIP_API_BASE_URL").expect("AIP_API_BASE_URL must be set"),
+            env::var("AIP_API_KEY").expect("AIP_API_KEY must be set"),
+        );
+        if let Some(secs) = env::var("AIP_API_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            client.timeout = Duration::from_secs(secs);
+        }
+        if let Some(retries) = env::var("AIP_API_RETRIES")
+            .ok()
+            .and_then(|v| v.parse::<u32>().ok())
+        {
+            client.retries = retries.min(MAX_RETRIES);
+        }
+
+        client
+    }
+
+    /// A client for the upstream at `base_path`, such as a [`crate::mock_upstream`].
+    pub fn with_base_url(base_path: String, api_key: String) -> Self {
         let configuration = Configuration {
-            base_path: env::var("AIP_API_BASE_URL").expect("AIP_API_BASE_URL must be set"),
-            user_agent: Some("api-front/1.0".to_string()),
+            base_path,
+            user_agent: Some("aip-front/1.0".to_string()),
             api_key: Some(ApiKey {
                 prefix: None,
-                key: env::var("AIP_API_KEY").expect("AIP_API_KEY must be set"),
+                key: api_key,
             }),
             ..Default::default()
         };
 
-        let pilot_name_cache = Cache::builder().max_capacity(2048).build();
-
-        ApiClient {
+        HttpApiClient {
             configuration,
-            pilot_name_cache,
+            timeout: Duration::from_secs(10),
+            retries: 2,
+            breaker: CircuitBreaker::default(),
         }
     }
 
-    pub async fn get_match(&self, match_id: &str) -> Option<MatchResult> {
-        match client::apis::default_api::get_match_results(
-            &self.configuration,
-            None,
-            None,
-            Some(match_id),
-        )
-        .await
-        {
-            Ok(mut matches) => matches.pop(),
-            Err(e) => {
-                error!("Failed to fetch match result: {}", e);
-                None
+    /// Calls `function` of the generated client, retrying with jittered backoff if `idempotent`.
+    async fn call<T, E, Fut>(
+        &self,
+        function: &'static str,
+        idempotent: bool,
+        limit: Duration,
+        mut call: impl FnMut() -> Fut,
+    ) -> Result<T, UpstreamError>
+    where
+        Fut: Future<Output = Result<T, Error<E>>>,
+    {
+        let mut attempt = 0;
+
+        loop {
+            self.breaker.check()?;
+
+            let result = metrics::upstream(function, async {
+                match timeout(limit, call()).await {
+                    Ok(result) => result.map_err(UpstreamError::from),
+                    Err(_) => Err(UpstreamError::Timeout),
+                }
+            })
+            .await;
+
+            let e = match result {
+                Ok(value) => {
+                    self.breaker.record_success();
+                    return Ok(value);
+                }
+                Err(e) if !e.is_outage() => {
+                    self.breaker.record_success();
+                    return Err(e);
+                }
+                Err(e) => e,
+            };
+
+            self.breaker.record_failure();
+            if !idempotent || attempt >= self.retries {
+                error!("Upstream {} failed: {}", function, e);
+                return Err(e);
             }
+
+            log::warn!("Upstream {} failed, retrying: {}", function, e);
+            sleep(backoff(attempt)).await;
+            attempt += 1;
         }
     }
 
-    pub async fn get_matches(
+    async fn find_pilots(
+        &self,
+        name: Option<&str>,
+        id: Option<&str>,
+    ) -> Result<Vec<AiPilot>, UpstreamError> {
+        self.call("get_ai_pilots", true, self.timeout, || {
+            client::apis::default_api::get_ai_pilots(&self.configuration, name, id)
+        })
+        .await
+    }
+}
+
+impl Default for HttpApiClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl ApiClient for HttpApiClient {
+    async fn get_match(&self, match_id: &str) -> Result<Option<MatchResult>, UpstreamError> {
+        let mut matches = self
+            .call("get_match_results", true, self.timeout, || {
+                client::apis::default_api::get_match_results(
+                    &self.configuration,
+                    None,
+                    None,
+                    Some(match_id),
+                )
+            })
+            .await?;
+
+        Ok(matches.pop())
+    }
+
+    async fn get_matches(
         &self,
         pilot_id: Option<&str>,
         pilot_version: Option<i32>,
-    ) -> Vec<MatchResult> {
-        match client::apis::default_api::get_match_results(
-            &self.configuration,
-            pilot_id,
-            pilot_version.map(|v| v.to_string()).as_deref(),
-            None,
-        )
+    ) -> Result<Vec<MatchResult>, UpstreamError> {
+        let pilot_version = pilot_version.map(|v| v.to_string());
+
+        self.call("get_match_results", true, self.timeout, || {
+            client::apis::default_api::get_match_results(
+                &self.configuration,
+                pilot_id,
+                pilot_version.as_deref(),
+                None,
+            )
+        })
         .await
-        {
-            Ok(matches) => matches,
-            Err(e) => {
-                error!("Failed to fetch match results: {}", e);
-                Vec::new()
-            }
-        }
     }
 
-    pub async fn create_match(&self, pilot_a: &str, pilot_b: &str) -> Result<String, String> {
-        let res =
-            client::apis::default_api::start_manual_fight(&self.configuration, pilot_a, pilot_b)
-                .await
-                .map_err(|e| e.to_string())?;
+    async fn create_match(&self, pilot_a: &str, pilot_b: &str) -> Result<String, UpstreamError> {
+        // Starting a fight is a GET upstream, but repeating it would queue a second fight
+        let res = self
+            .call("start_manual_fight", false, self.timeout, || {
+                client::apis::default_api::start_manual_fight(&self.configuration, pilot_a, pilot_b)
+            })
+            .await?;
 
         Ok(res.match_id.to_string())
     }
 
-    pub async fn get_pilot(&self, pilot_id: &str) -> Option<AiPilot> {
-        match client::apis::default_api::get_ai_pilots(&self.configuration, None, Some(pilot_id))
-            .await
-        {
-            Ok(mut pilots) => pilots.pop(),
-            Err(e) => {
-                error!("Failed to fetch pilot: {}", e);
-                None
-            }
-        }
+    async fn get_pilot(&self, pilot_id: &str) -> Result<Option<AiPilot>, UpstreamError> {
+        Ok(self.find_pilots(None, Some(pilot_id)).await?.pop())
     }
 
-    pub async fn get_pilot_by_name(&self, pilot_name: &str) -> Option<AiPilot> {
-        match client::apis::default_api::get_ai_pilots(&self.configuration, Some(pilot_name), None)
-            .await
-        {
-            Ok(mut pilots) => pilots.pop(),
-            Err(e) => {
-                error!("Failed to fetch pilot by name: {}", e);
-                None
-            }
-        }
+    async fn get_pilot_by_name(&self, pilot_name: &str) -> Result<Option<AiPilot>, UpstreamError> {
+        Ok(self.find_pilots(Some(pilot_name), None).await?.pop())
     }
 
-    pub async fn get_pilots(&self) -> Vec<AiPilot> {
-        match client::apis::default_api::get_ai_pilots(&self.configuration, None, None).await {
-            Ok(pilots) => {
-                join_all(pilots.iter().map(|pilot| {
-                    self.pilot_name_cache
-                        .insert(pilot.id.to_string(), pilot.name.clone())
-                }))
-                .await;
-                pilots
-            }
-            Err(e) => {
-                error!("Failed to fetch pilot list: {}", e);
-                Vec::new()
-            }
-        }
+    async fn get_pilots(&self) -> Result<Vec<AiPilot>, UpstreamError> {
+        self.find_pilots(None, None).await
     }
 
-    pub async fn upload_ai_pilot(
+    async fn upload_ai_pilot(
         &self,
         name: &str,
         owner: &str,
         data: Vec<u8>,
-    ) -> Result<(Uuid, i32), String> {
-        let res = client::apis::default_api::upload_ai_pilot(
-            &self.configuration,
-            name,
-            data,
-            Some(owner),
-        )
-        .await
-        .map_err(|e| e.to_string())?;
+    ) -> Result<(Uuid, i32, AiPilot), UpstreamError> {
+        // Never retried, so the body is only needed once
+        let mut data = Some(data);
+        let res = self
+            .call("upload_ai_pilot", false, UPLOAD_TIMEOUT, || {
+                client::apis::default_api::upload_ai_pilot(
+                    &self.configuration,
+                    name,
+                    data.take().unwrap_or_default(),
+                    Some(owner),
+                )
+            })
+            .await?;
 
-        Ok((res.upload_id, res.version))
+        Ok((res.upload_id, res.version, res.ai_pilot))
     }
 
-    pub async fn get_cached_pilot_name(&self, pilot_id: &str) -> Option<String> {
-        self.pilot_name_cache.get(pilot_id).await
+    fn base_url(&self) -> &str {
+        &self.configuration.base_path
     }
+}
 
-    pub fn base_url(&self) -> &str {
-        &self.configuration.base_path
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOMENT: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn breaker_opens_after_repeated_failures() {
+        let breaker = CircuitBreaker::default();
+        let now = Instant::now();
+
+        for _ in 1..BREAKER_THRESHOLD {
+            breaker.record_failure_at(now);
+            assert_eq!(breaker.check_at(now), Ok(()));
+        }
+        breaker.record_failure_at(now);
+        assert_eq!(breaker.check_at(now), Err(UpstreamError::CircuitOpen));
+        assert_eq!(
+            breaker.check_at(now + BREAKER_COOLDOWN - MOMENT),
+            Err(UpstreamError::CircuitOpen)
+        );
+    }
+
+    #[test]
+    fn breaker_half_opens_after_cooldown() {
+        let breaker = CircuitBreaker::default();
+        let now = Instant::now();
+        for _ in 0..BREAKER_THRESHOLD {
+            breaker.record_failure_at(now);
+        }
+
+        // One call goes through, and failing again reopens it right away
+        let later = now + BREAKER_COOLDOWN;
+        assert_eq!(breaker.check_at(later), Ok(()));
+        breaker.record_failure_at(later);
+        assert_eq!(breaker.check_at(later), Err(UpstreamError::CircuitOpen));
+        assert_eq!(
+            breaker.check_at(later + BREAKER_COOLDOWN - MOMENT),
+            Err(UpstreamError::CircuitOpen)
+        );
+
+        // A success closes it, so it takes the full threshold to open again
+        let later = later + BREAKER_COOLDOWN;
+        assert_eq!(breaker.check_at(later), Ok(()));
+        breaker.record_success();
+        for _ in 1..BREAKER_THRESHOLD {
+            breaker.record_failure_at(later);
+        }
+        assert_eq!(breaker.check_at(later), Ok(()));
+    }
+
+    #[test]
+    fn breaker_only_counts_consecutive_failures() {
+        let breaker = CircuitBreaker::default();
+        let now = Instant::now();
+
+        for _ in 0..BREAKER_THRESHOLD * 3 {
+            breaker.record_failure_at(now);
+            breaker.record_failure_at(now);
+            breaker.record_success();
+        }
+        assert_eq!(breaker.check_at(now), Ok(()));
+    }
+
+    #[test]
+    fn backoff_doubles() {
+        assert_eq!(max_backoff(0), Duration::from_millis(200));
+        assert_eq!(max_backoff(1), Duration::from_millis(400));
+        assert_eq!(max_backoff(2), Duration::from_millis(800));
+        assert_eq!(max_backoff(5), Duration::from_millis(6400));
+        assert_eq!(max_backoff(6), RETRY_MAX_DELAY);
+        assert_eq!(max_backoff(40), RETRY_MAX_DELAY);
+
+        for attempt in 0..4 {
+            for _ in 0..100 {
+                assert!(backoff(attempt) <= max_backoff(attempt));
+            }
+        }
     }
 }
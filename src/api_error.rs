@@ -1,5 +1,10 @@
 use okapi::{Map, openapi3::RefOr};
-use rocket::{Request, http::Status, response::Responder, serde::json::Json};
+use rocket::{
+    Request,
+    http::{Header, Status},
+    response::Responder,
+    serde::json::Json,
+};
 use rocket_dyn_templates::{Template, context};
 use rocket_okapi::{JsonSchema, r#gen::OpenApiGenerator, response::OpenApiResponderInner};
 use serde::{Deserialize, Serialize};
@@ -13,7 +18,12 @@ struct ErrorMessageInner {
 pub enum ApiErrors {
     NotFound(String),
     BadRequest(String),
+    Forbidden(String),
+    /// Rate limited, with the seconds until the client may retry
+    TooManyRequests(String, i64),
     InternalError(String),
+    /// The upstream AIP API is down or timing out
+    ServiceUnavailable(String),
 }
 
 impl ApiErrors {
@@ -21,7 +31,10 @@ impl ApiErrors {
         match self {
             ApiErrors::NotFound(_) => 404,
             ApiErrors::BadRequest(_) => 400,
+            ApiErrors::Forbidden(_) => 403,
+            ApiErrors::TooManyRequests(..) => 429,
             ApiErrors::InternalError(_) => 500,
+            ApiErrors::ServiceUnavailable(_) => 503,
         }
     }
 
@@ -29,7 +42,10 @@ impl ApiErrors {
         match self {
             ApiErrors::NotFound(msg) => msg,
             ApiErrors::BadRequest(msg) => msg,
+            ApiErrors::Forbidden(msg) => msg,
+            ApiErrors::TooManyRequests(msg, _) => msg,
             ApiErrors::InternalError(msg) => msg,
+            ApiErrors::ServiceUnavailable(msg) => msg,
         }
     }
 
@@ -37,7 +53,10 @@ impl ApiErrors {
         match self {
             ApiErrors::NotFound(_) => "Not Found",
             ApiErrors::BadRequest(_) => "Bad Request",
+            ApiErrors::Forbidden(_) => "Forbidden",
+            ApiErrors::TooManyRequests(..) => "Too Many Requests",
             ApiErrors::InternalError(_) => "Internal Server Error",
+            ApiErrors::ServiceUnavailable(_) => "Service Unavailable",
         }
     }
 }
@@ -61,7 +80,7 @@ impl<'r> Responder<'r, 'static> for ApiErrors {
             .get("Accept")
             .any(|accept| accept.contains("text/html"));
 
-        if accepts_html {
+        let mut response = if accepts_html {
             // Render HTML error page
             let template = Template::render(
                 "error",
@@ -70,7 +89,7 @@ impl<'r> Responder<'r, 'static> for ApiErrors {
                     message: self.message()
                 },
             );
-            template.respond_to(request)
+            template.respond_to(request)?
         } else {
             // Render JSON error
             let json_response = Json(ErrorMessageInner {
@@ -79,8 +98,20 @@ impl<'r> Responder<'r, 'static> for ApiErrors {
 
             let mut response = json_response.respond_to(request)?;
             response.set_status(Status::from_code(self.status_code()).unwrap());
-            Ok(response)
+            response
+        };
+
+        if let ApiErrors::TooManyRequests(_, retry_after) = self {
+            response.set_status(Status::TooManyRequests);
+            response.set_header(Header::new("Retry-After", retry_after.to_string()));
+        }
+
+        // Also for the HTML page, so monitoring sees the outage
+        if let ApiErrors::ServiceUnavailable(_) = self {
+            response.set_status(Status::ServiceUnavailable);
         }
+
+        Ok(response)
     }
 }
 
@@ -118,6 +149,36 @@ impl OpenApiResponderInner for ApiErrors {
             }),
         );
 
+        responses.insert(
+            "403".to_string(),
+            RefOr::Object(okapi::openapi3::Response {
+                description: "Forbidden".to_string(),
+                content: Map::from([(
+                    "application/json".to_string(),
+                    okapi::openapi3::MediaType {
+                        schema: Some(gene.json_schema::<ErrorMessageInner>()),
+                        ..Default::default()
+                    },
+                )]),
+                ..Default::default()
+            }),
+        );
+
+        responses.insert(
+            "429".to_string(),
+            RefOr::Object(okapi::openapi3::Response {
+                description: "Too Many Requests".to_string(),
+                content: Map::from([(
+                    "application/json".to_string(),
+                    okapi::openapi3::MediaType {
+                        schema: Some(gene.json_schema::<ErrorMessageInner>()),
+                        ..Default::default()
+                    },
+                )]),
+                ..Default::default()
+            }),
+        );
+
         responses.insert(
             "500".to_string(),
             RefOr::Object(okapi::openapi3::Response {
@@ -133,6 +194,21 @@ impl OpenApiResponderInner for ApiErrors {
             }),
         );
 
+        responses.insert(
+            "503".to_string(),
+            RefOr::Object(okapi::openapi3::Response {
+                description: "Upstream AI Pilot server unavailable".to_string(),
+                content: Map::from([(
+                    "application/json".to_string(),
+                    okapi::openapi3::MediaType {
+                        schema: Some(gene.json_schema::<ErrorMessageInner>()),
+                        ..Default::default()
+                    },
+                )]),
+                ..Default::default()
+            }),
+        );
+
         Ok(okapi::openapi3::Responses {
             responses,
             ..Default::default()
@@ -0,0 +1,291 @@
+use std::{
+    fmt,
+    marker::PhantomData,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use chrono::{DateTime, Utc};
+use rocket::{
+    Request, Response,
+    fairing::{Fairing, Info, Kind},
+    http::Header,
+    request::{FromRequest, Outcome},
+};
+use rocket_okapi::{
+    r#gen::OpenApiGenerator,
+    request::{OpenApiFromRequest, RequestHeaderInput},
+};
+use serde::{Deserialize, Serialize};
+use sqlx::prelude::FromRow;
+use uuid::Uuid;
+
+use crate::{SqliteClient, cookie::ApiUser, model::UserId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum AuditAction {
+    PilotUpload,
+    MatchCreate,
+    TournamentCreate,
+    TournamentStart,
+    TokenCreate,
+    TokenDelete,
+    WebhookCreate,
+    WebhookDelete,
+    UserBan,
+    UserUnban,
+    AdminTokenDelete,
+}
+
+impl AuditAction {
+    pub fn description(&self) -> &'static str {
+        match self {
+            AuditAction::PilotUpload => "Uploaded a pilot",
+            AuditAction::MatchCreate => "Queued a match",
+            AuditAction::TournamentCreate => "Created a tournament",
+            AuditAction::TournamentStart => "Started a tournament",
+            AuditAction::TokenCreate => "Created a token",
+            AuditAction::TokenDelete => "Deleted a token",
+            AuditAction::WebhookCreate => "Registered a webhook",
+            AuditAction::WebhookDelete => "Deleted a webhook",
+            AuditAction::UserBan => "Banned a user",
+            AuditAction::UserUnban => "Unbanned a user",
+            AuditAction::AdminTokenDelete => "Revoked a user's token",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum AuditResult {
+    Success,
+    Failure,
+}
+
+/// A mutating request by a signed in user, written by [`AuditLog`] after the response.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, FromRow)]
+pub struct AuditEvent {
+    pub id: i64,
+    /// Missing once the user was deleted, their name is kept
+    pub actor_id: Option<UserId>,
+    pub actor_name: String,
+    pub action: AuditAction,
+    /// What the action was applied to, the request path if the route didn't say
+    pub target: String,
+    pub request_id: String,
+    pub ip: Option<String>,
+    pub result: AuditResult,
+    /// HTTP status of the response
+    pub status: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AuditEvent {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn insert(
+        actor_id: UserId,
+        actor_name: &str,
+        action: AuditAction,
+        target: &str,
+        request_id: &str,
+        ip: Option<&str>,
+        status: u16,
+        client: &SqliteClient,
+    ) -> Result<(), sqlx::Error> {
+        let result = if status < 400 {
+            AuditResult::Success
+        } else {
+            AuditResult::Failure
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO audit_events (actor_id, actor_name, action, target, request_id, ip, result,
+                                      status, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+        )
+        .bind(actor_id)
+        .bind(actor_name)
+        .bind(action)
+        .bind(target)
+        .bind(request_id)
+        .bind(ip)
+        .bind(result)
+        .bind(status)
+        .bind(Utc::now())
+        .execute(client)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Most recent events, optionally only those of one actor.
+    pub async fn recent(
+        actor_id: Option<UserId>,
+        limit: i64,
+        client: &SqliteClient,
+    ) -> Result<Vec<AuditEvent>, sqlx::Error> {
+        sqlx::query_as::<_, AuditEvent>(
+            r#"
+            SELECT id, actor_id, actor_name, action, target, request_id, ip, result, status,
+                   created_at
+            FROM audit_events
+            WHERE $1 IS NULL OR actor_id = $1
+            ORDER BY created_at DESC, id DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(actor_id)
+        .bind(limit)
+        .fetch_all(client)
+        .await
+    }
+}
+
+/// Id of the current request, echoed in the `X-Request-Id` response header.
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    pub fn of<'r>(request: &'r Request<'_>) -> &'r str {
+        &request
+            .local_cache(|| RequestId(Uuid::new_v4().to_string()))
+            .0
+    }
+}
+
+/// What the audited route of the current request did, filled in by [`Audit`].
+#[derive(Debug, Default)]
+struct AuditNote {
+    action: OnceLock<AuditAction>,
+    actor: OnceLock<Option<(UserId, String)>>,
+    target: Mutex<Option<String>>,
+}
+
+/// Action a route is audited as.
+pub trait AuditedAction: Send + Sync + 'static {
+    const ACTION: AuditAction;
+}
+
+macro_rules! audited_action {
+    ($name:ident) => {
+        pub struct $name;
+
+        impl AuditedAction for $name {
+            const ACTION: AuditAction = AuditAction::$name;
+        }
+    };
+}
+
+audited_action!(PilotUpload);
+audited_action!(MatchCreate);
+audited_action!(TournamentCreate);
+audited_action!(TournamentStart);
+audited_action!(TokenCreate);
+audited_action!(TokenDelete);
+audited_action!(WebhookCreate);
+audited_action!(WebhookDelete);
+audited_action!(UserBan);
+audited_action!(UserUnban);
+audited_action!(AdminTokenDelete);
+
+/// Records the request as `A` once the response is ready, whether it succeeded or not.
+/// Never fails, so place it first to also record requests rejected by later guards.
+pub struct Audit<A: AuditedAction> {
+    note: Arc<AuditNote>,
+    _action: PhantomData<A>,
+}
+
+impl<A: AuditedAction> Audit<A> {
+    /// Names what the action was applied to, e.g. the id of a created resource.
+    pub fn target(&self, target: impl fmt::Display) {
+        *self.note.target.lock().unwrap() = Some(target.to_string());
+    }
+}
+
+#[async_trait]
+impl<'r, A: AuditedAction> FromRequest<'r> for Audit<A> {
+    type Error = String;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let actor = request
+            .guard::<ApiUser>()
+            .await
+            .succeeded()
+            .map(|u| (u.id, u.username));
+
+        let note = request.local_cache(Arc::<AuditNote>::default);
+        let _ = note.action.set(A::ACTION);
+        let _ = note.actor.set(actor);
+
+        Outcome::Success(Audit {
+            note: note.clone(),
+            _action: PhantomData,
+        })
+    }
+}
+
+impl<'a, A: AuditedAction> OpenApiFromRequest<'a> for Audit<A> {
+    fn from_request_input(
+        _gene: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::None)
+    }
+}
+
+/// Writes the audit events noted by [`Audit`] guards and tags responses with a request id.
+pub struct AuditLog;
+
+#[async_trait]
+impl Fairing for AuditLog {
+    fn info(&self) -> Info {
+        Info {
+            name: "Audit log",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let request_id = RequestId::of(request);
+        response.set_header(Header::new("X-Request-Id", request_id.to_string()));
+
+        let note = request.local_cache(Arc::<AuditNote>::default);
+        // Anonymous requests never get past the auth guards, so aren't worth keeping
+        let (Some(action), Some(Some((actor_id, actor_name)))) =
+            (note.action.get(), note.actor.get())
+        else {
+            return;
+        };
+        let Some(client) = request.rocket().state::<SqliteClient>() else {
+            return;
+        };
+
+        let target = note
+            .target
+            .lock()
+            .unwrap()
+            .clone()
+            .unwrap_or_else(|| request.uri().to_string());
+        let ip = request.client_ip().map(|ip| ip.to_string());
+
+        if let Err(e) = AuditEvent::insert(
+            *actor_id,
+            actor_name,
+            *action,
+            &target,
+            request_id,
+            ip.as_deref(),
+            response.status().code,
+            client,
+        )
+        .await
+        {
+            log::error!("Failed to write audit event for {:?}: {}", action, e);
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use client::models::MatchResult;
+use rocket::{
+    Route, State,
+    http::{Header, Status},
+};
+use rocket_dyn_templates::{Template, context};
+use uuid::Uuid;
+
+use crate::{
+    SqliteClient,
+    api_error::ApiErrors,
+    mirror::{self, MatchFilter},
+    sso_client::SSOClient,
+    stats::{Outcome, Record, StatsCache},
+};
+
+// Shields style SVG badges showing a pilot's or user's record, for embedding in READMEs.
+
+/// Badges are embedded in pages proxied by image caches, no need to recompute them often.
+const CACHE_CONTROL: &str = "public, max-age=300";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, FromFormField)]
+pub enum BadgeStyle {
+    #[default]
+    Flat,
+    #[field(value = "flat-square")]
+    FlatSquare,
+    #[field(value = "for-the-badge")]
+    ForTheBadge,
+}
+
+/// What the middle of the message shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, FromFormField)]
+pub enum BadgeMetric {
+    #[default]
+    Winrate,
+    Rating,
+}
+
+#[derive(Debug, Default, FromForm)]
+pub struct BadgeQuery {
+    style: Option<BadgeStyle>,
+    metric: Option<BadgeMetric>,
+    /// Replaces the pilot or user name on the left
+    label: Option<String>,
+    /// Named shields color or hex code, picked from the win rate or rating by default
+    color: Option<String>,
+    #[field(name = "labelColor")]
+    label_color: Option<String>,
+}
+
+/// Shields color names, anything else has to be a hex code.
+const NAMED_COLORS: [(&str, &str); 10] = [
+    ("brightgreen", "4c1"),
+    ("green", "97ca00"),
+    ("yellowgreen", "a4a61d"),
+    ("yellow", "dfb317"),
+    ("orange", "fe7d37"),
+    ("red", "e05d44"),
+    ("blue", "007ec6"),
+    ("lightgrey", "9f9f9f"),
+    ("grey", "555"),
+    ("gray", "555"),
+];
+
+/// Hex code for a color parameter, `None` for anything unknown so it can't end up in the SVG.
+fn parse_color(color: &str) -> Option<String> {
+    let color = color.trim_start_matches('#');
+    if let Some((_, hex)) = NAMED_COLORS.iter().find(|(name, _)| *name == color) {
+        return Some(hex.to_string());
+    }
+
+    let is_hex = matches!(color.len(), 3 | 6) && color.chars().all(|c| c.is_ascii_hexdigit());
+    is_hex.then(|| color.to_string())
+}
+
+fn named_color(name: &str) -> String {
+    parse_color(name).unwrap_or_default()
+}
+
+/// Approximate width of `text` in 11px Verdana, close enough to size the badge.
+fn text_width(text: &str) -> f64 {
+    text.chars()
+        .map(|c| match c {
+            'i' | 'l' | 'j' | '.' | ',' | ':' | ';' | '!' | '|' | '\'' => 3.5,
+            ' ' | '·' | '(' | ')' | '-' | 'f' | 't' | 'r' | 'I' => 4.5,
+            'm' | 'w' | '%' | 'M' | 'W' => 10.5,
+            c if c.is_ascii_uppercase() => 7.5,
+            _ => 6.8,
+        })
+        .sum()
+}
+
+/// Result of the latest match any of `pilot_ids` played, a match counts as won when one
+/// of them was on the winning team.
+async fn last_outcome(
+    pilot_ids: &[Uuid],
+    client: &SqliteClient,
+) -> Result<Option<Outcome>, ApiErrors> {
+    let mut latest: Option<MatchResult> = None;
+    for pilot_id in pilot_ids {
+        let filter = MatchFilter {
+            pilot_id: Some(*pilot_id),
+            ..Default::default()
+        };
+        let (matches, _) = mirror::get_matches_page(&filter, None, 1, client).await?;
+        for m in matches {
+            if latest.as_ref().is_none_or(|l| m.created_at > l.created_at) {
+                latest = Some(m);
+            }
+        }
+    }
+
+    Ok(latest.and_then(|m| Outcome::of_any(&m, pilot_ids)))
+}
+
+/// Builds the message after `prefix`, e.g. `v3 · 67% (8-4) · last won`, and its default color.
+fn message(
+    prefix: String,
+    (record, last): (Record, Option<Outcome>),
+    metric: BadgeMetric,
+    rating: f64,
+) -> (String, String) {
+    if record.total() == 0 {
+        return (format!("{} · no matches", prefix), named_color("lightgrey"));
+    }
+
+    let (value, color) = match metric {
+        BadgeMetric::Winrate => {
+            let win_rate = record.win_rate();
+            let color = match win_rate {
+                r if r >= 60.0 => "brightgreen",
+                r if r >= 50.0 => "green",
+                r if r >= 40.0 => "yellow",
+                r if r >= 25.0 => "orange",
+                _ => "red",
+            };
+            // Undecided matches only show up when there are any, as a third count
+            let counts = match record.undecided {
+                0 => format!("{}-{}", record.wins, record.losses),
+                undecided => format!("{}-{}-{}", record.wins, record.losses, undecided),
+            };
+            (format!("{:.0}% ({})", win_rate, counts), color)
+        }
+        BadgeMetric::Rating => {
+            let color = match rating {
+                r if r >= 1700.0 => "brightgreen",
+                r if r >= 1600.0 => "green",
+                r if r >= 1500.0 => "yellowgreen",
+                r if r >= 1400.0 => "yellow",
+                r if r >= 1300.0 => "orange",
+                _ => "red",
+            };
+            (format!("{:.0} rating", rating), color)
+        }
+    };
+    let last = match last {
+        Some(Outcome::Win) => " · last won",
+        Some(Outcome::Loss) => " · last lost",
+        Some(Outcome::Undecided) => " · last undecided",
+        None => "",
+    };
+
+    (
+        format!("{} · {}{}", prefix, value, last),
+        named_color(color),
+    )
+}
+
+/// An SVG badge with cache headers.
+#[derive(Responder)]
+pub struct Badge {
+    svg: Template,
+    cache_control: Header<'static>,
+}
+
+impl Badge {
+    fn new(label: String, message: String, color: String, query: &BadgeQuery) -> Self {
+        let style = query.style.unwrap_or_default();
+        let label = query.label.clone().unwrap_or(label);
+        let color = query
+            .color
+            .as_deref()
+            .and_then(parse_color)
+            .unwrap_or(color);
+        let label_color = query
+            .label_color
+            .as_deref()
+            .and_then(parse_color)
+            .unwrap_or_else(|| named_color("grey"));
+
+        let (label, message, height, font_size, text_y, spacing) = match style {
+            BadgeStyle::ForTheBadge => (
+                label.to_uppercase(),
+                message.to_uppercase(),
+                28,
+                10,
+                18,
+                1.25,
+            ),
+            _ => (label, message, 20, 11, 14, 0.0),
+        };
+        let width_of = |text: &str| {
+            let scale = font_size as f64 / 11.0;
+            let text = text_width(text) * scale + spacing * text.chars().count() as f64;
+            (text + if spacing > 0.0 { 24.0 } else { 10.0 }).round() as u32
+        };
+        let label_width = width_of(&label);
+        let message_width = width_of(&message);
+
+        Badge {
+            svg: Template::render(
+                "badge",
+                context! {
+                    width: label_width + message_width,
+                    height,
+                    label_width,
+                    message_width,
+                    label_x: label_width as f64 / 2.0,
+                    message_x: label_width as f64 + message_width as f64 / 2.0,
+                    text_y,
+                    font_size,
+                    letter_spacing: spacing,
+                    bold: style == BadgeStyle::ForTheBadge,
+                    rounded: style == BadgeStyle::Flat,
+                    label,
+                    message,
+                    color,
+                    label_color,
+                },
+            ),
+            cache_control: Header::new("Cache-Control", CACHE_CONTROL),
+        }
+    }
+
+    /// Grey badge for unknown pilots and users, still an image so embeds don't break.
+    fn not_found(label: &str, query: &BadgeQuery) -> (Status, Self) {
+        // Only the style carries over, a custom label or color would hide the error
+        let query = BadgeQuery {
+            style: query.style,
+            ..Default::default()
+        };
+        let badge = Badge::new(
+            label.to_string(),
+            "not found".into(),
+            named_color("lightgrey"),
+            &query,
+        );
+        (Status::NotFound, badge)
+    }
+}
+
+/// Badges are named `<name>.svg`, Rocket can't match part of a segment.
+fn strip_svg(file: &str) -> Result<&str, ApiErrors> {
+    file.strip_suffix(".svg")
+        .ok_or_else(|| ApiErrors::NotFound("Badge not found".into()))
+}
+
+#[get("/pilot/<file>?<query..>")]
+async fn pilot_badge(
+    file: &str,
+    query: BadgeQuery,
+    client: &State<SqliteClient>,
+    stats: &State<StatsCache>,
+) -> Result<(Status, Badge), ApiErrors> {
+    let name = strip_svg(file)?;
+    let Some(pilot) = mirror::get_pilot_by_name(name, client).await? else {
+        return Ok(Badge::not_found(name, &query));
+    };
+
+    let stats = stats.get(client).await?;
+    let pilot_stats = stats.pilot(&pilot.id);
+    let record = pilot_stats.map(|p| p.record).unwrap_or_default();
+    let rating = pilot_stats.map(|p| p.rating.rating).unwrap_or_default();
+    let last = last_outcome(&[pilot.id], client).await?;
+
+    let (message, color) = message(
+        format!("v{}", pilot.current.version),
+        (record, last),
+        query.metric.unwrap_or_default(),
+        rating,
+    );
+    Ok((Status::Ok, Badge::new(pilot.name, message, color, &query)))
+}
+
+#[get("/user/<file>?<query..>")]
+async fn user_badge(
+    file: &str,
+    query: BadgeQuery,
+    sso_client: &State<SSOClient>,
+    client: &State<SqliteClient>,
+    stats: &State<StatsCache>,
+) -> Result<(Status, Badge), ApiErrors> {
+    let owner_id = strip_svg(file)?;
+    let stats = stats.get(client).await?;
+    let Some(owner) = stats.owner(owner_id) else {
+        return Ok(Badge::not_found("user", &query));
+    };
+    let pilot_ids: Vec<_> = stats
+        .pilots_of(owner_id)
+        .into_iter()
+        .map(|p| p.pilot_id)
+        .collect();
+    let last = last_outcome(&pilot_ids, client).await?;
+
+    let username = sso_client
+        .get_user(owner_id)
+        .await
+        .map(|info| info.username)
+        .unwrap_or_else(|| owner_id.to_string());
+    let pilots = match pilot_ids.len() {
+        1 => "1 pilot".to_string(),
+        n => format!("{} pilots", n),
+    };
+
+    // The user's best pilot, like the ranking on the users page
+    let (message, color) = message(
+        pilots,
+        (owner.record, last),
+        query.metric.unwrap_or_default(),
+        owner.best_rating.rating,
+    );
+    Ok((Status::Ok, Badge::new(username, message, color, &query)))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![pilot_badge, user_badge]
+}
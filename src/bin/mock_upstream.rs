@@ -0,0 +1,7 @@
+use aip_front::mock_upstream::{self, MockConfig};
+
+/// Runs the in-memory upstream on its own, point `AIP_API_BASE_URL` at it.
+#[rocket::launch]
+fn rocket() -> _ {
+    mock_upstream::rocket(MockConfig::from_env())
+}
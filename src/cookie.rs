@@ -1,7 +1,10 @@
 use okapi::openapi3::{Object, Parameter};
+use std::ops::Deref;
+
 use rocket::{
     Request, State,
     http::Status,
+    outcome::try_outcome,
     request::{FromRequest, Outcome},
 };
 use rocket_okapi::{r#gen::OpenApiGenerator, request::OpenApiFromRequest};
@@ -9,7 +12,8 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     SqliteClient,
-    model::{User, UserId},
+    model::{Role, User, UserId, UserToken, UserTokenStore},
+    scope::{Scope, Scopes},
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,33 +22,59 @@ pub struct ApiUser {
     pub discord_id: String,
     pub username: String,
     pub avatar: String,
+    /// Role at login time, only used for display. [`AdminUser`] checks the current role.
+    #[serde(default)]
+    pub role: Role,
+    /// Scopes of the token used to authenticate, `None` for browser sessions which may do anything
+    #[serde(skip)]
+    pub scopes: Option<Scopes>,
 }
 
-#[async_trait]
-impl<'r> FromRequest<'r> for ApiUser {
-    type Error = String;
+impl ApiUser {
+    pub fn has_scope(&self, scope: &Scope) -> bool {
+        self.scopes.as_ref().is_none_or(|s| s.grants(scope))
+    }
 
-    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+    async fn authenticate(request: &Request<'_>) -> Result<ApiUser, String> {
         if let Some(cookie) = request.cookies().get_private("auth") {
-            if let Ok(user) = serde_json::from_str(cookie.value()) {
-                return Outcome::Success(user);
-            } else {
-                return Outcome::Error((Status::Unauthorized, "Malformed auth cookie".to_string()));
-            }
+            return serde_json::from_str(cookie.value())
+                .map_err(|_| "Malformed auth cookie".to_string());
         } else if let Some(auth_token) = request.headers().get_one("x-auth-token") {
             if let Outcome::Success(client) = request.guard::<&State<SqliteClient>>().await {
-                if let Ok(user) = User::get_user_by_user_token(auth_token, client).await {
-                    return Outcome::Success(ApiUser {
+                if let Ok(token) = UserToken::get_by_secret(auth_token, client).await
+                    && let Ok(user) = User::get_by_id(token.user_id, client).await
+                {
+                    return Ok(ApiUser {
                         id: user.id,
                         discord_id: user.discord_id,
                         username: user.username,
                         avatar: user.avatar_url,
+                        role: user.role,
+                        scopes: Some(token.scopes),
                     });
                 }
             }
         }
 
-        return Outcome::Error((Status::Unauthorized, "Auth cookie missing".to_string()));
+        Err("Auth cookie missing".to_string())
+    }
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for ApiUser {
+    type Error = String;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        // Several guards of one request may ask for the user, only look the token up once
+        let cached = request
+            .local_cache_async(async { ApiUser::authenticate(request).await })
+            .await
+            .clone();
+
+        match cached {
+            Ok(user) => Outcome::Success(user),
+            Err(e) => Outcome::Error((Status::Unauthorized, e)),
+        }
     }
 }
 
@@ -77,3 +107,57 @@ impl<'a> OpenApiFromRequest<'a> for ApiUser {
         ))
     }
 }
+
+/// An [`ApiUser`] who currently has the admin role. Tokens also need the `admin` scope.
+pub struct AdminUser(pub ApiUser);
+
+impl Deref for AdminUser {
+    type Target = ApiUser;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for AdminUser {
+    type Error = String;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let user = try_outcome!(request.guard::<ApiUser>().await);
+
+        if !user.has_scope(&Scope::Admin) {
+            return Outcome::Error((
+                Status::Forbidden,
+                "Token is missing the admin scope".to_string(),
+            ));
+        }
+
+        let Outcome::Success(client) = request.guard::<&State<SqliteClient>>().await else {
+            return Outcome::Error((Status::InternalServerError, "Missing database".to_string()));
+        };
+
+        // The role in the session cookie may be stale, so always ask the database
+        match User::get_by_id(user.id, client).await {
+            Ok(u) if u.role == Role::Admin => Outcome::Success(AdminUser(user)),
+            Ok(_) => Outcome::Error((Status::Forbidden, "Admins only".to_string())),
+            Err(e) => {
+                log::error!("Failed to fetch user: {}", e);
+                Outcome::Error((
+                    Status::InternalServerError,
+                    "Failed to fetch user".to_string(),
+                ))
+            }
+        }
+    }
+}
+
+impl<'a> OpenApiFromRequest<'a> for AdminUser {
+    fn from_request_input(
+        gene: &mut OpenApiGenerator,
+        name: String,
+        required: bool,
+    ) -> rocket_okapi::Result<rocket_okapi::request::RequestHeaderInput> {
+        ApiUser::from_request_input(gene, name, required)
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    collections::HashMap,
+    env,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use client::models::{AiPilot, MatchResult, match_result::Winner};
+use rocket::tokio::{sync::broadcast, time::sleep};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{SqliteClient, api_client::ApiClient, mirror, stats::StatsCache, webhook};
+
+/// How long a queued fight is polled for before it's assumed lost.
+const PENDING_TTL: chrono::TimeDelta = chrono::TimeDelta::hours(1);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchCreated {
+    pub match_id: String,
+    pub pilot_a: String,
+    pub pilot_b: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchFinished {
+    #[serde(flatten)]
+    pub match_result: MatchResult,
+    pub team_a_name: String,
+    pub team_b_name: String,
+    pub winner_name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PilotUploaded {
+    pub pilot_id: Uuid,
+    pub name: String,
+    pub version: i32,
+}
+
+/// Pushed to browsers over `/events`, the variant is sent as the SSE event name.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum LiveEvent {
+    MatchCreated(MatchCreated),
+    MatchFinished(MatchFinished),
+    PilotUploaded(PilotUploaded),
+}
+
+impl LiveEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LiveEvent::MatchCreated(_) => "match_created",
+            LiveEvent::MatchFinished(_) => "match_finished",
+            LiveEvent::PilotUploaded(_) => "pilot_uploaded",
+        }
+    }
+}
+
+/// Fans live events out to every `/events` subscriber. Fights queued through this app
+/// are polled upstream until their result shows up, instead of waiting for the next sync.
+#[derive(Debug, Clone)]
+pub struct Broadcaster {
+    sender: broadcast::Sender<LiveEvent>,
+    /// Queued match ids and when they were queued
+    pending: Arc<Mutex<HashMap<String, DateTime<Utc>>>>,
+}
+
+impl Default for Broadcaster {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Broadcaster {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(256);
+        Broadcaster {
+            sender,
+            pending: Arc::default(),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveEvent> {
+        self.sender.subscribe()
+    }
+
+    fn send(&self, event: LiveEvent) {
+        // Only fails when nobody is listening
+        let _ = self.sender.send(event);
+    }
+
+    pub fn is_pending(&self, match_id: &str) -> bool {
+        self.pending.lock().unwrap().contains_key(match_id)
+    }
+
+    /// Announces a queued fight and watches upstream for its result.
+    pub fn match_created(&self, match_id: &str, pilot_a: String, pilot_b: String) {
+        self.pending
+            .lock()
+            .unwrap()
+            .insert(match_id.to_string(), Utc::now());
+
+        self.send(LiveEvent::MatchCreated(MatchCreated {
+            match_id: match_id.to_string(),
+            pilot_a,
+            pilot_b,
+        }));
+    }
+
+    /// Announces freshly mirrored matches.
+    pub async fn matches_finished(&self, matches: &[MatchResult], client: &SqliteClient) {
+        if matches.is_empty() {
+            return;
+        }
+
+        let names = mirror::get_pilot_names(client).await.unwrap_or_default();
+        let name_of = |id: &Uuid| names.get(id).cloned().unwrap_or_else(|| id.to_string());
+
+        let mut pending = self.pending.lock().unwrap();
+        for m in matches {
+            pending.remove(&m.id.to_string());
+
+            let team_a_name = name_of(&m.team_a.aip_id);
+            let team_b_name = name_of(&m.team_b.aip_id);
+            let winner_name = match m.winner {
+                Winner::TeamA => team_a_name.clone(),
+                Winner::TeamB => team_b_name.clone(),
+                Winner::Unknown => "Unknown".to_string(),
+            };
+
+            self.send(LiveEvent::MatchFinished(MatchFinished {
+                match_result: m.clone(),
+                team_a_name,
+                team_b_name,
+                winner_name,
+            }));
+        }
+    }
+
+    /// Announces freshly mirrored pilot versions.
+    pub fn versions_uploaded(&self, versions: &[(AiPilot, i32)]) {
+        for (pilot, version) in versions {
+            self.send(LiveEvent::PilotUploaded(PilotUploaded {
+                pilot_id: pilot.id,
+                name: pilot.name.clone(),
+                version: *version,
+            }));
+        }
+    }
+
+    /// Polls upstream for the results of queued fights every `EVENTS_POLL_INTERVAL_SECS`.
+    pub async fn run(
+        self,
+        api_client: Arc<dyn ApiClient>,
+        client: SqliteClient,
+        stats: StatsCache,
+    ) {
+        let interval = env::var("EVENTS_POLL_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(Duration::from_secs(3));
+
+        loop {
+            sleep(interval).await;
+
+            let ids: Vec<_> = {
+                let mut pending = self.pending.lock().unwrap();
+                let now = Utc::now();
+                pending.retain(|_, queued_at| now - *queued_at < PENDING_TTL);
+                pending.keys().cloned().collect()
+            };
+
+            for id in ids {
+                let Ok(Some(m)) = api_client.get_match(&id).await else {
+                    continue;
+                };
+
+                let baseline = webhook::rating_baseline(&stats, &client).await;
+                match mirror::upsert_matches(std::slice::from_ref(&m), &client).await {
+                    Ok(new_matches) => {
+                        webhook::dispatch_new_matches(&new_matches, baseline, &stats, &client)
+                            .await;
+                        self.matches_finished(&new_matches, &client).await;
+                    }
+                    Err(e) => log::error!("Failed to store match {}: {}", id, e),
+                }
+                // Already announced by whoever mirrored it first
+                self.pending.lock().unwrap().remove(&id);
+            }
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+
+use client::models::{MatchResult, match_result::Winner};
+use okapi::openapi3::{MediaType, RefOr, Responses};
+use rocket::{
+    Request, Response,
+    http::ContentType,
+    response::{self, Responder, stream::TextStream},
+};
+use rocket_okapi::{r#gen::OpenApiGenerator, response::OpenApiResponderInner};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    SqliteClient,
+    mirror::{self, MatchCursor, MatchFilter},
+    util::replay_url,
+};
+
+// Full match history as CSV or JSON Lines, streamed a page at a time from the mirror.
+
+/// Matches read from the database per chunk of the response.
+const PAGE_SIZE: u32 = 500;
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, FromFormField,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    fn content_type(self) -> ContentType {
+        match self {
+            ExportFormat::Csv => ContentType::CSV,
+            ExportFormat::Ndjson => ContentType::new("application", "x-ndjson"),
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+
+    /// Printed before the first row.
+    fn header(self) -> String {
+        match self {
+            ExportFormat::Csv => format!("{}\r\n", ExportRow::COLUMNS.join(",")),
+            ExportFormat::Ndjson => String::new(),
+        }
+    }
+
+    fn write_row(self, row: &ExportRow, out: &mut String) {
+        match self {
+            ExportFormat::Csv => {
+                let fields = [
+                    row.id.to_string(),
+                    row.created_at.clone(),
+                    row.pilot_a.clone(),
+                    row.pilot_a_id.to_string(),
+                    row.pilot_a_version.to_string(),
+                    row.pilot_b.clone(),
+                    row.pilot_b_id.to_string(),
+                    row.pilot_b_version.to_string(),
+                    row.winner.clone().unwrap_or_default(),
+                    row.manual.to_string(),
+                    row.replay_url.clone().unwrap_or_default(),
+                ];
+                let fields: Vec<_> = fields.iter().map(|f| csv_field(f)).collect();
+                out.push_str(&fields.join(","));
+                out.push_str("\r\n");
+            }
+            ExportFormat::Ndjson => {
+                out.push_str(&serde_json::to_string(row).expect("Rows are always serializable"));
+                out.push('\n');
+            }
+        }
+    }
+}
+
+/// Quotes a field containing a separator, quote or line break, as RFC 4180 asks.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// One exported match, flat so it maps onto a table as is.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ExportRow {
+    pub id: Uuid,
+    /// RFC 3339
+    pub created_at: String,
+    pub pilot_a: String,
+    pub pilot_a_id: Uuid,
+    pub pilot_a_version: i32,
+    pub pilot_b: String,
+    pub pilot_b_id: Uuid,
+    pub pilot_b_version: i32,
+    /// Name of the winning pilot, unset when the result is unknown
+    pub winner: Option<String>,
+    pub manual: bool,
+    pub replay_url: Option<String>,
+}
+
+impl ExportRow {
+    const COLUMNS: [&str; 11] = [
+        "id",
+        "created_at",
+        "pilot_a",
+        "pilot_a_id",
+        "pilot_a_version",
+        "pilot_b",
+        "pilot_b_id",
+        "pilot_b_version",
+        "winner",
+        "manual",
+        "replay_url",
+    ];
+
+    pub fn new(m: &MatchResult, names: &HashMap<Uuid, String>, api_base_url: &str) -> Self {
+        // Pilots deleted upstream keep their id in place of a name
+        let name = |id: &Uuid| names.get(id).cloned().unwrap_or_else(|| id.to_string());
+        let pilot_a = name(&m.team_a.aip_id);
+        let pilot_b = name(&m.team_b.aip_id);
+
+        ExportRow {
+            id: m.id,
+            created_at: chrono::DateTime::from_timestamp_millis(m.created_at)
+                .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+                .unwrap_or_default(),
+            winner: match m.winner {
+                Winner::TeamA => Some(pilot_a.clone()),
+                Winner::TeamB => Some(pilot_b.clone()),
+                Winner::Unknown => None,
+            },
+            pilot_a,
+            pilot_a_id: m.team_a.aip_id,
+            pilot_a_version: m.team_a.version,
+            pilot_b,
+            pilot_b_id: m.team_b.aip_id,
+            pilot_b_version: m.team_b.version,
+            manual: m.manual_run,
+            replay_url: replay_url(api_base_url, m.replay_id.as_deref()),
+        }
+    }
+}
+
+/// Streams every match passing `filter` as a file download.
+///
+/// Filters are resolved up front so bad parameters still get a proper error status,
+/// a database error halfway through can only cut the response short.
+pub struct MatchExport {
+    pub filter: MatchFilter,
+    pub cursor: Option<MatchCursor>,
+    pub format: ExportFormat,
+    /// Download name, without extension
+    pub file_name: String,
+    pub names: HashMap<Uuid, String>,
+    pub api_base_url: String,
+    pub client: SqliteClient,
+}
+
+impl<'r> Responder<'r, 'r> for MatchExport {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'r> {
+        let MatchExport {
+            filter,
+            mut cursor,
+            format,
+            file_name,
+            names,
+            api_base_url,
+            client,
+        } = self;
+        let disposition = format!(
+            "attachment; filename=\"{}.{}\"",
+            file_name,
+            format.extension()
+        );
+
+        let stream = TextStream! {
+            yield format.header();
+            loop {
+                let Ok((matches, next)) =
+                    mirror::get_matches_page(&filter, cursor.as_ref(), PAGE_SIZE, &client).await
+                else {
+                    break;
+                };
+
+                let mut chunk = String::new();
+                for m in &matches {
+                    format.write_row(&ExportRow::new(m, &names, &api_base_url), &mut chunk);
+                }
+                yield chunk;
+
+                match next {
+                    Some(next) => cursor = Some(next),
+                    None => break,
+                }
+            }
+        };
+
+        Response::build_from(stream.respond_to(req)?)
+            .header(format.content_type())
+            .raw_header("Content-Disposition", disposition)
+            .ok()
+    }
+}
+
+impl OpenApiResponderInner for MatchExport {
+    fn responses(_gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        let content = [ExportFormat::Csv, ExportFormat::Ndjson]
+            .into_iter()
+            .map(|f| (f.content_type().to_string(), MediaType::default()))
+            .collect();
+
+        let mut responses = Responses::default();
+        responses.responses.insert(
+            "200".to_string(),
+            RefOr::Object(okapi::openapi3::Response {
+                description: "Match history, one match per row".to_string(),
+                content,
+                ..Default::default()
+            }),
+        );
+        Ok(responses)
+    }
+}
@@ -0,0 +1,315 @@
+use std::{cmp::Reverse, collections::HashMap, env};
+
+use chrono::{DateTime, Utc};
+use client::models::{AiPilot, MatchResult, match_result::Winner};
+use rocket::{
+    Orbit, Request, Response, Rocket, Route, State,
+    http::{ContentType, Header, Status},
+    request::{FromRequest, Outcome},
+    response::{self, Responder},
+};
+use rocket_dyn_templates::{Template, context};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    SqliteClient,
+    api_error::ApiErrors,
+    mirror::{self, MatchFilter, SeenVersion},
+    sso_client::SSOClient,
+};
+
+// Atom feeds of new matches and pilot versions, for following pilots in a feed reader.
+// Feed readers poll, so responses carry an ETag and Last-Modified for conditional GET.
+
+/// Entries per feed.
+const FEED_LENGTH: u32 = 50;
+
+/// Absolute URL of this site, `BASE_URL` or else the request's host.
+struct SiteUrl(String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SiteUrl {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let url = env::var("BASE_URL").unwrap_or_else(|_| {
+            req.host()
+                .map(|host| format!("http://{}", host))
+                .unwrap_or_default()
+        });
+        Outcome::Success(SiteUrl(url.trim_end_matches('/').to_string()))
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Entry {
+    id: String,
+    title: String,
+    link: String,
+    summary: String,
+    #[serde(skip)]
+    updated_at: DateTime<Utc>,
+    /// RFC 3339
+    updated: String,
+}
+
+fn match_entry(m: &MatchResult, names: &HashMap<Uuid, String>, site: &str) -> Entry {
+    let name = |id: &Uuid| names.get(id).cloned().unwrap_or_else(|| id.to_string());
+    let a = format!("{} v{}", name(&m.team_a.aip_id), m.team_a.version);
+    let b = format!("{} v{}", name(&m.team_b.aip_id), m.team_b.version);
+    let title = match m.winner {
+        Winner::TeamA => format!("{} beat {}", a, b),
+        Winner::TeamB => format!("{} beat {}", b, a),
+        Winner::Unknown => format!("{} vs {} ended without a winner", a, b),
+    };
+    let kind = if m.manual_run { "Manual" } else { "Scheduled" };
+    let updated_at = DateTime::from_timestamp_millis(m.created_at).unwrap_or_default();
+
+    Entry {
+        id: format!("urn:uuid:{}", m.id),
+        title,
+        link: format!("{}/match/{}", site, m.id),
+        summary: format!("{} match between {} and {}", kind, a, b),
+        updated: updated_at.to_rfc3339(),
+        updated_at,
+    }
+}
+
+fn version_entry(v: &SeenVersion, names: &HashMap<Uuid, String>, site: &str) -> Entry {
+    let name = names
+        .get(&Uuid::parse_str(&v.pilot_id).unwrap_or_default())
+        .cloned()
+        .unwrap_or_else(|| v.pilot_id.clone());
+    let link = format!("{}/pilot/{}/version/{}", site, name, v.version);
+    let updated_at = DateTime::from_timestamp_millis(v.seen_at).unwrap_or_default();
+
+    Entry {
+        // Upload ids are unique per version, the link stands in for odd ones
+        id: match Uuid::parse_str(&v.upload_id) {
+            Ok(upload_id) => format!("urn:uuid:{}", upload_id),
+            Err(_) => link.clone(),
+        },
+        title: format!("{} v{} uploaded", name, v.version),
+        link,
+        summary: format!("Version {} of {} is ready to fight", v.version, name),
+        updated: updated_at.to_rfc3339(),
+        updated_at,
+    }
+}
+
+/// A feed, or 304 Not Modified when the client's copy is still current.
+pub struct AtomFeed {
+    title: String,
+    self_link: String,
+    alternate: String,
+    entries: Vec<Entry>,
+    updated_at: DateTime<Utc>,
+}
+
+impl AtomFeed {
+    /// Keeps the newest `FEED_LENGTH` of `entries`. Paths are relative to `site`.
+    fn new(
+        title: String,
+        path: &str,
+        alternate: &str,
+        site: &str,
+        mut entries: Vec<Entry>,
+    ) -> Self {
+        entries.sort_by_key(|e| Reverse(e.updated_at));
+        entries.truncate(FEED_LENGTH as usize);
+
+        AtomFeed {
+            title,
+            self_link: format!("{}{}", site, path),
+            alternate: format!("{}{}", site, alternate),
+            // An empty feed still needs an updated time, stay at the epoch until something happens
+            updated_at: entries.first().map(|e| e.updated_at).unwrap_or_default(),
+            entries,
+        }
+    }
+
+    fn render(self, rocket: &Rocket<Orbit>) -> Option<String> {
+        Template::show(
+            rocket,
+            "feed",
+            context! {
+                id: &self.self_link,
+                title: self.title,
+                self_link: &self.self_link,
+                alternate: self.alternate,
+                updated: self.updated_at.to_rfc3339(),
+                entries: self.entries,
+            },
+        )
+    }
+}
+
+fn etag(body: &str) -> String {
+    let digest = Sha256::digest(body.as_bytes());
+    format!("\"{}\"", hex::encode(&digest[..16]))
+}
+
+fn is_fresh(req: &Request<'_>, etag: &str, updated_at: DateTime<Utc>) -> bool {
+    // If-None-Match wins over If-Modified-Since when both are sent
+    if let Some(tags) = req.headers().get_one("If-None-Match") {
+        return tags
+            .split(',')
+            .map(|t| t.trim().trim_start_matches("W/"))
+            .any(|t| t == etag || t == "*");
+    }
+
+    req.headers()
+        .get_one("If-Modified-Since")
+        .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+        .is_some_and(|since| updated_at.timestamp() <= since.timestamp())
+}
+
+impl<'r> Responder<'r, 'static> for AtomFeed {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let updated_at = self.updated_at;
+        let Some(body) = self.render(req.rocket()) else {
+            log::error!("Failed to render feed {}", req.uri());
+            return Err(Status::InternalServerError);
+        };
+
+        let etag = etag(&body);
+        let mut response = Response::build();
+        response
+            .raw_header("ETag", etag.clone())
+            .raw_header(
+                "Last-Modified",
+                updated_at.format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+            )
+            // New matches are only mirrored every 30 seconds or so
+            .header(Header::new("Cache-Control", "public, max-age=60"));
+
+        if is_fresh(req, &etag, updated_at) {
+            return response.status(Status::NotModified).ok();
+        }
+
+        response
+            .header(ContentType::new("application", "atom+xml"))
+            .sized_body(body.len(), std::io::Cursor::new(body))
+            .ok()
+    }
+}
+
+#[get("/matches.atom")]
+async fn matches_feed(site: SiteUrl, client: &State<SqliteClient>) -> Result<AtomFeed, ApiErrors> {
+    let names = mirror::get_pilot_names(client).await?;
+    let (matches, _) =
+        mirror::get_matches_page(&MatchFilter::default(), None, FEED_LENGTH, client).await?;
+    let versions = mirror::get_recent_versions(None, FEED_LENGTH, client).await?;
+
+    let entries = matches
+        .iter()
+        .map(|m| match_entry(m, &names, &site.0))
+        .chain(versions.iter().map(|v| version_entry(v, &names, &site.0)))
+        .collect();
+
+    Ok(AtomFeed::new(
+        "AI Pilot matches".into(),
+        "/feeds/matches.atom",
+        "/matches",
+        &site.0,
+        entries,
+    ))
+}
+
+/// Feeds are named `<name>.atom`, Rocket can't match part of a segment.
+fn strip_atom(file: &str) -> Result<&str, ApiErrors> {
+    file.strip_suffix(".atom")
+        .ok_or_else(|| ApiErrors::NotFound("Feed not found".into()))
+}
+
+#[get("/pilot/<file>")]
+async fn pilot_feed(
+    file: &str,
+    site: SiteUrl,
+    client: &State<SqliteClient>,
+) -> Result<AtomFeed, ApiErrors> {
+    let pilot = mirror::get_pilot_by_name(strip_atom(file)?, client)
+        .await?
+        .ok_or_else(|| ApiErrors::NotFound("Pilot not found".into()))?;
+
+    let names = mirror::get_pilot_names(client).await?;
+    let entries = pilot_entries(&pilot, &names, &site.0, client).await?;
+
+    Ok(AtomFeed::new(
+        format!("{} on AI Pilot", pilot.name),
+        &format!("/feeds/pilot/{}.atom", pilot.name),
+        &format!("/pilot/{}", pilot.name),
+        &site.0,
+        entries,
+    ))
+}
+
+#[get("/user/<file>")]
+async fn user_feed(
+    file: &str,
+    site: SiteUrl,
+    sso_client: &State<SSOClient>,
+    client: &State<SqliteClient>,
+) -> Result<AtomFeed, ApiErrors> {
+    let owner_id = strip_atom(file)?;
+    let pilots: Vec<_> = mirror::get_pilots(client)
+        .await?
+        .into_iter()
+        .filter(|p| p.owner_id == owner_id)
+        .collect();
+    if pilots.is_empty() {
+        return Err(ApiErrors::NotFound(
+            "User not found or has no pilots".into(),
+        ));
+    }
+
+    let names = mirror::get_pilot_names(client).await?;
+    let mut entries = Vec::new();
+    for pilot in &pilots {
+        entries.extend(pilot_entries(pilot, &names, &site.0, client).await?);
+    }
+    // Matches between two of the user's own pilots show up once per pilot
+    entries.sort_by(|a, b| a.id.cmp(&b.id));
+    entries.dedup_by(|a, b| a.id == b.id);
+
+    let username = sso_client
+        .get_user(owner_id)
+        .await
+        .map(|info| info.username)
+        .unwrap_or_else(|| owner_id.to_string());
+
+    Ok(AtomFeed::new(
+        format!("{}'s pilots on AI Pilot", username),
+        &format!("/feeds/user/{}.atom", owner_id),
+        &format!("/user/{}", owner_id),
+        &site.0,
+        entries,
+    ))
+}
+
+/// Latest matches and versions of one pilot.
+async fn pilot_entries(
+    pilot: &AiPilot,
+    names: &HashMap<Uuid, String>,
+    site: &str,
+    client: &SqliteClient,
+) -> Result<Vec<Entry>, ApiErrors> {
+    let filter = MatchFilter {
+        pilot_id: Some(pilot.id),
+        ..Default::default()
+    };
+    let (matches, _) = mirror::get_matches_page(&filter, None, FEED_LENGTH, client).await?;
+    let versions = mirror::get_recent_versions(Some(&pilot.id), FEED_LENGTH, client).await?;
+
+    Ok(matches
+        .iter()
+        .map(|m| match_entry(m, names, site))
+        .chain(versions.iter().map(|v| version_entry(v, names, site)))
+        .collect())
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![matches_feed, pilot_feed, user_feed]
+}
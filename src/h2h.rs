@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+use client::models::{AiPilot, MatchResult, match_result::Winner};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+// Head-to-head record between two pilots, shared by `/api/h2h` and the compare page.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    A,
+    B,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct H2hPilot {
+    pub id: Uuid,
+    pub name: String,
+    pub current_version: i32,
+    pub wins: usize,
+    /// Consecutive wins in the most recent meetings, 0 unless this pilot won the last one
+    pub streak: usize,
+}
+
+/// Results of one version of A against one version of B.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionMatchup {
+    pub version_a: i32,
+    pub version_b: i32,
+    pub a_wins: usize,
+    pub b_wins: usize,
+    pub undecided: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Meeting {
+    pub match_id: Uuid,
+    /// Milliseconds since epoch
+    pub created_at: i64,
+    pub version_a: i32,
+    pub version_b: i32,
+    /// Missing if the match had no winner
+    pub winner: Option<Side>,
+    pub manual_run: bool,
+    pub replay_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HeadToHead {
+    pub pilot_a: H2hPilot,
+    pub pilot_b: H2hPilot,
+    pub total: usize,
+    pub undecided: usize,
+    /// Versions of A and B that met, newest first
+    pub versions_a: Vec<i32>,
+    pub versions_b: Vec<i32>,
+    /// Every version pairing that met at least once
+    pub matchups: Vec<VersionMatchup>,
+    /// Newest first
+    pub meetings: Vec<Meeting>,
+}
+
+impl HeadToHead {
+    /// Builds the record from `matches`, which may include matches against other pilots.
+    pub fn new(pilot_a: &AiPilot, pilot_b: &AiPilot, matches: &[MatchResult]) -> Self {
+        let mut meetings: Vec<_> = matches
+            .iter()
+            .filter_map(|m| {
+                let (version_a, version_b, a_side) =
+                    if m.team_a.aip_id == pilot_a.id && m.team_b.aip_id == pilot_b.id {
+                        (m.team_a.version, m.team_b.version, Winner::TeamA)
+                    } else if m.team_b.aip_id == pilot_a.id && m.team_a.aip_id == pilot_b.id {
+                        (m.team_b.version, m.team_a.version, Winner::TeamB)
+                    } else {
+                        return None;
+                    };
+
+                let winner = match m.winner {
+                    Winner::Unknown => None,
+                    w if w == a_side => Some(Side::A),
+                    _ => Some(Side::B),
+                };
+
+                Some(Meeting {
+                    match_id: m.id,
+                    created_at: m.created_at,
+                    version_a,
+                    version_b,
+                    winner,
+                    manual_run: m.manual_run,
+                    replay_id: m.replay_id.clone().filter(|r| !r.trim().is_empty()),
+                })
+            })
+            .collect();
+        meetings.sort_by_key(|m| std::cmp::Reverse(m.created_at));
+
+        let mut matchups: BTreeMap<(i32, i32), VersionMatchup> = BTreeMap::new();
+        for m in &meetings {
+            let cell = matchups
+                .entry((m.version_a, m.version_b))
+                .or_insert_with(|| VersionMatchup {
+                    version_a: m.version_a,
+                    version_b: m.version_b,
+                    ..Default::default()
+                });
+            match m.winner {
+                Some(Side::A) => cell.a_wins += 1,
+                Some(Side::B) => cell.b_wins += 1,
+                None => cell.undecided += 1,
+            }
+        }
+
+        let mut versions_a: Vec<_> = meetings.iter().map(|m| m.version_a).collect();
+        versions_a.sort_by(|a, b| b.cmp(a));
+        versions_a.dedup();
+        let mut versions_b: Vec<_> = meetings.iter().map(|m| m.version_b).collect();
+        versions_b.sort_by(|a, b| b.cmp(a));
+        versions_b.dedup();
+
+        let wins = |side| meetings.iter().filter(|m| m.winner == Some(side)).count();
+        let streak = |side| {
+            meetings
+                .iter()
+                .take_while(|m| m.winner == Some(side))
+                .count()
+        };
+
+        HeadToHead {
+            pilot_a: H2hPilot {
+                id: pilot_a.id,
+                name: pilot_a.name.clone(),
+                current_version: pilot_a.current.version,
+                wins: wins(Side::A),
+                streak: streak(Side::A),
+            },
+            pilot_b: H2hPilot {
+                id: pilot_b.id,
+                name: pilot_b.name.clone(),
+                current_version: pilot_b.current.version,
+                wins: wins(Side::B),
+                streak: streak(Side::B),
+            },
+            total: meetings.len(),
+            undecided: meetings.iter().filter(|m| m.winner.is_none()).count(),
+            versions_a,
+            versions_b,
+            matchups: matchups.into_values().collect(),
+            meetings,
+        }
+    }
+
+    pub fn matchup(&self, version_a: i32, version_b: i32) -> Option<&VersionMatchup> {
+        self.matchups
+            .iter()
+            .find(|m| m.version_a == version_a && m.version_b == version_b)
+    }
+}
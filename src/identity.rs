@@ -0,0 +1,256 @@
+use std::{env, sync::Arc};
+
+use log::error;
+use serde::Deserialize;
+
+use crate::{metrics, sso_client::DiscordUserInfo};
+
+/// Signs users in with their Discord identity and looks up other users' profiles.
+///
+/// `state` holds the login nonce and the page to return to after signing in, already
+/// encoded as a single path segment. It must come back unchanged to
+/// `/login_callback/<state>?code=` or `/login_callback?code=&state=<state>`.
+#[async_trait]
+pub trait IdentityProvider: Send + Sync {
+    /// Where to send the browser to sign in.
+    fn login_url(&self, state: &str) -> String;
+
+    /// Resolves the `code` the provider sent back to the callback.
+    async fn exchange_code(&self, code: &str) -> Option<DiscordUserInfo>;
+
+    /// Profile of any user by Discord id, used to show pilot owners.
+    async fn lookup_user(&self, discord_id: &str) -> Option<DiscordUserInfo>;
+}
+
+/// Picks the provider named by `IDENTITY_PROVIDER`: `isan` (default), `discord` or `dev`.
+pub fn from_env() -> Arc<dyn IdentityProvider> {
+    let own_base_url = env::var("BASE_URL").expect("BASE_URL must be set");
+
+    match env::var("IDENTITY_PROVIDER").as_deref() {
+        Ok("isan") | Err(_) => Arc::new(IsanSso::new(own_base_url)),
+        Ok("discord") => Arc::new(DiscordOAuth::new(own_base_url)),
+        Ok("dev") => {
+            log::warn!("Signing everyone in as the dev user, never use this in production");
+            Arc::new(DevIdentity::new())
+        }
+        Ok(other) => panic!("Unknown IDENTITY_PROVIDER {}", other),
+    }
+}
+
+/// The isan.to SSO, which wraps Discord OAuth and can look up any user.
+pub struct IsanSso {
+    client: reqwest::Client,
+    /// `SSO_BASE_URL`, https://sso.isan.to unless self hosted
+    base_url: String,
+    own_base_url: String,
+}
+
+impl IsanSso {
+    pub fn new(own_base_url: String) -> Self {
+        IsanSso {
+            client: reqwest::Client::new(),
+            base_url: env::var("SSO_BASE_URL").unwrap_or_else(|_| "https://sso.isan.to".into()),
+            own_base_url,
+        }
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for IsanSso {
+    fn login_url(&self, state: &str) -> String {
+        let callback = format!("{}/login_callback/{}", self.own_base_url, state);
+        format!("{}/login?service={}", self.base_url, callback)
+    }
+
+    async fn exchange_code(&self, code: &str) -> Option<DiscordUserInfo> {
+        let res = metrics::sso(
+            "getuser",
+            self.client
+                .get(format!("{}/getuser/{}", self.base_url, code))
+                .send(),
+        )
+        .await;
+
+        let res = match res {
+            Ok(response) => response,
+            Err(e) => {
+                error!("Failed to get user data: {}", e);
+                return None;
+            }
+        };
+
+        match res.json::<DiscordUserInfo>().await {
+            Ok(user_info) => Some(user_info),
+            Err(e) => {
+                error!("Failed to parse user data: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn lookup_user(&self, discord_id: &str) -> Option<DiscordUserInfo> {
+        metrics::sso("uinfo", async {
+            self.client
+                .get(format!("{}/uinfo/{}", self.base_url, discord_id))
+                .send()
+                .await?
+                .json::<DiscordUserInfo>()
+                .await
+        })
+        .await
+        .ok()
+    }
+}
+
+/// Discord OAuth2 without a middleman. Other users can only be looked up with a bot token.
+pub struct DiscordOAuth {
+    client: reqwest::Client,
+    client_id: String,
+    client_secret: String,
+    /// `DISCORD_BOT_TOKEN`, optional
+    bot_token: Option<String>,
+    /// Must be registered as a redirect of the Discord application
+    redirect_uri: String,
+}
+
+const DISCORD_API: &str = "https://discord.com/api/v10";
+
+/// The parts of a Discord user object we use.
+#[derive(Deserialize)]
+struct DiscordUser {
+    id: String,
+    username: String,
+    avatar: Option<String>,
+}
+
+impl From<DiscordUser> for DiscordUserInfo {
+    fn from(user: DiscordUser) -> Self {
+        DiscordUserInfo {
+            id: user.id,
+            username: user.username,
+            avatar: user.avatar.unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct DiscordToken {
+    access_token: String,
+}
+
+impl DiscordOAuth {
+    pub fn new(own_base_url: String) -> Self {
+        DiscordOAuth {
+            client: reqwest::Client::new(),
+            client_id: env::var("DISCORD_CLIENT_ID").expect("DISCORD_CLIENT_ID must be set"),
+            client_secret: env::var("DISCORD_CLIENT_SECRET")
+                .expect("DISCORD_CLIENT_SECRET must be set"),
+            bot_token: env::var("DISCORD_BOT_TOKEN").ok(),
+            redirect_uri: format!("{}/login_callback", own_base_url),
+        }
+    }
+
+    async fn fetch_token(&self, code: &str) -> Result<DiscordToken, reqwest::Error> {
+        self.client
+            .post(format!("{}/oauth2/token", DISCORD_API))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.redirect_uri),
+                ("client_id", &self.client_id),
+                ("client_secret", &self.client_secret),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    async fn fetch_user(&self, path: &str, auth: &str) -> Result<DiscordUser, reqwest::Error> {
+        self.client
+            .get(format!("{}/users/{}", DISCORD_API, path))
+            .header("Authorization", auth)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for DiscordOAuth {
+    fn login_url(&self, state: &str) -> String {
+        // Discord only redirects to exact registered URIs, so the state travels as `state`
+        format!(
+            "https://discord.com/oauth2/authorize?response_type=code&scope=identify&client_id={}&redirect_uri={}&state={}",
+            self.client_id,
+            urlencoding::encode(&self.redirect_uri),
+            urlencoding::encode(state)
+        )
+    }
+
+    async fn exchange_code(&self, code: &str) -> Option<DiscordUserInfo> {
+        let result = metrics::sso("discord_token", async {
+            let token = self.fetch_token(code).await?;
+            let auth = format!("Bearer {}", token.access_token);
+            self.fetch_user("@me", &auth).await
+        })
+        .await;
+
+        match result {
+            Ok(user) => Some(user.into()),
+            Err(e) => {
+                error!("Failed to exchange Discord OAuth code: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn lookup_user(&self, discord_id: &str) -> Option<DiscordUserInfo> {
+        let auth = format!("Bot {}", self.bot_token.as_ref()?);
+        metrics::sso("discord_user", self.fetch_user(discord_id, &auth))
+            .await
+            .map(Into::into)
+            .ok()
+    }
+}
+
+/// Signs everyone in as one configured user without leaving the site, for local development.
+pub struct DevIdentity {
+    user: DiscordUserInfo,
+}
+
+impl DevIdentity {
+    pub fn new() -> Self {
+        DevIdentity {
+            user: DiscordUserInfo {
+                id: env::var("DEV_USER_ID").unwrap_or_else(|_| "1".into()),
+                username: env::var("DEV_USER_NAME").unwrap_or_else(|_| "dev".into()),
+                avatar: env::var("DEV_USER_AVATAR").unwrap_or_default(),
+            },
+        }
+    }
+}
+
+impl Default for DevIdentity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for DevIdentity {
+    fn login_url(&self, state: &str) -> String {
+        format!("/login_callback/{}?code=dev", state)
+    }
+
+    async fn exchange_code(&self, _code: &str) -> Option<DiscordUserInfo> {
+        Some(self.user.clone())
+    }
+
+    async fn lookup_user(&self, discord_id: &str) -> Option<DiscordUserInfo> {
+        (discord_id == self.user.id).then(|| self.user.clone())
+    }
+}
@@ -0,0 +1,315 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use client::models::{AiPilot, MatchResult};
+use moka::future::Cache;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    SqliteClient,
+    api_error::ApiErrors,
+    metrics, mirror,
+    rating::{Rating, Ratings},
+    stats::{self, Record},
+};
+
+// Pilot rankings over a time window, shared by `/api/leaderboard` and the leaderboard page.
+// Records and ratings per window are cached in `LeaderboardCache`, sorting and the
+// minimum number of games are applied per request.
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    FromFormField,
+)]
+pub enum LeaderboardWindow {
+    #[default]
+    #[serde(rename = "all")]
+    All,
+    #[serde(rename = "7d")]
+    #[field(value = "7d")]
+    Week,
+    #[serde(rename = "30d")]
+    #[field(value = "30d")]
+    Month,
+    /// Only matches played by each pilot's current version
+    #[serde(rename = "current")]
+    Current,
+}
+
+impl LeaderboardWindow {
+    /// Millisecond bounds of the window and of the one it is compared with. All time and
+    /// current version standings are compared with how they looked a week ago.
+    fn ranges(self, now: i64) -> ((i64, i64), (i64, i64)) {
+        match self {
+            LeaderboardWindow::All | LeaderboardWindow::Current => {
+                ((i64::MIN, i64::MAX), (i64::MIN, now - 7 * DAY_MS))
+            }
+            LeaderboardWindow::Week => (
+                (now - 7 * DAY_MS, i64::MAX),
+                (now - 14 * DAY_MS, now - 7 * DAY_MS),
+            ),
+            LeaderboardWindow::Month => (
+                (now - 30 * DAY_MS, i64::MAX),
+                (now - 60 * DAY_MS, now - 30 * DAY_MS),
+            ),
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, FromFormField,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaderboardSort {
+    #[default]
+    #[field(value = "win_rate")]
+    WinRate,
+    /// Conservative Glicko-2 rating, so pilots with few games don't top the list
+    Rating,
+}
+
+/// Query parameters shared by `/api/leaderboard` and the `/leaderboard` page.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, FromForm)]
+pub struct LeaderboardQuery {
+    pub window: Option<LeaderboardWindow>,
+    pub sort: Option<LeaderboardSort>,
+    /// Pilots with fewer decided matches in the window are left out, 5 by default
+    pub min_games: Option<u32>,
+    /// Only manual or only automatic matches, both if left out
+    pub manual: Option<bool>,
+}
+
+impl LeaderboardQuery {
+    pub const DEFAULT_MIN_GAMES: u32 = 5;
+
+    pub fn min_games(&self) -> u32 {
+        self.min_games.unwrap_or(Self::DEFAULT_MIN_GAMES)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaderboardEntry {
+    pub rank: usize,
+    /// Rank in the previous window, missing if the pilot wasn't ranked there
+    pub previous_rank: Option<usize>,
+    pub pilot_id: Uuid,
+    pub name: String,
+    pub owner_id: String,
+    /// The version the entry is for, only set in the current version window
+    pub version: Option<i32>,
+    pub matches: usize,
+    pub wins: usize,
+    pub losses: usize,
+    /// Matches without a known winner, part of `matches` but not of the win rate
+    pub undecided: usize,
+    /// Percentage of decided matches won
+    pub win_rate: f64,
+    pub rating: Rating,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Leaderboard {
+    pub window: LeaderboardWindow,
+    pub sort: LeaderboardSort,
+    pub min_games: u32,
+    pub manual: Option<bool>,
+    pub entries: Vec<LeaderboardEntry>,
+}
+
+impl Leaderboard {
+    fn new(standings: &Standings, query: &LeaderboardQuery) -> Self {
+        let sort = query.sort.unwrap_or_default();
+        let min_games = query.min_games();
+
+        let previous_ranks: HashMap<_, _> = standings
+            .rank(&standings.previous, sort, min_games)
+            .into_iter()
+            .map(|e| (e.pilot_id, e.rank))
+            .collect();
+
+        let mut entries = standings.rank(&standings.window, sort, min_games);
+        for entry in &mut entries {
+            entry.previous_rank = previous_ranks.get(&entry.pilot_id).copied();
+        }
+
+        Leaderboard {
+            window: standings.window_kind,
+            sort,
+            min_games,
+            manual: query.manual,
+            entries,
+        }
+    }
+}
+
+/// Records and ratings from the matches in one window.
+#[derive(Debug, Default)]
+struct Tally {
+    records: HashMap<Uuid, Record>,
+    ratings: Ratings,
+}
+
+/// Everything a leaderboard needs apart from how it is sorted and cut off.
+#[derive(Debug)]
+struct Standings {
+    pilots: Vec<AiPilot>,
+    window_kind: LeaderboardWindow,
+    window: Tally,
+    previous: Tally,
+}
+
+impl Standings {
+    /// Tallies `matches` in `window` as of `now`, in milliseconds, and in the window before.
+    fn new(
+        pilots: Vec<AiPilot>,
+        matches: &[MatchResult],
+        window: LeaderboardWindow,
+        manual: Option<bool>,
+        now: i64,
+    ) -> Self {
+        let current_only = window == LeaderboardWindow::Current;
+        let (range, previous_range) = window.ranges(now);
+        let in_range = |(from, to): (i64, i64)| -> Vec<MatchResult> {
+            matches
+                .iter()
+                .filter(|m| m.created_at >= from && m.created_at < to)
+                .filter(|m| manual.is_none_or(|manual| m.manual_run == manual))
+                .cloned()
+                .collect()
+        };
+
+        Standings {
+            window: tally(&pilots, &in_range(range), current_only),
+            previous: tally(&pilots, &in_range(previous_range), current_only),
+            pilots,
+            window_kind: window,
+        }
+    }
+
+    /// Ranked pilots with at least `min_games` decided matches, without previous ranks.
+    fn rank(&self, tally: &Tally, sort: LeaderboardSort, min_games: u32) -> Vec<LeaderboardEntry> {
+        let current_only = self.window_kind == LeaderboardWindow::Current;
+
+        let mut entries: Vec<_> = self
+            .pilots
+            .iter()
+            .filter_map(|pilot| {
+                // Undecided matches say nothing about the win rate the ranking is based on
+                let record = tally.records.get(&pilot.id)?;
+                if record.decided() < min_games.max(1) as usize {
+                    return None;
+                }
+                let version = current_only.then_some(pilot.current.version);
+
+                Some(LeaderboardEntry {
+                    rank: 0,
+                    previous_rank: None,
+                    pilot_id: pilot.id,
+                    name: pilot.name.clone(),
+                    owner_id: pilot.owner_id.clone(),
+                    version,
+                    matches: record.total(),
+                    wins: record.wins,
+                    losses: record.losses,
+                    undecided: record.undecided,
+                    win_rate: record.win_rate(),
+                    rating: match version {
+                        Some(version) => tally.ratings.version(&pilot.id, version),
+                        None => tally.ratings.pilot(&pilot.id),
+                    },
+                })
+            })
+            .collect();
+
+        entries.sort_by(|a, b| {
+            let order = match sort {
+                LeaderboardSort::WinRate => b
+                    .win_rate
+                    .total_cmp(&a.win_rate)
+                    .then(b.matches.cmp(&a.matches)),
+                LeaderboardSort::Rating => {
+                    b.rating.conservative().total_cmp(&a.rating.conservative())
+                }
+            };
+            order.then_with(|| a.name.cmp(&b.name))
+        });
+        for (i, entry) in entries.iter_mut().enumerate() {
+            entry.rank = i + 1;
+        }
+
+        entries
+    }
+}
+
+/// Records and ratings of `pilots`, or only of their current versions, in `matches`.
+fn tally(pilots: &[AiPilot], matches: &[MatchResult], current_only: bool) -> Tally {
+    Tally {
+        records: stats::records(pilots, matches, current_only),
+        ratings: Ratings::from_matches(matches),
+    }
+}
+
+/// Standings per window, only recomputed once the mirror changed. Rolling windows move on
+/// without new matches too, so entries also expire after a few minutes.
+#[derive(Clone)]
+pub struct LeaderboardCache {
+    cache: Cache<(u64, LeaderboardWindow, Option<bool>), Arc<Standings>>,
+}
+
+impl Default for LeaderboardCache {
+    fn default() -> Self {
+        LeaderboardCache::new()
+    }
+}
+
+impl LeaderboardCache {
+    pub fn new() -> Self {
+        let cache = Cache::builder()
+            .max_capacity(32)
+            .time_to_live(Duration::from_secs(5 * 60))
+            .build();
+        LeaderboardCache { cache }
+    }
+
+    pub async fn get(
+        &self,
+        query: &LeaderboardQuery,
+        client: &SqliteClient,
+    ) -> Result<Leaderboard, ApiErrors> {
+        let window = query.window.unwrap_or_default();
+        let entry = self
+            .cache
+            .entry((mirror::generation(), window, query.manual))
+            .or_try_insert_with(async {
+                let pilots = mirror::get_pilots(client).await?;
+                let matches = mirror::get_matches(None, None, client).await?;
+                let now = chrono::Utc::now().timestamp_millis();
+                Ok::<_, ApiErrors>(Arc::new(Standings::new(
+                    pilots,
+                    &matches,
+                    window,
+                    query.manual,
+                    now,
+                )))
+            })
+            .await
+            // The mirror already logged what went wrong
+            .map_err(|_| ApiErrors::InternalError("Failed to compute the leaderboard".into()))?;
+        metrics::cache_lookup("leaderboard", !entry.is_fresh());
+
+        Ok(Leaderboard::new(entry.value(), query))
+    }
+}
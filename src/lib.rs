@@ -0,0 +1,1421 @@
+pub mod api;
+pub mod api_client;
+pub mod api_error;
+pub mod audit;
+pub mod badge;
+pub mod cookie;
+pub mod events;
+pub mod export;
+pub mod feed;
+pub mod h2h;
+pub mod identity;
+pub mod leaderboard;
+pub mod metrics;
+pub mod mirror;
+pub mod mock_upstream;
+pub mod model;
+pub mod rate_limit;
+pub mod rating;
+pub mod scope;
+pub mod sso_client;
+pub mod stats;
+pub mod sync;
+pub mod tournament;
+pub mod util;
+pub mod webhook;
+
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use client::models::match_result::Winner;
+use rocket::{
+    Build, Rocket, Shutdown, State,
+    fs::{FileServer, relative},
+    futures::future::join_all,
+    http::{
+        ContentType, Cookie, CookieJar, SameSite, Status,
+        uri::fmt::{Query, UriDisplay},
+    },
+    response::{
+        Redirect,
+        stream::{Event, EventStream},
+    },
+    tokio::{select, spawn, sync::broadcast::error::RecvError},
+};
+use rocket_dyn_templates::{Template, context};
+use rocket_okapi::{
+    rapidoc::{GeneralConfig, HideShowConfig, RapiDocConfig, make_rapidoc},
+    settings::UrlObject,
+};
+
+use crate::{
+    api::{MatchDetail, MatchQuery, UserSummary, fetch_match},
+    api_client::{ApiClient, HttpApiClient},
+    api_error::ApiErrors,
+    audit::{AuditEvent, AuditLog, AuditResult},
+    cookie::{AdminUser, ApiUser},
+    events::Broadcaster,
+    h2h::{HeadToHead, Side},
+    identity::IdentityProvider,
+    leaderboard::{LeaderboardCache, LeaderboardQuery},
+    metrics::{MetricsAccess, RequestMetrics},
+    mirror::MatchFilter,
+    model::{Role, UsageEvent, User, UserToken, UserTokenStore, UserUsage},
+    rate_limit::{RateLimiter, RetryAfter},
+    rating::Rating,
+    scope::Scope,
+    sso_client::SSOClient,
+    stats::{Outcome, StatsCache, VersionStats},
+    sync::{SyncState, SyncWorker},
+    tournament::{
+        Tournament, TournamentDetail, TournamentFormat, TournamentId, TournamentRunner,
+        TournamentStatus,
+    },
+    util::{
+        build_info_ctx, discord_avatar_url, format_date_relative, format_date_time,
+        register_helpers,
+    },
+    webhook::{Delivery, DeliveryStatus, DeliveryWorker, Webhook, WebhookEvent},
+};
+
+#[macro_use]
+extern crate rocket;
+#[macro_use]
+extern crate rocket_okapi;
+
+pub type SqliteClient = sqlx::Pool<sqlx::Sqlite>;
+
+#[get("/")]
+async fn index_page(user: Option<ApiUser>) -> Template {
+    Template::render(
+        "index",
+        context! {
+            user: user,
+            build_info: build_info_ctx()
+        },
+    )
+}
+
+// Partials: Home Pilots
+#[get("/partials/home/pilots")]
+async fn partial_home_pilots(
+    user: Option<ApiUser>,
+    sso_client: &State<SSOClient>,
+    client: &State<SqliteClient>,
+    stats: &State<StatsCache>,
+) -> Result<Template, ApiErrors> {
+    // Fetch pilots owned by the user
+    let mut pilots = mirror::get_pilots(client).await?;
+    let stats = stats.get(client).await?;
+    let ratings = stats.ratings();
+
+    if let Some(user) = &user {
+        pilots.sort_by_key(|p| p.owner_id != user.discord_id);
+    }
+
+    let pilots_ctx: Vec<_> = join_all(pilots.into_iter().map(async |p| {
+        let is_own = if let Some(user) = &user {
+            p.owner_id == user.discord_id
+        } else {
+            false
+        };
+
+        let creator_user = sso_client.get_user(&p.owner_id).await;
+
+        context! {
+            id: p.id.to_string(),
+            name: p.name,
+            current: context! { version: p.current.version },
+            rating: rating_ctx(ratings.pilot(&p.id)),
+            creator_user: creator_user,
+            is_own: is_own,
+        }
+    }))
+    .await;
+
+    Ok(Template::render(
+        "partials/home_pilots",
+        context! { pilots: pilots_ctx },
+    ))
+}
+
+// Partials: Home Matches (recent)
+#[get("/partials/home/matches")]
+async fn partial_home_matches(
+    api_client: &State<Arc<dyn ApiClient>>,
+    client: &State<SqliteClient>,
+) -> Result<Template, ApiErrors> {
+    let mut matches = mirror::get_matches(None, None, client).await?;
+    let names = mirror::get_pilot_names(client).await?;
+
+    matches.sort_by_key(|m| -m.created_at);
+
+    let matches_ctx: Vec<_> = join_all(matches.into_iter().map(async |m| {
+        let team_a_name = resolve_pilot_name(&names, &m.team_a.aip_id);
+        let team_b_name = resolve_pilot_name(&names, &m.team_b.aip_id);
+
+        let download_url = if let Some(replay_id) = m.replay_id {
+            if replay_id.trim().len() == 0 {
+                None
+            } else {
+                Some(format!("{}/replay?replayId={}", api_client.base_url(), replay_id))
+            }
+        } else {
+            None
+        };
+
+        context! {
+            id: m.id.to_string(),
+            created_at: format_date_time(&chrono::DateTime::<chrono::Utc>::from_timestamp(m.created_at / 1_000, 0).unwrap_or_default()),
+            is_manual: m.manual_run,
+            team_a: context! { winner: m.winner == Winner::TeamA, aip_id: m.team_a.aip_id.to_string(), aip_name: team_a_name.clone(), version: m.team_a.version },
+            team_b: context! { winner: m.winner == Winner::TeamB, aip_id: m.team_b.aip_id.to_string(), aip_name: team_b_name.clone(), version: m.team_b.version },
+            winner: match m.winner {
+                Winner::TeamA => team_a_name,
+                Winner::TeamB => team_b_name,
+                Winner::Unknown => "Unknown".to_string(),
+            },
+            download_url: download_url,
+        }
+    }))
+    .await;
+
+    Ok(Template::render(
+        "partials/home_matches",
+        context! { matches: matches_ctx },
+    ))
+}
+
+// Partials: Upstream sync freshness
+#[get("/partials/sync_status")]
+async fn partial_sync_status(client: &State<SqliteClient>) -> Result<Template, ApiErrors> {
+    let state = SyncState::get(client).await?;
+
+    Ok(Template::render(
+        "partials/sync_status",
+        context! {
+            last_success: state.last_success_at.as_ref().map(format_date_relative),
+            last_success_at: state.last_success_at.as_ref().map(format_date_time),
+            failing: state.last_error.is_some(),
+            last_error: state.last_error,
+        },
+    ))
+}
+
+/// Private cookie holding the nonce of a login in progress.
+const LOGIN_NONCE_COOKIE: &str = "login_nonce";
+
+#[get("/login?<next>")]
+async fn login(
+    next: Option<&str>,
+    cookies: &CookieJar<'_>,
+    identity: &State<Arc<dyn IdentityProvider>>,
+) -> Result<Redirect, ApiErrors> {
+    let next = next.unwrap_or("/").replace("/", "__");
+
+    // Lax, the provider sends the browser back with a cross site redirect
+    let nonce = hex::encode(rand::random::<[u8; 16]>());
+    cookies.add_private(
+        Cookie::build((LOGIN_NONCE_COOKIE, nonce.clone()))
+            .same_site(SameSite::Lax)
+            .max_age(rocket::time::Duration::minutes(10)),
+    );
+
+    let state = format!("{}:{}", nonce, next);
+    Ok(Redirect::to(identity.login_url(&state)))
+}
+
+/// Providers that can't put the state in the callback path send it back as `state`.
+#[get("/login_callback?<code>&<state>")]
+async fn login_callback(
+    code: &str,
+    state: Option<&str>,
+    cookies: &CookieJar<'_>,
+    client: &State<SqliteClient>,
+    identity: &State<Arc<dyn IdentityProvider>>,
+) -> Result<Redirect, ApiErrors> {
+    login_callback_next(state.unwrap_or_default(), code, cookies, client, identity).await
+}
+
+/// `state` is `<nonce>:<next>`, the nonce has to match the cookie set by `/login` so
+/// nobody can sign a victim into their own account with a code of theirs.
+#[get("/login_callback/<state>?<code>")]
+async fn login_callback_next(
+    state: &str,
+    code: &str,
+    cookies: &CookieJar<'_>,
+    client: &State<SqliteClient>,
+    identity: &State<Arc<dyn IdentityProvider>>,
+) -> Result<Redirect, ApiErrors> {
+    let expected = cookies
+        .get_private(LOGIN_NONCE_COOKIE)
+        .map(|cookie| cookie.value().to_string());
+    cookies.remove_private(LOGIN_NONCE_COOKIE);
+    let next = match (state.split_once(':'), expected) {
+        (Some((nonce, next)), Some(expected)) if nonce == expected => next,
+        _ => {
+            return Err(ApiErrors::BadRequest(
+                "Login expired or started elsewhere, please sign in again".into(),
+            ));
+        }
+    };
+
+    let Some(user) = identity.exchange_code(code).await else {
+        return Err(ApiErrors::BadRequest("Invalid OAuth code".into()));
+    };
+
+    let user = User::upsert_by_discord_id(&user.id, &user.username, &user.avatar, &**client)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to upsert user: {}", e);
+            ApiErrors::InternalError("Failed to upsert user".into())
+        })?;
+
+    let cookie_str = serde_json::to_string(&ApiUser {
+        id: user.id,
+        discord_id: user.discord_id,
+        username: user.username,
+        avatar: user.avatar_url,
+        role: user.role,
+        scopes: None,
+    })
+    .map_err(|e| {
+        log::error!("Failed to serialize user data: {}", e);
+        ApiErrors::InternalError("Failed to serialize user data".into())
+    })?;
+
+    cookies.add_private(Cookie::new("auth", cookie_str));
+
+    // Needed since cookies are queued for redirects
+    let next = next.replace("__", "/");
+    let callback_redirect = format!("/login_callback_redirect?next={}", next);
+    Ok(Redirect::found(callback_redirect))
+}
+
+#[get("/logout")]
+async fn logout(cookies: &CookieJar<'_>) -> Template {
+    cookies.remove_private("auth");
+    Template::render("logout_callback", context! { next: "/" })
+}
+
+#[get("/login_callback_redirect?<next>")]
+async fn login_callback_redirect_page(next: Option<&str>) -> Template {
+    Template::render("login_callback", context! { next })
+}
+
+#[get("/user_tokens")]
+async fn user_tokens_page(
+    user: ApiUser,
+    client: &State<SqliteClient>,
+) -> Result<Template, ApiErrors> {
+    let tokens = UserToken::get_by_user_id(user.id, client)
+        .await
+        .map_err(|_| ApiErrors::InternalError("Failed to fetch user tokens".into()))?;
+    let pilots = mirror::get_pilots(client).await?;
+
+    let pilot_scopes = pilots
+        .into_iter()
+        .filter(|p| p.owner_id == user.discord_id)
+        .map(|p| Scope::PilotUpload(p.name));
+    let is_admin = User::get_by_id(user.id, client)
+        .await
+        .is_ok_and(|u| u.role == Role::Admin);
+    let scope_options: Vec<_> = Scope::FIXED
+        .into_iter()
+        .chain(pilot_scopes)
+        .chain(is_admin.then_some(Scope::Admin))
+        .map(|s| context! { value: s.to_string(), description: s.description() })
+        .collect();
+
+    Ok(Template::render(
+        "user_tokens",
+        context! {
+            tokens: tokens.iter().map(|t| context! {
+                id: t.id,
+                name: t.name.clone(),
+                token_prefix: t.token_prefix.clone(),
+                created_at: format_date_time(&t.created_at),
+                expires_at: t.expires_at.map(|d| format_date_time(&d)),
+                last_used_at: t.last_used_at.map(|d| format_date_relative(&d)),
+                scopes: t.scopes.0.iter().map(Scope::to_string).collect::<Vec<_>>(),
+            }).collect::<Vec<_>>(),
+            scope_options: scope_options,
+            user: user,
+            build_info: build_info_ctx()
+        },
+    ))
+}
+
+#[get("/webhooks")]
+async fn webhooks_page(user: ApiUser, client: &State<SqliteClient>) -> Result<Template, ApiErrors> {
+    let webhooks = Webhook::get_by_user_id(user.id, client).await.map_err(|e| {
+        log::error!("Failed to fetch webhooks: {}", e);
+        ApiErrors::InternalError("Failed to fetch webhooks".into())
+    })?;
+    let deliveries = Delivery::get_by_user_id(user.id, 100, client)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to fetch webhook deliveries: {}", e);
+            ApiErrors::InternalError("Failed to fetch webhook deliveries".into())
+        })?;
+
+    let event_options: Vec<_> = WebhookEvent::ALL
+        .iter()
+        .map(|e| context! { value: e.to_string(), description: e.description() })
+        .collect();
+
+    Ok(Template::render(
+        "webhooks",
+        context! {
+            webhooks: webhooks.iter().map(|w| context! {
+                id: w.id,
+                url: w.url.clone(),
+                secret: w.secret.clone(),
+                events: w.events.0.iter().map(WebhookEvent::to_string).collect::<Vec<_>>(),
+                created_at: format_date_time(&w.created_at),
+            }).collect::<Vec<_>>(),
+            deliveries: deliveries.iter().map(|d| context! {
+                id: d.id,
+                url: d.url.clone(),
+                event: d.event.clone(),
+                status: d.status,
+                pending: d.status == DeliveryStatus::Pending,
+                attempts: d.attempts,
+                last_status_code: d.last_status_code,
+                last_error: d.last_error.clone(),
+                created_at: format_date_relative(&d.created_at),
+                next_attempt_at: d.next_attempt_at.map(|t| format_date_time(&t)),
+            }).collect::<Vec<_>>(),
+            event_options: event_options,
+            user: user,
+            build_info: build_info_ctx()
+        },
+    ))
+}
+
+/// Privileged actions of the signed in user. Admins see everyone's, or those of `actor`.
+#[get("/audit?<actor>")]
+async fn audit_page(
+    user: ApiUser,
+    admin: Option<AdminUser>,
+    actor: Option<model::UserId>,
+    client: &State<SqliteClient>,
+) -> Result<Template, ApiErrors> {
+    let actor = if admin.is_some() { actor } else { Some(user.id) };
+    let events = AuditEvent::recent(actor, 200, client)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to fetch audit events: {}", e);
+            ApiErrors::InternalError("Failed to fetch audit events".into())
+        })?;
+
+    Ok(Template::render(
+        "audit",
+        context! {
+            is_admin: admin.is_some(),
+            filtered: admin.is_some() && actor.is_some(),
+            events: events.iter().map(|e| context! {
+                actor_id: e.actor_id,
+                actor_name: e.actor_name.clone(),
+                action: e.action,
+                description: e.action.description(),
+                target: e.target.clone(),
+                request_id: e.request_id.clone(),
+                ip: e.ip.clone(),
+                success: e.result == AuditResult::Success,
+                status: e.status,
+                created_at: format_date_relative(&e.created_at),
+                created_at_full: format_date_time(&e.created_at),
+            }).collect::<Vec<_>>(),
+            user: user,
+            build_info: build_info_ctx()
+        },
+    ))
+}
+
+#[get("/admin")]
+async fn admin_page(admin: AdminUser, client: &State<SqliteClient>) -> Result<Template, ApiErrors> {
+    let users = UserUsage::all(client).await.map_err(|e| {
+        log::error!("Failed to fetch user usage: {}", e);
+        ApiErrors::InternalError("Failed to fetch user usage".into())
+    })?;
+    let events = UsageEvent::recent(None, 50, client).await.map_err(|e| {
+        log::error!("Failed to fetch usage events: {}", e);
+        ApiErrors::InternalError("Failed to fetch usage events".into())
+    })?;
+
+    let usernames: HashMap<_, _> = users
+        .iter()
+        .map(|u| (u.user.id, u.user.username.clone()))
+        .collect();
+
+    Ok(Template::render(
+        "admin",
+        context! {
+            total_users: users.len(),
+            banned_users: users.iter().filter(|u| u.user.banned_at.is_some()).count(),
+            uploads_last_day: users.iter().map(|u| u.uploads_last_day).sum::<i64>(),
+            matches_last_day: users.iter().map(|u| u.matches_created_last_day).sum::<i64>(),
+            users: users.iter().map(|u| context! {
+                id: u.user.id,
+                username: u.user.username.clone(),
+                discord_id: u.user.discord_id.clone(),
+                is_admin: u.user.role == Role::Admin,
+                banned: u.user.banned_at.is_some(),
+                uploads: u.uploads,
+                matches_created: u.matches_created,
+                uploads_last_day: u.uploads_last_day,
+                matches_created_last_day: u.matches_created_last_day,
+                tokens: u.tokens,
+                last_active_at: u.last_active_at.map(|d| format_date_relative(&d)),
+            }).collect::<Vec<_>>(),
+            events: events.iter().map(|e| context! {
+                username: usernames.get(&e.user_id).cloned().unwrap_or_default(),
+                user_id: e.user_id,
+                kind: e.kind,
+                detail: e.detail.clone(),
+                created_at: format_date_relative(&e.created_at),
+            }).collect::<Vec<_>>(),
+            user: admin.0,
+            build_info: build_info_ctx()
+        },
+    ))
+}
+
+#[get("/admin/user/<user_id>")]
+async fn admin_user_page(
+    admin: AdminUser,
+    user_id: model::UserId,
+    client: &State<SqliteClient>,
+) -> Result<Template, ApiErrors> {
+    let target = User::get_by_id(user_id, client)
+        .await
+        .map_err(|_| ApiErrors::NotFound("User not found".into()))?;
+    let tokens = UserToken::get_by_user_id(user_id, client).await?;
+    let events = UsageEvent::recent(Some(user_id), 100, client)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to fetch usage events: {}", e);
+            ApiErrors::InternalError("Failed to fetch usage events".into())
+        })?;
+
+    Ok(Template::render(
+        "admin_user",
+        context! {
+            target: context! {
+                id: target.id,
+                username: target.username,
+                discord_id: target.discord_id,
+                is_admin: target.role == Role::Admin,
+                is_self: target.id == admin.id,
+                banned_at: target.banned_at.map(|d| format_date_time(&d)),
+                ban_reason: target.ban_reason,
+            },
+            tokens: tokens.iter().map(|t| context! {
+                id: t.id,
+                name: t.name.clone(),
+                token_prefix: t.token_prefix.clone(),
+                created_at: format_date_time(&t.created_at),
+                expires_at: t.expires_at.map(|d| format_date_time(&d)),
+                last_used_at: t.last_used_at.map(|d| format_date_relative(&d)),
+                scopes: t.scopes.0.iter().map(Scope::to_string).collect::<Vec<_>>(),
+            }).collect::<Vec<_>>(),
+            events: events.iter().map(|e| context! {
+                kind: e.kind,
+                detail: e.detail.clone(),
+                created_at: format_date_relative(&e.created_at),
+            }).collect::<Vec<_>>(),
+            user: admin.0,
+            build_info: build_info_ctx()
+        },
+    ))
+}
+
+#[get("/upload?<name>")]
+async fn upload_page(
+    user: ApiUser,
+    name: Option<String>,
+    client: &State<SqliteClient>,
+) -> Result<Template, ApiErrors> {
+    let pilots = mirror::get_pilots(client).await?;
+
+    let mut my_names = Vec::new();
+    let mut other_names = Vec::new();
+
+    for p in pilots.into_iter() {
+        if p.owner_id == user.discord_id.to_string() {
+            my_names.push(p.name);
+        } else {
+            other_names.push(p.name);
+        }
+    }
+
+    Ok(Template::render(
+        "upload",
+        context! {
+            my_names: my_names,
+            other_names: other_names,
+            preset_name: name,
+            user: user,
+            build_info: build_info_ctx()
+        },
+    ))
+}
+
+#[get("/match/create")]
+async fn match_create_page(user: ApiUser) -> Result<Template, ApiErrors> {
+    Ok(Template::render(
+        "match_create",
+        context! {
+            user: user,
+            build_info: build_info_ctx()
+        },
+    ))
+}
+
+#[get("/match/<match_id>")]
+async fn match_page(
+    user: Option<ApiUser>,
+    match_id: &str,
+    api_client: &State<Arc<dyn ApiClient>>,
+    broadcaster: &State<Broadcaster>,
+    client: &State<SqliteClient>,
+    stats: &State<StatsCache>,
+) -> Result<Template, ApiErrors> {
+    // Freshly finished matches may not have been synced yet
+    let match_result =
+        match fetch_match(match_id, api_client.inner(), broadcaster, stats, client).await? {
+            Some(m) => m,
+            // Queued fights reload once `/events` reports their result
+            None if broadcaster.is_pending(match_id) => {
+                return Ok(Template::render(
+                    "match",
+                    context! {
+                        user: user,
+                        build_info: build_info_ctx(),
+                        pending_match_id: match_id,
+                    },
+                ));
+            }
+            None => return Err(ApiErrors::NotFound("Match not found".into())),
+        };
+
+    let names = mirror::get_pilot_names(client).await?;
+    let detail = MatchDetail::new(&match_result, &names, api_client.base_url());
+    let team_a_name = detail.team_a.name.unwrap_or("Unknown".to_string());
+    let team_b_name = detail.team_b.name.unwrap_or("Unknown".to_string());
+
+    // Build the download URL from replay_id if available
+    let download_url = detail.replay_url;
+
+    // URL encode the download URL for the iframe using percent encoding
+    let url_encoded_download_url = download_url.as_ref().map(|url| {
+        url.chars()
+            .map(|c| match c {
+                'A'..='Z' | 'a'..='z' | '0'..='9' | '-' | '_' | '.' | '~' => c.to_string(),
+                _ => format!("%{:02X}", c as u8),
+            })
+            .collect::<String>()
+    });
+
+    // Format the timestamp
+    let created_at = chrono::DateTime::from_timestamp(match_result.created_at / 1000, 0)
+        .map(|dt| dt.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    // Create enhanced match data with winner flags
+    let match_result = context! {
+        id: detail.id.to_string(),
+        team_a: context! {
+            aip_name: team_a_name,
+            version: detail.team_a.version,
+            winner: detail.team_a.winner
+        },
+        team_b: context! {
+            aip_name: team_b_name,
+            version: detail.team_b.version,
+            winner: detail.team_b.winner
+        },
+        is_manual: detail.manual,
+        created_at: created_at,
+        download_url: download_url
+    };
+
+    Ok(Template::render(
+        "match",
+        context! {
+            user: user,
+            build_info: build_info_ctx(),
+            match_result,
+            url_encoded_download_url: url_encoded_download_url
+        },
+    ))
+}
+
+#[get("/matches?<query..>")]
+async fn matches_page(
+    user: Option<ApiUser>,
+    query: MatchQuery,
+    api_client: &State<Arc<dyn ApiClient>>,
+    client: &State<SqliteClient>,
+) -> Result<Template, ApiErrors> {
+    let (filter, cursor) = query.resolve(client).await?;
+    let (matches, next) =
+        mirror::get_matches_page(&filter, cursor.as_ref(), query.limit(), client).await?;
+    let total_count = mirror::count_matches(&filter, client).await?;
+    let names = mirror::get_pilot_names(client).await?;
+
+    let mut pilot_names: Vec<_> = names.values().cloned().collect();
+    pilot_names.sort();
+
+    // Links keep the current filters so pages can be bookmarked
+    let page_url = |cursor: Option<String>| {
+        let query = MatchQuery {
+            cursor,
+            ..query.clone()
+        };
+        format!("/matches?{}", &query as &dyn UriDisplay<Query>)
+    };
+    let next_url = next.map(|c| page_url(Some(c.to_string())));
+    let first_url = cursor.is_some().then(|| page_url(None));
+    // Only the unfiltered first page can take new matches as they finish
+    let live = cursor.is_none() && filter == MatchFilter::default();
+
+    let matches_ctx: Vec<_> = join_all(matches.into_iter().map(async |m| {
+        let team_a_name = resolve_pilot_name(&names, &m.team_a.aip_id);
+        let team_b_name = resolve_pilot_name(&names, &m.team_b.aip_id);
+
+        let download_url = if let Some(replay_id) = m.replay_id {
+            if replay_id.trim().len() == 0 {
+                None
+            } else {
+                Some(format!("{}/replay?replayId={}", api_client.base_url(), replay_id))
+            }
+        } else {
+            None
+        };
+
+        context! {
+            id: m.id.to_string(),
+            created_at: format_date_time(&chrono::DateTime::<chrono::Utc>::from_timestamp(m.created_at / 1_000, 0).unwrap_or_default()),
+            created_at_timestamp: m.created_at,
+            is_manual: m.manual_run,
+            match_type: if m.manual_run { "Manual" } else { "Auto" },
+            team_a: context! { 
+                winner: m.winner == Winner::TeamA, 
+                aip_id: m.team_a.aip_id.to_string(), 
+                aip_name: team_a_name.clone(), 
+                version: m.team_a.version 
+            },
+            team_b: context! { 
+                winner: m.winner == Winner::TeamB, 
+                aip_id: m.team_b.aip_id.to_string(), 
+                aip_name: team_b_name.clone(), 
+                version: m.team_b.version 
+            },
+            winner_name: match m.winner {
+                Winner::TeamA => team_a_name,
+                Winner::TeamB => team_b_name,
+                Winner::Unknown => "Unknown".to_string(),
+            },
+            has_replay: download_url.is_some(),
+            download_url: download_url,
+        }
+    }))
+    .await;
+
+    let matches_count = matches_ctx.len();
+
+    Ok(Template::render(
+        "matches",
+        context! {
+            feed_url: "/feeds/matches.atom",
+            matches: matches_ctx,
+            matches_count: matches_count,
+            total_count: total_count,
+            query: query,
+            pilot_names: pilot_names,
+            next_url: next_url,
+            first_url: first_url,
+            live: live,
+            user: user,
+            build_info: build_info_ctx()
+        },
+    ))
+}
+
+#[get("/compare/<pilot_a>/<pilot_b>")]
+async fn compare_page(
+    user: Option<ApiUser>,
+    pilot_a: &str,
+    pilot_b: &str,
+    api_client: &State<Arc<dyn ApiClient>>,
+    client: &State<SqliteClient>,
+) -> Result<Template, ApiErrors> {
+    let pilot_a = mirror::get_pilot_by_name(pilot_a, client)
+        .await?
+        .ok_or_else(|| ApiErrors::NotFound("Pilot not found".into()))?;
+    let pilot_b = mirror::get_pilot_by_name(pilot_b, client)
+        .await?
+        .ok_or_else(|| ApiErrors::NotFound("Pilot not found".into()))?;
+    if pilot_a.id == pilot_b.id {
+        return Err(ApiErrors::BadRequest(
+            "Cannot compare a pilot with itself".into(),
+        ));
+    }
+
+    let matches = mirror::get_matches(Some(&pilot_a.id), None, client).await?;
+    let h2h = HeadToHead::new(&pilot_a, &pilot_b, &matches);
+
+    // Rows are versions of A, columns versions of B
+    let grid: Vec<_> = h2h
+        .versions_a
+        .iter()
+        .map(|&version_a| {
+            let cells: Vec<_> = h2h
+                .versions_b
+                .iter()
+                .map(|&version_b| {
+                    h2h.matchup(version_a, version_b).map(|m| {
+                        context! {
+                            a_wins: m.a_wins,
+                            b_wins: m.b_wins,
+                            undecided: m.undecided,
+                            leader: match m.a_wins.cmp(&m.b_wins) {
+                                std::cmp::Ordering::Greater => "a",
+                                std::cmp::Ordering::Less => "b",
+                                std::cmp::Ordering::Equal => "even",
+                            },
+                        }
+                    })
+                })
+                .collect();
+            context! { version: version_a, cells: cells }
+        })
+        .collect();
+
+    let meetings: Vec<_> = h2h
+        .meetings
+        .iter()
+        .map(|m| context! {
+            id: m.match_id.to_string(),
+            created_at: format_date_time(&chrono::DateTime::<chrono::Utc>::from_timestamp(m.created_at / 1_000, 0).unwrap_or_default()),
+            version_a: m.version_a,
+            version_b: m.version_b,
+            winner: m.winner,
+            winner_name: match m.winner {
+                Some(Side::A) => h2h.pilot_a.name.as_str(),
+                Some(Side::B) => h2h.pilot_b.name.as_str(),
+                None => "Unknown",
+            },
+            match_type: if m.manual_run { "Manual" } else { "Auto" },
+            download_url: m.replay_id.as_ref().map(|r| format!("{}/replay?replayId={}", api_client.base_url(), r)),
+        })
+        .collect();
+
+    Ok(Template::render(
+        "compare",
+        context! {
+            h2h: &h2h,
+            grid: grid,
+            meetings: meetings,
+            user: user,
+            build_info: build_info_ctx()
+        },
+    ))
+}
+
+/// Live match and upload events as Server-Sent Events.
+#[get("/events")]
+fn live_events(broadcaster: &State<Broadcaster>, mut shutdown: Shutdown) -> EventStream![] {
+    let mut receiver = broadcaster.subscribe();
+
+    EventStream! {
+        loop {
+            let event = select! {
+                event = receiver.recv() => match event {
+                    Ok(event) => event,
+                    Err(RecvError::Closed) => break,
+                    // Slow clients miss events rather than stalling everyone else
+                    Err(RecvError::Lagged(_)) => continue,
+                },
+                _ = &mut shutdown => break,
+            };
+
+            yield Event::json(&event).event(event.name());
+        }
+    }
+    .heartbeat(Duration::from_secs(15))
+}
+
+/// Request, upstream, cache and database pool metrics in the Prometheus text format.
+#[get("/metrics")]
+fn prometheus_metrics(
+    _access: MetricsAccess,
+    client: &State<SqliteClient>,
+) -> (ContentType, String) {
+    let content_type = ContentType::new("text", "plain").with_params(("version", "0.0.4"));
+    (content_type, metrics::render(client))
+}
+
+#[get("/tournaments")]
+async fn tournaments_page(
+    user: Option<ApiUser>,
+    client: &State<SqliteClient>,
+) -> Result<Template, ApiErrors> {
+    let tournaments = Tournament::all(client).await.map_err(|e| {
+        log::error!("Failed to fetch tournaments: {}", e);
+        ApiErrors::InternalError("Failed to fetch tournaments".into())
+    })?;
+    let pilots = mirror::get_pilots(client).await?;
+    let tournaments_count = tournaments.len();
+
+    Ok(Template::render(
+        "tournaments",
+        context! {
+            tournaments_count: tournaments_count,
+            tournaments: tournaments.iter().map(|t| context! {
+                id: t.id,
+                name: t.name.clone(),
+                format: t.format.label(),
+                status: t.status,
+                current_round: t.current_round,
+                total_rounds: t.total_rounds,
+                created_at: format_date_relative(&t.created_at),
+            }).collect::<Vec<_>>(),
+            pilots: pilots.iter().map(|p| context! {
+                name: p.name.clone(),
+                version: p.current.version,
+            }).collect::<Vec<_>>(),
+            user: user,
+            build_info: build_info_ctx()
+        },
+    ))
+}
+
+#[get("/tournament/<tournament_id>")]
+async fn tournament_page(
+    user: Option<ApiUser>,
+    tournament_id: TournamentId,
+    client: &State<SqliteClient>,
+) -> Result<Template, ApiErrors> {
+    let detail = TournamentDetail::load(tournament_id, client).await?;
+
+    let is_creator = user
+        .as_ref()
+        .is_some_and(|u| u.id == detail.tournament.created_by);
+    let can_start = is_creator && detail.tournament.status == TournamentStatus::Pending;
+    let is_bracket = detail.tournament.format == TournamentFormat::SingleElimination;
+
+    Ok(Template::render(
+        "tournament",
+        context! {
+            detail: detail,
+            can_start: can_start,
+            is_bracket: is_bracket,
+            user: user,
+            build_info: build_info_ctx()
+        },
+    ))
+}
+
+#[get("/pilot/<pilot_name>")]
+async fn pilot_stats_page(
+    user: Option<ApiUser>,
+    pilot_name: &str,
+    sso_client: &State<SSOClient>,
+    stats: &State<StatsCache>,
+    client: &State<SqliteClient>,
+) -> Result<Template, ApiErrors> {
+    let pilot = mirror::get_pilot_by_name(pilot_name, client)
+        .await?
+        .ok_or_else(|| ApiErrors::NotFound("Pilot not found".into()))?;
+    let stats = stats.get(client).await?;
+    let pilot_stats = stats
+        .pilot(&pilot.id)
+        .ok_or_else(|| ApiErrors::NotFound("Pilot not found".into()))?;
+    let matches = mirror::get_matches(Some(&pilot.id), None, client).await?;
+    let names = mirror::get_pilot_names(client).await?;
+
+    // Recent matches (last 10) - sort by created_at descending to get latest first
+    let mut sorted_matches = matches.clone();
+    sorted_matches.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    let recent_matches = join_all(sorted_matches.iter().take(10).map(async |m| {
+        let (opponent_id, opponent_version) = if m.team_a.aip_id == pilot.id {
+            (m.team_b.aip_id, m.team_b.version)
+        } else {
+            (m.team_a.aip_id, m.team_a.version)
+        };
+
+        let opponent_name = resolve_pilot_name(&names, &opponent_id);
+        context! {
+            opponent: opponent_name,
+            opponent_version: opponent_version,
+            outcome: Outcome::of(m, &pilot.id),
+            created_at: format_date_time(&chrono::DateTime::<chrono::Utc>::from_timestamp(m.created_at / 1_000, 0).unwrap_or_default()),
+            is_manual: m.manual_run,
+        }
+    })).await;
+
+    let pilot_name = pilot.name.clone();
+    let pilot_owner_id = pilot.owner_id.clone();
+    let pilot_current_version = pilot.current.version;
+    let is_own_pilot = user
+        .as_ref()
+        .map(|u| pilot_owner_id == u.discord_id.to_string())
+        .unwrap_or(false);
+
+    // Get creator info from Discord cache
+    let creator_info = sso_client.get_user(&pilot_owner_id).await;
+    let creator_name = creator_info
+        .as_ref()
+        .map(|info| info.username.clone())
+        .unwrap_or_else(|| pilot_owner_id.clone());
+    let creator_avatar = creator_info
+        .as_ref()
+        .map(|info| discord_avatar_url(&pilot_owner_id, &info.avatar));
+
+    Ok(Template::render(
+        "pilot_stats",
+        context! {
+            feed_url: format!("/feeds/pilot/{}.atom", pilot_name),
+            pilot: context! {
+                name: pilot_name.clone(),
+                creator: creator_name,
+                creator_avatar: creator_avatar,
+                owner_id: pilot_owner_id,
+                current_version: pilot_current_version,
+                is_own: is_own_pilot,
+            },
+            stats: pilot_stats,
+            recent_matches: recent_matches,
+            // Pass raw matches data for JavaScript filtering
+            all_matches_json: serde_json::to_string(&matches).unwrap_or_default(),
+            pilot_id: pilot.id.to_string(),
+            user: user,
+            build_info: build_info_ctx()
+        },
+    ))
+}
+
+#[get("/pilot/<pilot_name>/version/<version>")]
+async fn partial_pilot_version_stats(
+    pilot_name: &str,
+    version: i32,
+    stats: &State<StatsCache>,
+    client: &State<SqliteClient>,
+) -> Result<Template, ApiErrors> {
+    let pilot = mirror::get_pilot_by_name(pilot_name, client)
+        .await?
+        .ok_or_else(|| ApiErrors::NotFound("Pilot not found".into()))?;
+
+    let stats = stats.get(client).await?;
+    let version_stats = stats
+        .pilot(&pilot.id)
+        .and_then(|p| p.version(version))
+        .cloned()
+        .unwrap_or_else(|| VersionStats::unplayed(version));
+    let version_matches = mirror::get_matches(Some(&pilot.id), Some(version), client).await?;
+    let names = mirror::get_pilot_names(client).await?;
+
+    // Recent matches for this version
+    let mut sorted_matches = version_matches.clone();
+    sorted_matches.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    let recent_matches = join_all(sorted_matches.iter().take(10).map(async |m| {
+        let (opponent_id, opponent_version) = if m.team_a.aip_id == pilot.id {
+            (m.team_b.aip_id, m.team_b.version)
+        } else {
+            (m.team_a.aip_id, m.team_a.version)
+        };
+
+        let opponent_name = names.get(&opponent_id).cloned();
+
+        context! {
+            opponent: opponent_name,
+            opponent_version: opponent_version,
+            outcome: Outcome::of(m, &pilot.id),
+            created_at: format_date_time(&chrono::DateTime::<chrono::Utc>::from_timestamp(m.created_at / 1_000, 0).unwrap_or_default()),
+            is_manual: m.manual_run,
+        }
+    })).await;
+
+    Ok(Template::render(
+        "partials/version_stats",
+        context! {
+            stats: version_stats,
+            recent_matches: recent_matches,
+        },
+    ))
+}
+
+#[get("/leaderboard?<query..>")]
+async fn leaderboard_page(
+    user: Option<ApiUser>,
+    query: LeaderboardQuery,
+    client: &State<SqliteClient>,
+    leaderboards: &State<LeaderboardCache>,
+) -> Result<Template, ApiErrors> {
+    let leaderboard = leaderboards.get(&query, client).await?;
+
+    let entries_ctx: Vec<_> = leaderboard
+        .entries
+        .iter()
+        .map(|e| {
+            // Positive when the pilot climbed since the previous window
+            let change = e.previous_rank.map(|previous| previous as i64 - e.rank as i64);
+            context! {
+                rank: e.rank,
+                name: &e.name,
+                owner_id: &e.owner_id,
+                version: e.version,
+                matches: e.matches,
+                wins: e.wins,
+                losses: e.losses,
+                undecided: e.undecided,
+                win_rate: format!("{:.0}", e.win_rate),
+                rating: rating_ctx(e.rating),
+                is_new: change.is_none(),
+                up: change.filter(|c| *c > 0),
+                down: change.filter(|c| *c < 0).map(|c| -c),
+            }
+        })
+        .collect();
+
+    Ok(Template::render(
+        "leaderboard",
+        context! {
+            entries: entries_ctx,
+            query: context! {
+                window: leaderboard.window,
+                sort: leaderboard.sort,
+                min_games: leaderboard.min_games,
+                manual: leaderboard.manual,
+            },
+            user: user,
+            build_info: build_info_ctx()
+        },
+    ))
+}
+
+#[get("/users")]
+async fn users_page(
+    user: Option<ApiUser>,
+    sso_client: &State<SSOClient>,
+    stats: &State<StatsCache>,
+    client: &State<SqliteClient>,
+) -> Result<Template, ApiErrors> {
+    let stats = stats.get(client).await?;
+
+    // Sorted by pilot count descending, then by total matches
+    let users = UserSummary::all(&stats, sso_client).await;
+
+    Ok(Template::render(
+        "users",
+        context! {
+            users: users,
+            user: user,
+            build_info: build_info_ctx()
+        },
+    ))
+}
+
+#[get("/user/<owner_id>")]
+async fn user_page(
+    user: Option<ApiUser>,
+    owner_id: &str,
+    sso_client: &State<SSOClient>,
+    stats: &State<StatsCache>,
+    client: &State<SqliteClient>,
+) -> Result<Template, ApiErrors> {
+    let stats = stats.get(client).await?;
+    let owner_stats = stats
+        .owner(owner_id)
+        .ok_or_else(|| ApiErrors::NotFound("User not found or has no pilots".into()))?;
+    let user_pilots = stats.pilots_of(owner_id);
+    let names = mirror::get_pilot_names(client).await?;
+
+    // Get user info from Discord cache
+    let user_info = sso_client.get_user(owner_id).await;
+    let username = user_info
+        .as_ref()
+        .map(|info| info.username.clone())
+        .unwrap_or_else(|| owner_id.to_string());
+    let user_avatar = user_info
+        .as_ref()
+        .map(|info| discord_avatar_url(owner_id, &info.avatar));
+
+    // Gather all matches for user's pilots, once even when two of them met
+    let mut all_matches = Vec::new();
+    let mut seen = HashSet::new();
+    for pilot in &user_pilots {
+        for m in mirror::get_matches(Some(&pilot.pilot_id), None, client).await? {
+            if seen.insert(m.id) {
+                all_matches.push(m);
+            }
+        }
+    }
+
+    // Get recent matches (last 20, sorted by date)
+    let mut sorted_matches = all_matches.clone();
+    sorted_matches.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    let recent_matches = join_all(sorted_matches.iter().take(20).map(async |m| {
+        // Find which pilot was involved in this match
+        let user_pilot = user_pilots.iter().find(|pilot| {
+            m.team_a.aip_id == pilot.pilot_id || m.team_b.aip_id == pilot.pilot_id
+        }).unwrap();
+        let (opponent_id, opponent_version, pilot_version) = if m.team_a.aip_id == user_pilot.pilot_id {
+            (m.team_b.aip_id, m.team_b.version, m.team_a.version)
+        } else {
+            (m.team_a.aip_id, m.team_a.version, m.team_b.version)
+        };
+
+        let opponent_name = resolve_pilot_name(&names, &opponent_id);
+        context! {
+            pilot_name: user_pilot.name.clone(),
+            pilot_version: pilot_version,
+            opponent: opponent_name,
+            opponent_version: opponent_version,
+            outcome: Outcome::of(m, &user_pilot.pilot_id),
+            created_at: format_date_time(&chrono::DateTime::<chrono::Utc>::from_timestamp(m.created_at / 1_000, 0).unwrap_or_default()),
+            is_manual: m.manual_run,
+        }
+    })).await;
+
+    Ok(Template::render(
+        "user",
+        context! {
+            feed_url: format!("/feeds/user/{}.atom", owner_id),
+            target_user: context! {
+                owner_id: owner_id,
+                username: username,
+                avatar: user_avatar,
+            },
+            stats: owner_stats,
+            pilots: user_pilots,
+            recent_matches: recent_matches,
+            user: user,
+            build_info: build_info_ctx()
+        },
+    ))
+}
+
+fn resolve_pilot_name(names: &HashMap<uuid::Uuid, String>, pilot_id: &uuid::Uuid) -> String {
+    names
+        .get(pilot_id)
+        .cloned()
+        .unwrap_or_else(|| pilot_id.to_string())
+}
+
+fn rating_ctx(rating: Rating) -> impl serde::Serialize {
+    context! {
+        value: format!("{:.0}", rating.rating),
+        deviation: format!("{:.0}", rating.deviation),
+        matches: rating.matches,
+    }
+}
+
+fn render_error_page(code: u16, message: &str) -> Template {
+    Template::render(
+        "error",
+        context! {
+        code: code.to_string(),
+            message: message,
+            build_info: build_info_ctx(),
+        },
+    )
+}
+
+#[catch(401)]
+fn unauthorized_catcher(_status: Status, req: &rocket::Request<'_>) -> Redirect {
+    let next = req.uri().path();
+    Redirect::to(format!("/login?next={}", next))
+}
+
+#[catch(404)]
+fn not_found_catcher(_status: Status, _req: &rocket::Request<'_>) -> Template {
+    render_error_page(404, "Not Found")
+}
+
+#[catch(429)]
+fn too_many_requests_catcher(_status: Status, req: &rocket::Request<'_>) -> ApiErrors {
+    let RetryAfter(retry_after) = *req.local_cache(|| RetryAfter(60));
+    ApiErrors::TooManyRequests(
+        format!("Too many requests, try again in {} seconds", retry_after),
+        retry_after,
+    )
+}
+
+#[catch(500)]
+fn internal_error_catcher(_status: Status, _req: &rocket::Request<'_>) -> Template {
+    render_error_page(500, "Internal Server Error")
+}
+
+#[catch(default)]
+fn default_catcher(status: Status, _req: &rocket::Request<'_>) -> Template {
+    let message = match status.code {
+        400 => "Bad Request",
+        403 => "Forbidden",
+        405 => "Method Not Allowed",
+        422 => "Unprocessable Entity",
+        503 => "Service Unavailable",
+        _ => "Error",
+    };
+
+    render_error_page(status.code, message)
+}
+
+/// Builds the site from the environment, see `main.rs` for launching it.
+pub async fn rocket() -> Rocket<Build> {
+    simple_logger::init_with_level(log::Level::Info).expect("Failed to initialize logger");
+
+    let _ = dotenvy::dotenv();
+
+    let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let opts = sqlx::sqlite::SqliteConnectOptions::from_str(&url)
+        .expect("Failed to parse DATABASE_URL")
+        .create_if_missing(true);
+    let client = sqlx::sqlite::SqlitePool::connect_with(opts)
+        .await
+        .expect("Failed to connect to database");
+    sqlx::migrate!("./migrations")
+        .run(&client)
+        .await
+        .expect("Failed to run migrations");
+
+    match User::promote_admins(&client).await {
+        Ok(0) => {}
+        Ok(n) => log::info!("Promoted {} users to admin", n),
+        Err(e) => log::error!("Failed to promote admins: {}", e),
+    }
+
+    UserToken::init_hash_key();
+    match UserToken::hash_plaintext_tokens(&client).await {
+        Ok(0) => {}
+        Ok(n) => log::info!("Hashed {} plaintext user tokens", n),
+        Err(e) => panic!("Failed to hash plaintext user tokens: {}", e),
+    }
+
+    let identity = identity::from_env();
+    let api_client: Arc<dyn ApiClient> = Arc::new(HttpApiClient::new());
+
+    // Mirror upstream pilots and matches into SQLite
+    let broadcaster = Broadcaster::new();
+    let stats = StatsCache::new();
+    spawn(
+        SyncWorker::new(
+            api_client.clone(),
+            client.clone(),
+            broadcaster.clone(),
+            stats.clone(),
+        )
+        .run(),
+    );
+
+    // Watch queued fights for live updates
+    spawn(
+        broadcaster
+            .clone()
+            .run(api_client.clone(), client.clone(), stats.clone()),
+    );
+
+    // Schedule tournament games and collect their results
+    spawn(TournamentRunner::new(api_client.clone(), client.clone()).run());
+
+    // Send queued webhook deliveries
+    spawn(DeliveryWorker::new(client.clone()).run());
+
+    build(client, identity, api_client, broadcaster, stats)
+}
+
+/// Mounts every route on an already migrated database, without starting any background workers.
+pub fn build(
+    client: SqliteClient,
+    identity: Arc<dyn IdentityProvider>,
+    api_client: Arc<dyn ApiClient>,
+    broadcaster: Broadcaster,
+    stats: StatsCache,
+) -> Rocket<Build> {
+    let sso_client = SSOClient::new(identity.clone());
+
+    rocket::build()
+        .manage(client)
+        .manage(sso_client)
+        .manage(identity)
+        .manage(api_client)
+        .manage(broadcaster)
+        .manage(RateLimiter::from_env())
+        .manage(stats)
+        .manage(LeaderboardCache::new())
+        .mount("/api", api::routes())
+        .mount("/feeds", feed::routes())
+        .mount("/badge", badge::routes())
+        .mount("/static", FileServer::from(relative!("public")))
+        .mount(
+            "/",
+            routes![
+                index_page,
+                partial_home_pilots,
+                partial_home_matches,
+                partial_sync_status,
+                user_tokens_page,
+                webhooks_page,
+                audit_page,
+                admin_page,
+                admin_user_page,
+                upload_page,
+                match_create_page,
+                match_page,
+                matches_page,
+                compare_page,
+                live_events,
+                prometheus_metrics,
+                tournaments_page,
+                tournament_page,
+                pilot_stats_page,
+                partial_pilot_version_stats,
+                users_page,
+                leaderboard_page,
+                user_page,
+                login_callback_redirect_page,
+                login,
+                login_callback,
+                login_callback_next,
+                logout,
+            ],
+        )
+        .mount(
+            "/rapidoc",
+            make_rapidoc(&RapiDocConfig {
+                general: GeneralConfig {
+                    spec_urls: vec![UrlObject::new("General", "../api/openapi.json")],
+                    ..Default::default()
+                },
+                hide_show: HideShowConfig {
+                    allow_spec_url_load: false,
+                    allow_spec_file_load: false,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+        )
+        .register(
+            "/",
+            catchers![
+                unauthorized_catcher,
+                not_found_catcher,
+                too_many_requests_catcher,
+                internal_error_catcher,
+                default_catcher
+            ],
+        )
+        .attach(Template::custom(|engines| {
+            register_helpers(&mut engines.handlebars)
+        }))
+        .attach(AuditLog)
+        .attach(RequestMetrics)
+}
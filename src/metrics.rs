@@ -0,0 +1,191 @@
+use std::{env, future::Future, time::Instant};
+
+use lazy_static::lazy_static;
+use prometheus::{
+    Encoder, HistogramVec, IntCounterVec, IntGaugeVec, TextEncoder, register_histogram_vec,
+    register_int_counter_vec, register_int_gauge_vec,
+};
+use rocket::{
+    Data, Request, Response,
+    fairing::{Fairing, Info, Kind},
+    http::Status,
+    request::{FromRequest, Outcome},
+};
+use sha2::{Digest, Sha256};
+
+use crate::{SqliteClient, cookie::AdminUser};
+
+// Prometheus metrics served on `/metrics`, all in the default registry.
+// Only scrapers with `METRICS_TOKEN` and signed in admins may read them.
+
+lazy_static! {
+    static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "http_request_duration_seconds",
+        "Time to handle a request, including template rendering",
+        &["method", "route", "status"]
+    )
+    .unwrap();
+    static ref UPSTREAM_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "upstream_request_duration_seconds",
+        "Time spent in calls to the upstream AIP API",
+        &["function"]
+    )
+    .unwrap();
+    static ref UPSTREAM_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "upstream_errors_total",
+        "Failed calls to the upstream AIP API",
+        &["function"]
+    )
+    .unwrap();
+    static ref SSO_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "sso_request_duration_seconds",
+        "Time spent in calls to the SSO service",
+        &["endpoint"]
+    )
+    .unwrap();
+    static ref SSO_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "sso_errors_total",
+        "Failed calls to the SSO service",
+        &["endpoint"]
+    )
+    .unwrap();
+    static ref CACHE_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "cache_requests_total",
+        "Cache lookups by cache and whether they hit",
+        &["cache", "result"]
+    )
+    .unwrap();
+    static ref SQLITE_POOL_CONNECTIONS: IntGaugeVec = register_int_gauge_vec!(
+        "sqlite_pool_connections",
+        "SQLite pool connections by state",
+        &["state"]
+    )
+    .unwrap();
+}
+
+/// Times an upstream API call made through `client::apis::default_api::<function>`.
+pub async fn upstream<T, E>(
+    function: &'static str,
+    call: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    timed(&UPSTREAM_REQUEST_DURATION, &UPSTREAM_ERRORS, function, call).await
+}
+
+/// Times a call to the SSO service.
+pub async fn sso<T, E>(
+    endpoint: &'static str,
+    call: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    timed(&SSO_REQUEST_DURATION, &SSO_ERRORS, endpoint, call).await
+}
+
+async fn timed<T, E>(
+    duration: &HistogramVec,
+    errors: &IntCounterVec,
+    label: &str,
+    call: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let start = Instant::now();
+    let result = call.await;
+
+    duration
+        .with_label_values(&[label])
+        .observe(start.elapsed().as_secs_f64());
+    if result.is_err() {
+        errors.with_label_values(&[label]).inc();
+    }
+
+    result
+}
+
+pub fn cache_lookup(cache: &'static str, hit: bool) {
+    CACHE_REQUESTS
+        .with_label_values(&[cache, if hit { "hit" } else { "miss" }])
+        .inc();
+}
+
+/// Every metric in the Prometheus text format, with the pool usage read at call time.
+pub fn render(client: &SqliteClient) -> String {
+    let idle = client.num_idle() as i64;
+    let size = client.size() as i64;
+    SQLITE_POOL_CONNECTIONS
+        .with_label_values(&["idle"])
+        .set(idle);
+    SQLITE_POOL_CONNECTIONS
+        .with_label_values(&["in_use"])
+        .set(size - idle);
+    SQLITE_POOL_CONNECTIONS
+        .with_label_values(&["max"])
+        .set(client.options().get_max_connections() as i64);
+
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        log::error!("Failed to encode metrics: {}", e);
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+/// Access to `/metrics`, with `Authorization: Bearer <METRICS_TOKEN>` or as an admin.
+pub struct MetricsAccess;
+
+#[async_trait]
+impl<'r> FromRequest<'r> for MetricsAccess {
+    type Error = String;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let bearer = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|auth| auth.strip_prefix("Bearer "));
+        if let Some(bearer) = bearer {
+            // Comparing digests keeps the comparison time independent of the token
+            let token = env::var("METRICS_TOKEN").unwrap_or_default();
+            return if !token.is_empty() && Sha256::digest(bearer) == Sha256::digest(token) {
+                Outcome::Success(MetricsAccess)
+            } else {
+                Outcome::Error((Status::Forbidden, "Invalid metrics token".to_string()))
+            };
+        }
+
+        request.guard::<AdminUser>().await.map(|_| MetricsAccess)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct RequestStart(Option<Instant>);
+
+/// Records the latency of every request under the route it matched.
+pub struct RequestMetrics;
+
+#[async_trait]
+impl Fairing for RequestMetrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        request.local_cache(|| RequestStart(Some(Instant::now())));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let RequestStart(Some(start)) = *request.local_cache(|| RequestStart(None)) else {
+            return;
+        };
+        // Route templates keep the label set small, unlike raw paths
+        let route = request
+            .route()
+            .map(|r| r.uri.to_string())
+            .unwrap_or_else(|| "unmatched".to_string());
+
+        HTTP_REQUEST_DURATION
+            .with_label_values(&[
+                request.method().as_str(),
+                &route,
+                &response.status().code.to_string(),
+            ])
+            .observe(start.elapsed().as_secs_f64());
+    }
+}
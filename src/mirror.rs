@@ -0,0 +1,627 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use client::models::{AiPilot, AipVersion, MatchResult, TeamInfo, match_result::Winner};
+use sqlx::{Sqlite, prelude::FromRow, query::QueryAs, sqlite::SqliteArguments};
+use uuid::Uuid;
+
+use crate::{SqliteClient, api_error::ApiErrors};
+
+// Read and write access to the local copy of upstream pilots and matches.
+// Rows are converted back into the `client` models so handlers don't care
+// whether data came from SQLite or the upstream API.
+
+#[derive(Debug, FromRow)]
+struct PilotRow {
+    id: String,
+    name: String,
+    owner_id: String,
+    current_version: i32,
+}
+
+#[derive(Debug, FromRow)]
+struct PilotVersionRow {
+    pilot_id: String,
+    version: i32,
+    upload_id: String,
+}
+
+#[derive(Debug, FromRow)]
+struct MatchRow {
+    id: String,
+    team_a_id: String,
+    team_a_version: i32,
+    team_b_id: String,
+    team_b_version: i32,
+    winner: i64,
+    manual_run: bool,
+    created_at: i64,
+    normalized_name: String,
+    replay_id: Option<String>,
+}
+
+/// Bumped after every write that changed a row, so anything derived from the mirror knows to recompute.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Changes whenever pilots or matches were written.
+pub fn generation() -> u64 {
+    GENERATION.load(Ordering::Acquire)
+}
+
+fn parse_uuid(id: &str) -> Uuid {
+    Uuid::parse_str(id).unwrap_or_default()
+}
+
+fn winner_to_db(winner: Winner) -> i64 {
+    winner as i64
+}
+
+fn winner_from_db(winner: i64) -> Winner {
+    match winner {
+        0 => Winner::TeamA,
+        1 => Winner::TeamB,
+        _ => Winner::Unknown,
+    }
+}
+
+impl From<MatchRow> for MatchResult {
+    fn from(row: MatchRow) -> Self {
+        MatchResult {
+            id: parse_uuid(&row.id),
+            team_a: TeamInfo::new(parse_uuid(&row.team_a_id), row.team_a_version),
+            team_b: TeamInfo::new(parse_uuid(&row.team_b_id), row.team_b_version),
+            winner: winner_from_db(row.winner),
+            manual_run: row.manual_run,
+            created_at: row.created_at,
+            normalized_name: row.normalized_name,
+            replay_id: row.replay_id,
+        }
+    }
+}
+
+fn assemble_pilots(pilots: Vec<PilotRow>, versions: Vec<PilotVersionRow>) -> Vec<AiPilot> {
+    let mut versions_by_pilot: HashMap<String, Vec<AipVersion>> = HashMap::new();
+    for v in versions {
+        versions_by_pilot
+            .entry(v.pilot_id)
+            .or_default()
+            .push(AipVersion::new(v.version, v.upload_id));
+    }
+
+    pilots
+        .into_iter()
+        .map(|p| {
+            let versions = versions_by_pilot.remove(&p.id).unwrap_or_default();
+            let current = versions
+                .iter()
+                .find(|v| v.version == p.current_version)
+                .cloned()
+                .unwrap_or_else(|| AipVersion::new(p.current_version, String::new()));
+
+            AiPilot::new(parse_uuid(&p.id), p.name, p.owner_id, current, versions)
+        })
+        .collect()
+}
+
+fn db_error(action: &str, e: sqlx::Error) -> ApiErrors {
+    log::error!("Failed to {}: {}", action, e);
+    ApiErrors::InternalError(format!("Failed to {}", action))
+}
+
+pub async fn get_pilots(client: &SqliteClient) -> Result<Vec<AiPilot>, ApiErrors> {
+    let pilots = sqlx::query_as::<_, PilotRow>(
+        r#"
+        SELECT id, name, owner_id, current_version
+        FROM pilots
+        ORDER BY name
+        "#,
+    )
+    .fetch_all(client)
+    .await
+    .map_err(|e| db_error("fetch pilots", e))?;
+
+    let versions = sqlx::query_as::<_, PilotVersionRow>(
+        r#"
+        SELECT pilot_id, version, upload_id
+        FROM pilot_versions
+        ORDER BY version
+        "#,
+    )
+    .fetch_all(client)
+    .await
+    .map_err(|e| db_error("fetch pilot versions", e))?;
+
+    Ok(assemble_pilots(pilots, versions))
+}
+
+async fn get_pilot_where(
+    column: &str,
+    value: &str,
+    client: &SqliteClient,
+) -> Result<Option<AiPilot>, ApiErrors> {
+    let pilot = sqlx::query_as::<_, PilotRow>(&format!(
+        r#"
+        SELECT id, name, owner_id, current_version
+        FROM pilots
+        WHERE {} = $1
+        "#,
+        column
+    ))
+    .bind(value)
+    .fetch_optional(client)
+    .await
+    .map_err(|e| db_error("fetch pilot", e))?;
+
+    let Some(pilot) = pilot else {
+        return Ok(None);
+    };
+
+    let versions = sqlx::query_as::<_, PilotVersionRow>(
+        r#"
+        SELECT pilot_id, version, upload_id
+        FROM pilot_versions
+        WHERE pilot_id = $1
+        ORDER BY version
+        "#,
+    )
+    .bind(&pilot.id)
+    .fetch_all(client)
+    .await
+    .map_err(|e| db_error("fetch pilot versions", e))?;
+
+    Ok(assemble_pilots(vec![pilot], versions).pop())
+}
+
+pub async fn get_pilot(
+    pilot_id: &Uuid,
+    client: &SqliteClient,
+) -> Result<Option<AiPilot>, ApiErrors> {
+    get_pilot_where("id", &pilot_id.to_string(), client).await
+}
+
+pub async fn get_pilot_by_name(
+    name: &str,
+    client: &SqliteClient,
+) -> Result<Option<AiPilot>, ApiErrors> {
+    get_pilot_where("name", name, client).await
+}
+
+/// A pilot version and when the mirror first saw it.
+#[derive(Debug, Clone, FromRow)]
+pub struct SeenVersion {
+    pub pilot_id: String,
+    pub version: i32,
+    pub upload_id: String,
+    /// Milliseconds since epoch
+    pub seen_at: i64,
+}
+
+/// The `limit` most recently seen versions, optionally only those of one pilot.
+/// Versions mirrored before `seen_at` was tracked and never played are left out.
+pub async fn get_recent_versions(
+    pilot_id: Option<&Uuid>,
+    limit: u32,
+    client: &SqliteClient,
+) -> Result<Vec<SeenVersion>, ApiErrors> {
+    sqlx::query_as::<_, SeenVersion>(
+        r#"
+        SELECT pilot_id, version, upload_id, seen_at
+        FROM pilot_versions
+        WHERE seen_at IS NOT NULL
+          AND ($1 IS NULL OR pilot_id = $1)
+        ORDER BY seen_at DESC, version DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(pilot_id.map(|id| id.to_string()))
+    .bind(limit)
+    .fetch_all(client)
+    .await
+    .map_err(|e| db_error("fetch recent versions", e))
+}
+
+/// Pilot id to name lookup for rendering match participants.
+pub async fn get_pilot_names(client: &SqliteClient) -> Result<HashMap<Uuid, String>, ApiErrors> {
+    let rows = sqlx::query_as::<_, (String, String)>(
+        r#"
+        SELECT id, name
+        FROM pilots
+        "#,
+    )
+    .fetch_all(client)
+    .await
+    .map_err(|e| db_error("fetch pilot names", e))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(id, name)| (parse_uuid(&id), name))
+        .collect())
+}
+
+/// Matches involving `pilot_id` (optionally a specific version of it), newest first.
+/// Without a pilot every match is returned.
+pub async fn get_matches(
+    pilot_id: Option<&Uuid>,
+    version: Option<i32>,
+    client: &SqliteClient,
+) -> Result<Vec<MatchResult>, ApiErrors> {
+    let rows = sqlx::query_as::<_, MatchRow>(
+        r#"
+        SELECT id, team_a_id, team_a_version, team_b_id, team_b_version,
+               winner, manual_run, created_at, normalized_name, replay_id
+        FROM matches
+        WHERE $1 IS NULL
+           OR (team_a_id = $1 AND ($2 IS NULL OR team_a_version = $2))
+           OR (team_b_id = $1 AND ($2 IS NULL OR team_b_version = $2))
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(pilot_id.map(|id| id.to_string()))
+    .bind(version)
+    .fetch_all(client)
+    .await
+    .map_err(|e| db_error("fetch matches", e))?;
+
+    Ok(rows.into_iter().map(MatchResult::from).collect())
+}
+
+/// Filters for [`get_matches_page`], with pilot names already resolved to ids.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MatchFilter {
+    pub pilot_id: Option<Uuid>,
+    /// Version of `pilot_id`, ignored without it
+    pub version: Option<i32>,
+    pub opponent_id: Option<Uuid>,
+    pub winner_id: Option<Uuid>,
+    pub manual: Option<bool>,
+    pub has_replay: Option<bool>,
+    /// Inclusive lower bound in milliseconds
+    pub from: Option<i64>,
+    /// Exclusive upper bound in milliseconds
+    pub to: Option<i64>,
+    pub oldest_first: bool,
+}
+
+/// Position after the last match of a page, matches are ordered by `(created_at, id)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchCursor {
+    pub created_at: i64,
+    pub id: String,
+}
+
+impl fmt::Display for MatchCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.created_at, self.id)
+    }
+}
+
+impl FromStr for MatchCursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (created_at, id) = s.split_once('.').ok_or("Invalid cursor")?;
+        let created_at = created_at.parse().map_err(|_| "Invalid cursor")?;
+        let id = Uuid::parse_str(id).map_err(|_| "Invalid cursor")?;
+
+        Ok(MatchCursor {
+            created_at,
+            id: id.to_string(),
+        })
+    }
+}
+
+/// Conditions of [`MatchFilter`], binds `$1` through `$8` in field order.
+const MATCH_FILTER_SQL: &str = r#"
+    ($1 IS NULL
+     OR (team_a_id = $1 AND ($2 IS NULL OR team_a_version = $2) AND ($3 IS NULL OR team_b_id = $3))
+     OR (team_b_id = $1 AND ($2 IS NULL OR team_b_version = $2) AND ($3 IS NULL OR team_a_id = $3)))
+    AND ($1 IS NOT NULL OR $3 IS NULL OR team_a_id = $3 OR team_b_id = $3)
+    AND ($4 IS NULL OR (winner = 0 AND team_a_id = $4) OR (winner = 1 AND team_b_id = $4))
+    AND ($5 IS NULL OR manual_run = $5)
+    AND ($6 IS NULL OR (replay_id IS NOT NULL AND TRIM(replay_id) != '') = $6)
+    AND ($7 IS NULL OR created_at >= $7)
+    AND ($8 IS NULL OR created_at < $8)
+"#;
+
+impl MatchFilter {
+    fn bind<'q, O>(
+        &self,
+        query: QueryAs<'q, Sqlite, O, SqliteArguments<'q>>,
+    ) -> QueryAs<'q, Sqlite, O, SqliteArguments<'q>> {
+        query
+            .bind(self.pilot_id.map(|id| id.to_string()))
+            .bind(self.version)
+            .bind(self.opponent_id.map(|id| id.to_string()))
+            .bind(self.winner_id.map(|id| id.to_string()))
+            .bind(self.manual)
+            .bind(self.has_replay)
+            .bind(self.from)
+            .bind(self.to)
+    }
+}
+
+/// Number of matches passing `filter`.
+pub async fn count_matches(filter: &MatchFilter, client: &SqliteClient) -> Result<i64, ApiErrors> {
+    let (count,) = filter
+        .bind(sqlx::query_as::<_, (i64,)>(&format!(
+            r#"
+            SELECT COUNT(*)
+            FROM matches
+            WHERE {MATCH_FILTER_SQL}
+            "#,
+        )))
+        .fetch_one(client)
+        .await
+        .map_err(|e| db_error("count matches", e))?;
+
+    Ok(count)
+}
+
+/// Up to `limit` matches passing `filter` after `cursor`, plus the cursor of the next page
+/// if there is one.
+pub async fn get_matches_page(
+    filter: &MatchFilter,
+    cursor: Option<&MatchCursor>,
+    limit: u32,
+    client: &SqliteClient,
+) -> Result<(Vec<MatchResult>, Option<MatchCursor>), ApiErrors> {
+    let (direction, comparison) = if filter.oldest_first {
+        ("ASC", ">")
+    } else {
+        ("DESC", "<")
+    };
+
+    let sql = format!(
+        r#"
+        SELECT id, team_a_id, team_a_version, team_b_id, team_b_version,
+               winner, manual_run, created_at, normalized_name, replay_id
+        FROM matches
+        WHERE {MATCH_FILTER_SQL}
+          AND ($9 IS NULL OR (created_at, id) {comparison} ($9, $10))
+        ORDER BY created_at {direction}, id {direction}
+        LIMIT $11
+        "#,
+    );
+    let mut rows = filter
+        .bind(sqlx::query_as::<_, MatchRow>(&sql))
+        .bind(cursor.map(|c| c.created_at))
+        .bind(cursor.map(|c| c.id.clone()))
+        // One extra row tells whether there is a next page
+        .bind(i64::from(limit) + 1)
+        .fetch_all(client)
+        .await
+        .map_err(|e| db_error("fetch matches", e))?;
+
+    let next = if rows.len() > limit as usize {
+        rows.truncate(limit as usize);
+        rows.last().map(|row| MatchCursor {
+            created_at: row.created_at,
+            id: row.id.clone(),
+        })
+    } else {
+        None
+    };
+
+    Ok((rows.into_iter().map(MatchResult::from).collect(), next))
+}
+
+/// Looks up a pilot by id, or by name if `name_or_id` isn't a UUID.
+pub async fn resolve_pilot_id(
+    name_or_id: &str,
+    client: &SqliteClient,
+) -> Result<Option<Uuid>, ApiErrors> {
+    if let Ok(id) = Uuid::parse_str(name_or_id) {
+        return Ok(Some(id));
+    }
+
+    Ok(get_pilot_by_name(name_or_id, client).await?.map(|p| p.id))
+}
+
+pub async fn get_match(
+    match_id: &str,
+    client: &SqliteClient,
+) -> Result<Option<MatchResult>, ApiErrors> {
+    let row = sqlx::query_as::<_, MatchRow>(
+        r#"
+        SELECT id, team_a_id, team_a_version, team_b_id, team_b_version,
+               winner, manual_run, created_at, normalized_name, replay_id
+        FROM matches
+        WHERE id = $1
+        "#,
+    )
+    .bind(match_id)
+    .fetch_optional(client)
+    .await
+    .map_err(|e| db_error("fetch match", e))?;
+
+    Ok(row.map(MatchResult::from))
+}
+
+/// Stores `pilots`, returning each version that was not mirrored before along with its pilot.
+pub async fn upsert_pilots(
+    pilots: &[AiPilot],
+    client: &SqliteClient,
+) -> Result<Vec<(AiPilot, i32)>, sqlx::Error> {
+    let mut tx = client.begin().await?;
+    let mut inserted = Vec::new();
+    let mut changed = 0;
+    let seen_at = chrono::Utc::now().timestamp_millis();
+
+    for pilot in pilots {
+        let res = sqlx::query(
+            r#"
+            INSERT INTO pilots (id, name, owner_id, current_version, synced_at)
+            VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP)
+            ON CONFLICT (id) DO UPDATE SET
+                name = EXCLUDED.name,
+                owner_id = EXCLUDED.owner_id,
+                current_version = EXCLUDED.current_version,
+                synced_at = EXCLUDED.synced_at
+            WHERE pilots.name IS NOT EXCLUDED.name
+               OR pilots.owner_id IS NOT EXCLUDED.owner_id
+               OR pilots.current_version IS NOT EXCLUDED.current_version
+            "#,
+        )
+        .bind(pilot.id.to_string())
+        .bind(&pilot.name)
+        .bind(&pilot.owner_id)
+        .bind(pilot.current.version)
+        .execute(&mut *tx)
+        .await?;
+        changed += res.rows_affected();
+
+        for version in pilot.versions.iter().chain(std::iter::once(&pilot.current)) {
+            let res = sqlx::query(
+                r#"
+                INSERT INTO pilot_versions (pilot_id, version, upload_id, seen_at)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (pilot_id, version) DO NOTHING
+                "#,
+            )
+            .bind(pilot.id.to_string())
+            .bind(version.version)
+            .bind(&version.upload_id)
+            .bind(seen_at)
+            .execute(&mut *tx)
+            .await?;
+
+            if res.rows_affected() > 0 {
+                inserted.push((pilot.clone(), version.version));
+                changed += 1;
+                continue;
+            }
+
+            let res = sqlx::query(
+                r#"
+                UPDATE pilot_versions
+                SET upload_id = $3
+                WHERE pilot_id = $1 AND version = $2 AND upload_id IS NOT $3
+                "#,
+            )
+            .bind(pilot.id.to_string())
+            .bind(version.version)
+            .bind(&version.upload_id)
+            .execute(&mut *tx)
+            .await?;
+            changed += res.rows_affected();
+        }
+    }
+
+    tx.commit().await?;
+    if changed > 0 {
+        GENERATION.fetch_add(1, Ordering::AcqRel);
+    }
+    Ok(inserted)
+}
+
+/// Removes mirrored pilots that are not in `pilots` any more, along with their versions.
+/// Their matches stay, names fall back to the pilot id. Returns how many were removed.
+pub async fn prune_pilots(pilots: &[AiPilot], client: &SqliteClient) -> Result<u64, sqlx::Error> {
+    let ids: Vec<_> = pilots.iter().map(|p| p.id.to_string()).collect();
+    let ids = serde_json::to_string(&ids).expect("Ids are always serializable");
+
+    let mut tx = client.begin().await?;
+    sqlx::query(
+        r#"
+        DELETE FROM pilot_versions
+        WHERE pilot_id NOT IN (SELECT value FROM json_each($1))
+        "#,
+    )
+    .bind(&ids)
+    .execute(&mut *tx)
+    .await?;
+    let res = sqlx::query(
+        r#"
+        DELETE FROM pilots
+        WHERE id NOT IN (SELECT value FROM json_each($1))
+        "#,
+    )
+    .bind(&ids)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    if res.rows_affected() > 0 {
+        GENERATION.fetch_add(1, Ordering::AcqRel);
+    }
+    Ok(res.rows_affected())
+}
+
+/// Mirrored matches that have their replay, so upstream has nothing more to fill in.
+pub async fn get_settled_match_ids(client: &SqliteClient) -> Result<HashSet<Uuid>, sqlx::Error> {
+    let ids = sqlx::query_scalar::<_, String>(
+        r#"
+        SELECT id
+        FROM matches
+        WHERE replay_id IS NOT NULL
+        "#,
+    )
+    .fetch_all(client)
+    .await?;
+
+    Ok(ids.iter().map(|id| parse_uuid(id)).collect())
+}
+
+/// Stores `matches`, returning the ones that were not mirrored before.
+pub async fn upsert_matches(
+    matches: &[MatchResult],
+    client: &SqliteClient,
+) -> Result<Vec<MatchResult>, sqlx::Error> {
+    let mut tx = client.begin().await?;
+    let mut inserted = Vec::new();
+    let mut changed = 0;
+
+    for m in matches {
+        let res = sqlx::query(
+            r#"
+            INSERT INTO matches (id, team_a_id, team_a_version, team_b_id, team_b_version,
+                                 winner, manual_run, created_at, normalized_name, replay_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            ON CONFLICT (id) DO NOTHING
+            "#,
+        )
+        .bind(m.id.to_string())
+        .bind(m.team_a.aip_id.to_string())
+        .bind(m.team_a.version)
+        .bind(m.team_b.aip_id.to_string())
+        .bind(m.team_b.version)
+        .bind(winner_to_db(m.winner))
+        .bind(m.manual_run)
+        .bind(m.created_at)
+        .bind(&m.normalized_name)
+        .bind(&m.replay_id)
+        .execute(&mut *tx)
+        .await?;
+
+        if res.rows_affected() > 0 {
+            inserted.push(m.clone());
+            changed += 1;
+            continue;
+        }
+
+        // Results and replays can be filled in after the match was first seen
+        let res = sqlx::query(
+            r#"
+            UPDATE matches
+            SET winner = $2, replay_id = $3
+            WHERE id = $1 AND (winner IS NOT $2 OR replay_id IS NOT $3)
+            "#,
+        )
+        .bind(m.id.to_string())
+        .bind(winner_to_db(m.winner))
+        .bind(&m.replay_id)
+        .execute(&mut *tx)
+        .await?;
+        changed += res.rows_affected();
+    }
+
+    tx.commit().await?;
+    if changed > 0 {
+        GENERATION.fetch_add(1, Ordering::AcqRel);
+    }
+    Ok(inserted)
+}
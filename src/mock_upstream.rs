@@ -0,0 +1,372 @@
+use std::{
+    collections::VecDeque,
+    env,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use client::models::{
+    AiPilot, AipVersion, MatchResult, StartManualFight200Response, TeamInfo,
+    UploadAiPilot200Response, match_result::Winner,
+};
+use rocket::{
+    Build, Data, Request, Rocket, State,
+    data::ToByteUnit,
+    http::Status,
+    request::{FromRequest, Outcome},
+    serde::json::Json,
+};
+use uuid::Uuid;
+
+// In-memory stand-in for the upstream AIP API, implementing the endpoints the `client`
+// crate calls. Used by the `mock_upstream` binary for offline development and by tests.
+
+/// How the mock decides who wins a fight.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WinnerPolicy {
+    /// Team A, team B or undecided at random
+    Random,
+    Always(Winner),
+    /// Winners in order, then random once the script runs out
+    Scripted(VecDeque<Winner>),
+}
+
+impl FromStr for WinnerPolicy {
+    type Err = String;
+
+    /// `random`, `a`, `b`, `unknown`, or a comma separated script such as `a,b,unknown`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn winner(s: &str) -> Result<Winner, String> {
+            match s.trim() {
+                "a" => Ok(Winner::TeamA),
+                "b" => Ok(Winner::TeamB),
+                "unknown" => Ok(Winner::Unknown),
+                other => Err(format!("Unknown winner {}", other)),
+            }
+        }
+
+        match s {
+            "random" => Ok(WinnerPolicy::Random),
+            _ if s.contains(',') => s
+                .split(',')
+                .map(winner)
+                .collect::<Result<_, _>>()
+                .map(WinnerPolicy::Scripted),
+            _ => winner(s).map(WinnerPolicy::Always),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MockConfig {
+    /// Required in the `authorization` header of uploads, if set
+    pub api_key: Option<String>,
+    pub winner: WinnerPolicy,
+    /// How long a manual fight takes before its result shows up
+    pub fight_duration: Duration,
+    /// Pilots that exist from the start, named `<name>` and owned by `<owner>`
+    pub pilots: Vec<(String, String)>,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        MockConfig {
+            api_key: None,
+            winner: WinnerPolicy::Random,
+            fight_duration: Duration::ZERO,
+            pilots: Vec::new(),
+        }
+    }
+}
+
+impl MockConfig {
+    /// Reads `MOCK_API_KEY`, `MOCK_WINNER`, `MOCK_FIGHT_SECS` and `MOCK_PILOTS`
+    /// (comma separated `name:owner` pairs).
+    pub fn from_env() -> Self {
+        let winner = env::var("MOCK_WINNER")
+            .ok()
+            .map(|v| v.parse().expect("Invalid MOCK_WINNER"))
+            .unwrap_or(WinnerPolicy::Random);
+        let fight_duration = env::var("MOCK_FIGHT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(Duration::from_secs(5));
+        let pilots = env::var("MOCK_PILOTS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|p| p.split_once(':'))
+            .map(|(name, owner)| (name.trim().to_string(), owner.trim().to_string()))
+            .collect();
+
+        MockConfig {
+            api_key: env::var("MOCK_API_KEY").ok(),
+            winner,
+            fight_duration,
+            pilots,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    pilots: Vec<AiPilot>,
+    /// Includes fights still running, whose `created_at` is in the future
+    matches: Vec<MatchResult>,
+    winner: Option<WinnerPolicy>,
+}
+
+impl MockState {
+    fn upload(&mut self, name: &str, owner_id: &str) -> (Uuid, AiPilot) {
+        let upload_id = Uuid::new_v4();
+
+        let pilot = match self.pilots.iter_mut().find(|p| p.name == name) {
+            Some(pilot) => pilot,
+            None => {
+                self.pilots.push(AiPilot::new(
+                    Uuid::new_v4(),
+                    name.to_string(),
+                    owner_id.to_string(),
+                    AipVersion::default(),
+                    Vec::new(),
+                ));
+                self.pilots.last_mut().unwrap()
+            }
+        };
+        let version = AipVersion::new(pilot.current.version + 1, upload_id.to_string());
+        pilot.current = version.clone();
+        pilot.versions.push(version);
+
+        (upload_id, pilot.clone())
+    }
+
+    /// Like upstream, fights only take ids, anything else is an unknown pilot.
+    fn find_pilot(&self, id: &str) -> Option<&AiPilot> {
+        let id = Uuid::parse_str(id).ok()?;
+        self.pilots.iter().find(|p| p.id == id)
+    }
+
+    fn next_winner(&mut self) -> Winner {
+        let random = || match rand::random_range(0..3) {
+            0 => Winner::TeamA,
+            1 => Winner::TeamB,
+            _ => Winner::Unknown,
+        };
+
+        match self.winner.as_mut() {
+            Some(WinnerPolicy::Always(winner)) => *winner,
+            Some(WinnerPolicy::Scripted(script)) => script.pop_front().unwrap_or_else(random),
+            Some(WinnerPolicy::Random) | None => random(),
+        }
+    }
+}
+
+/// Shared state of a running mock, managed by its Rocket instance.
+pub struct MockUpstream {
+    state: Mutex<MockState>,
+    config: MockConfig,
+}
+
+impl MockUpstream {
+    pub fn new(config: MockConfig) -> Self {
+        let mut state = MockState {
+            winner: Some(config.winner.clone()),
+            ..Default::default()
+        };
+        for (name, owner) in &config.pilots {
+            state.upload(name, owner);
+        }
+
+        MockUpstream {
+            state: Mutex::new(state),
+            config,
+        }
+    }
+
+    /// Adds a finished match directly, e.g. to seed history for tests.
+    pub fn push_match(&self, m: MatchResult) {
+        self.state.lock().unwrap().matches.push(m);
+    }
+
+    pub fn pilots(&self) -> Vec<AiPilot> {
+        self.state.lock().unwrap().pilots.clone()
+    }
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+#[derive(FromForm)]
+struct PilotQuery<'r> {
+    name: Option<&'r str>,
+    id: Option<&'r str>,
+}
+
+#[get("/aipilot?<query..>")]
+fn get_ai_pilots(query: PilotQuery<'_>, mock: &State<MockUpstream>) -> Json<Vec<AiPilot>> {
+    let state = mock.state.lock().unwrap();
+    let pilots = state
+        .pilots
+        .iter()
+        .filter(|p| query.name.is_none_or(|name| p.name == name))
+        .filter(|p| query.id.is_none_or(|id| p.id.to_string() == id))
+        .cloned()
+        .collect();
+
+    Json(pilots)
+}
+
+/// The `authorization` header, checked against [`MockConfig::api_key`].
+struct Authorized;
+
+#[async_trait]
+impl<'r> FromRequest<'r> for Authorized {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Outcome::Success(mock) = request.guard::<&State<MockUpstream>>().await else {
+            return Outcome::Error((Status::InternalServerError, ()));
+        };
+
+        match &mock.config.api_key {
+            Some(key) if request.headers().get_one("authorization") != Some(key.as_str()) => {
+                Outcome::Error((Status::Unauthorized, ()))
+            }
+            _ => Outcome::Success(Authorized),
+        }
+    }
+}
+
+#[derive(FromForm)]
+struct UploadQuery<'r> {
+    name: &'r str,
+    #[field(name = "ownerId")]
+    owner_id: Option<&'r str>,
+}
+
+#[post("/aipilot?<query..>", data = "<data>")]
+async fn upload_ai_pilot(
+    _auth: Authorized,
+    query: UploadQuery<'_>,
+    data: Data<'_>,
+    mock: &State<MockUpstream>,
+) -> Result<Json<UploadAiPilot200Response>, Status> {
+    let data = data
+        .open(25.mebibytes())
+        .into_bytes()
+        .await
+        .map_err(|_| Status::BadRequest)?;
+    if !data.is_complete() || data.is_empty() {
+        return Err(Status::BadRequest);
+    }
+
+    let (upload_id, pilot) = mock
+        .state
+        .lock()
+        .unwrap()
+        .upload(query.name, query.owner_id.unwrap_or_default());
+
+    Ok(Json(UploadAiPilot200Response::new(
+        upload_id,
+        pilot.current.version,
+        pilot,
+    )))
+}
+
+#[derive(FromForm)]
+struct MatchQuery<'r> {
+    #[field(name = "aipId")]
+    aip_id: Option<&'r str>,
+    version: Option<i32>,
+    id: Option<&'r str>,
+}
+
+#[get("/matches?<query..>")]
+fn get_match_results(query: MatchQuery<'_>, mock: &State<MockUpstream>) -> Json<Vec<MatchResult>> {
+    let now = now_millis();
+    let state = mock.state.lock().unwrap();
+
+    let matches = state
+        .matches
+        .iter()
+        .filter(|m| m.created_at <= now)
+        .filter(|m| query.id.is_none_or(|id| m.id.to_string() == id))
+        .filter(|m| {
+            let Some(aip_id) = query.aip_id else {
+                return true;
+            };
+            [&m.team_a, &m.team_b].iter().any(|team| {
+                team.aip_id.to_string() == aip_id && query.version.is_none_or(|v| team.version == v)
+            })
+        })
+        .cloned()
+        .collect();
+
+    Json(matches)
+}
+
+#[derive(FromForm)]
+struct FightQuery<'r> {
+    #[field(name = "aipId1")]
+    aip_id1: &'r str,
+    #[field(name = "aipId2")]
+    aip_id2: &'r str,
+}
+
+/// Queues a fight between the current versions, the result appears after
+/// [`MockConfig::fight_duration`].
+// The client calls `/fight/`, which only a segments match covers
+#[get("/fight/<_..>?<query..>")]
+fn start_manual_fight(
+    query: FightQuery<'_>,
+    mock: &State<MockUpstream>,
+) -> Result<Json<StartManualFight200Response>, Status> {
+    let mut state = mock.state.lock().unwrap();
+    let (Some(a), Some(b)) = (
+        state.find_pilot(query.aip_id1).cloned(),
+        state.find_pilot(query.aip_id2).cloned(),
+    ) else {
+        return Err(Status::NotFound);
+    };
+
+    let match_id = Uuid::new_v4();
+    let winner = state.next_winner();
+    state.matches.push(MatchResult::new(
+        match_id,
+        TeamInfo::new(a.id, a.current.version),
+        TeamInfo::new(b.id, b.current.version),
+        winner,
+        true,
+        now_millis() + mock.config.fight_duration.as_millis() as i64,
+        format!("{} vs {}", a.name, b.name),
+        Some(match_id.to_string()),
+    ));
+
+    Ok(Json(StartManualFight200Response { match_id }))
+}
+
+/// Stand-in replay file for every match.
+#[get("/replay?<replayId>")]
+#[allow(non_snake_case)]
+fn get_replay(replayId: &str) -> String {
+    format!("mock replay {}\n", replayId)
+}
+
+/// The mock as a Rocket instance, configure the address through the usual `ROCKET_` variables.
+pub fn rocket(config: MockConfig) -> Rocket<Build> {
+    rocket::build().manage(MockUpstream::new(config)).mount(
+        "/",
+        routes![
+            get_ai_pilots,
+            upload_ai_pilot,
+            get_match_results,
+            start_manual_fight,
+            get_replay
+        ],
+    )
+}
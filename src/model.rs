@@ -1,12 +1,36 @@
+use std::env;
+
 use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
 use rocket::serde::{Deserialize, Serialize};
-
+use sha2::Sha256;
 use sqlx::prelude::FromRow;
 
-use crate::{SqliteClient, api_error::ApiErrors};
+use crate::{SqliteClient, api_error::ApiErrors, scope::Scopes};
+
+pub use aip_types::model::{NewUserToken, UserId, UserToken, UserTokenId};
 
-pub type UserId = i64;
-pub type UserTokenId = i64;
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, sqlx::Type,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum Role {
+    #[default]
+    User,
+    Admin,
+}
+
+/// Discord ids listed in `ADMIN_DISCORD_IDS` (comma separated), promoted to admin on startup and login.
+pub fn admin_discord_ids() -> Vec<String> {
+    env::var("ADMIN_DISCORD_IDS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect()
+}
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema, FromRow)]
 pub struct User {
@@ -14,6 +38,10 @@ pub struct User {
     pub discord_id: String,
     pub username: String,
     pub avatar_url: String,
+    pub role: Role,
+    /// Banned users can't upload pilots or create matches
+    pub banned_at: Option<DateTime<Utc>>,
+    pub ban_reason: Option<String>,
 }
 
 impl User {
@@ -23,17 +51,23 @@ impl User {
         avatar_url: &str,
         client: &SqliteClient,
     ) -> Result<User, sqlx::Error> {
+        let is_admin = admin_discord_ids().iter().any(|id| id == discord_id);
+
         let res = sqlx::query_as::<_, User>(
             r#"
-            INSERT INTO users (discord_id, username, avatar_url)
-            VALUES ($1, $2, $3)
-            ON CONFLICT (discord_id) DO UPDATE SET username = EXCLUDED.username, avatar_url = EXCLUDED.avatar_url
-            RETURNING id, discord_id, username, avatar_url
+            INSERT INTO users (discord_id, username, avatar_url, role)
+            VALUES ($1, $2, $3, CASE WHEN $4 THEN 'admin' ELSE 'user' END)
+            ON CONFLICT (discord_id) DO UPDATE SET
+                username = EXCLUDED.username,
+                avatar_url = EXCLUDED.avatar_url,
+                role = CASE WHEN $4 THEN 'admin' ELSE users.role END
+            RETURNING id, discord_id, username, avatar_url, role, banned_at, ban_reason
             "#,
         )
         .bind(discord_id)
         .bind(username)
         .bind(avatar_url)
+        .bind(is_admin)
         .fetch_one(client)
         .await?;
 
@@ -43,7 +77,7 @@ impl User {
     pub async fn all(client: &SqliteClient) -> Result<Vec<User>, sqlx::Error> {
         let res = sqlx::query_as::<_, User>(
             r#"
-            SELECT id, discord_id, username, avatar_url
+            SELECT id, discord_id, username, avatar_url, role, banned_at, ban_reason
             FROM users
             "#,
         )
@@ -56,7 +90,7 @@ impl User {
     pub async fn get_by_id(id: UserId, client: &SqliteClient) -> Result<User, sqlx::Error> {
         let res = sqlx::query_as::<_, User>(
             r#"
-            SELECT id, discord_id, username, avatar_url
+            SELECT id, discord_id, username, avatar_url, role, banned_at, ban_reason
             FROM users
             WHERE id = $1
             "#,
@@ -68,73 +102,184 @@ impl User {
         Ok(res)
     }
 
-    pub async fn get_user_by_user_token(
-        token: &str,
+    /// Gives every user in [`admin_discord_ids`] the admin role.
+    pub async fn promote_admins(client: &SqliteClient) -> Result<u64, sqlx::Error> {
+        let mut promoted = 0;
+        for discord_id in admin_discord_ids() {
+            let res = sqlx::query(
+                r#"
+                UPDATE users
+                SET role = 'admin'
+                WHERE discord_id = $1 AND role != 'admin'
+                "#,
+            )
+            .bind(discord_id)
+            .execute(client)
+            .await?;
+
+            promoted += res.rows_affected();
+        }
+
+        Ok(promoted)
+    }
+
+    /// Bans the user with `reason`, or lifts the ban if `reason` is `None`.
+    pub async fn set_ban(
+        id: UserId,
+        reason: Option<&str>,
         client: &SqliteClient,
-    ) -> Result<User, ApiErrors> {
+    ) -> Result<User, sqlx::Error> {
         let res = sqlx::query_as::<_, User>(
             r#"
-            SELECT users.id, users.discord_id, users.username, users.avatar_url
-            FROM users
-            INNER JOIN user_tokens ON users.id = user_tokens.user_id
-            WHERE user_tokens.token = $1 AND (user_tokens.expires_at > $2 OR user_tokens.expires_at IS NULL)
+            UPDATE users
+            SET banned_at = CASE WHEN $2 IS NULL THEN NULL ELSE $3 END, ban_reason = $2
+            WHERE id = $1
+            RETURNING id, discord_id, username, avatar_url, role, banned_at, ban_reason
             "#,
         )
-        .bind(token)
+        .bind(id)
+        .bind(reason)
         .bind(Utc::now())
         .fetch_one(client)
-        .await
-        .map_err(|e| {
-            log::error!("Failed to fetch user by token: {}", e);
-            ApiErrors::InternalError("Failed to fetch user by token".into())
-        })?;
+        .await?;
 
         Ok(res)
     }
+
+    /// Fails with 403 if the user is banned from uploads and match creation.
+    pub async fn ensure_not_banned(id: UserId, client: &SqliteClient) -> Result<(), ApiErrors> {
+        let user = Self::get_by_id(id, client).await.map_err(|e| {
+            log::error!("Failed to fetch user: {}", e);
+            ApiErrors::InternalError("Failed to fetch user".into())
+        })?;
+
+        match user.banned_at {
+            Some(_) => Err(ApiErrors::Forbidden(format!(
+                "You are banned from uploads and match creation: {}",
+                user.ban_reason.unwrap_or_default()
+            ))),
+            None => Ok(()),
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema, FromRow)]
-pub struct UserToken {
-    pub id: UserTokenId,
-    pub name: String,
-    pub user_id: UserId,
-    pub token: String,
-    pub created_at: DateTime<Utc>,
-    pub expires_at: Option<DateTime<Utc>>,
+lazy_static! {
+    static ref TOKEN_HASH_KEY: Vec<u8> = env::var("TOKEN_HASH_KEY")
+        .ok()
+        .filter(|key| !key.is_empty())
+        .expect("TOKEN_HASH_KEY must be set")
+        .into_bytes();
+}
+
+/// Number of leading characters of a token secret stored in plaintext for lookups.
+const TOKEN_PREFIX_LEN: usize = 12;
+
+fn token_mac(secret: &str) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(&TOKEN_HASH_KEY).expect("HMAC accepts keys of any length");
+    mac.update(secret.as_bytes());
+    mac
+}
+
+fn hash_token(secret: &str) -> String {
+    hex::encode(token_mac(secret).finalize().into_bytes())
+}
+
+fn token_prefix(secret: &str) -> &str {
+    let end = secret
+        .char_indices()
+        .nth(TOKEN_PREFIX_LEN)
+        .map(|(i, _)| i)
+        .unwrap_or(secret.len());
+    &secret[..end]
+}
+
+#[derive(FromRow)]
+struct TokenHashRow {
+    id: UserTokenId,
+    token_hash: String,
 }
 
-impl UserToken {
-    pub async fn insert_user_token(
+/// Storage of user tokens. [`UserToken`] is shared with the CLI through `aip_types`, so its
+/// queries live in this trait rather than an inherent impl.
+#[async_trait]
+pub trait UserTokenStore {
+    async fn insert_user_token(
         name: String,
         user_id: UserId,
         expires_at: Option<DateTime<Utc>>,
+        scopes: &Scopes,
         client: &SqliteClient,
-    ) -> Result<UserToken, sqlx::Error> {
-        let res = sqlx::query_as::<_, UserToken>(
+    ) -> Result<NewUserToken, sqlx::Error>;
+
+    async fn get_by_user_id(
+        user_id: UserId,
+        client: &SqliteClient,
+    ) -> Result<Vec<UserToken>, ApiErrors>;
+
+    /// Finds the unexpired token matching `secret`. Candidates are narrowed down by prefix
+    /// and the hashes compared in constant time.
+    async fn get_by_secret(secret: &str, client: &SqliteClient) -> Result<UserToken, ApiErrors>;
+
+    /// Reads `TOKEN_HASH_KEY`, so a missing key stops the server at startup rather than
+    /// failing the first request that creates or checks a token.
+    fn init_hash_key();
+
+    /// Replaces tokens created before hashing was introduced with their hash.
+    async fn hash_plaintext_tokens(client: &SqliteClient) -> Result<u64, sqlx::Error>;
+
+    /// Deletes the token if it belongs to `user_id`, returns whether it did.
+    async fn delete_by_id_and_user_id(
+        id: UserTokenId,
+        user_id: UserId,
+        client: &SqliteClient,
+    ) -> Result<bool, sqlx::Error>;
+
+    /// Revokes a token regardless of owner, returns whose it was if it existed.
+    async fn delete_by_id(
+        id: UserTokenId,
+        client: &SqliteClient,
+    ) -> Result<Option<UserId>, sqlx::Error>;
+}
+
+#[async_trait]
+impl UserTokenStore for UserToken {
+    async fn insert_user_token(
+        name: String,
+        user_id: UserId,
+        expires_at: Option<DateTime<Utc>>,
+        scopes: &Scopes,
+        client: &SqliteClient,
+    ) -> Result<NewUserToken, sqlx::Error> {
+        let secret = format!("aip_{}", hex::encode(rand::random::<[u8; 24]>()));
+
+        let token = sqlx::query_as::<_, UserToken>(
             r#"
-            INSERT INTO user_tokens (name, user_id, token, created_at, expires_at)
-            VALUES ($1, $2, $3, $4, $5)
-            RETURNING id, name, user_id, token, created_at, expires_at
+            INSERT INTO user_tokens (name, user_id, token, token_prefix, token_hash, created_at, expires_at, scopes)
+            VALUES ($1, $2, '', $3, $4, $5, $6, $7)
+            RETURNING id, name, user_id, token_prefix, created_at, expires_at, last_used_at, scopes
             "#,
         )
         .bind(name)
         .bind(user_id)
-        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(token_prefix(&secret))
+        .bind(hash_token(&secret))
         .bind(Utc::now())
         .bind(expires_at)
+        .bind(scopes.to_string())
         .fetch_one(client)
         .await?;
 
-        Ok(res)
+        Ok(NewUserToken { token, secret })
     }
 
-    pub async fn get_by_user_id(
+    async fn get_by_user_id(
         user_id: UserId,
         client: &SqliteClient,
     ) -> Result<Vec<UserToken>, ApiErrors> {
         let res = sqlx::query_as::<_, UserToken>(
             r#"
-            SELECT id, name, user_id, token, created_at, expires_at
+            SELECT id, name, user_id, token_prefix, created_at, expires_at, last_used_at, scopes
             FROM user_tokens
             WHERE user_id = $1
             "#,
@@ -150,15 +295,42 @@ impl UserToken {
         Ok(res)
     }
 
-    pub async fn get_by_token(token: &str, client: &SqliteClient) -> Result<UserToken, ApiErrors> {
-        let res = sqlx::query_as::<_, UserToken>(
+    async fn get_by_secret(secret: &str, client: &SqliteClient) -> Result<UserToken, ApiErrors> {
+        let candidates = sqlx::query_as::<_, TokenHashRow>(
             r#"
-            SELECT id, name, user_id, token, created_at, expires_at
+            SELECT id, token_hash
             FROM user_tokens
-            WHERE token = $1
+            WHERE token_prefix = $1 AND (expires_at > $2 OR expires_at IS NULL)
+            "#,
+        )
+        .bind(token_prefix(secret))
+        .bind(Utc::now())
+        .fetch_all(client)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to fetch user token: {}", e);
+            ApiErrors::InternalError("Failed to fetch user token".into())
+        })?;
+
+        let id = candidates
+            .into_iter()
+            .find(|c| {
+                hex::decode(&c.token_hash)
+                    .is_ok_and(|hash| token_mac(secret).verify_slice(&hash).is_ok())
+            })
+            .map(|c| c.id)
+            .ok_or_else(|| ApiErrors::NotFound("User token not found".into()))?;
+
+        let res = sqlx::query_as::<_, UserToken>(
+            r#"
+            UPDATE user_tokens
+            SET last_used_at = $2
+            WHERE id = $1
+            RETURNING id, name, user_id, token_prefix, created_at, expires_at, last_used_at, scopes
             "#,
         )
-        .bind(token)
+        .bind(id)
+        .bind(Utc::now())
         .fetch_one(client)
         .await
         .map_err(|e| {
@@ -169,12 +341,47 @@ impl UserToken {
         Ok(res)
     }
 
-    pub async fn delete_by_id_and_user_id(
+    fn init_hash_key() {
+        lazy_static::initialize(&TOKEN_HASH_KEY);
+    }
+
+    async fn hash_plaintext_tokens(client: &SqliteClient) -> Result<u64, sqlx::Error> {
+        let plaintext = sqlx::query_as::<_, (UserTokenId, String)>(
+            r#"
+            SELECT id, token
+            FROM user_tokens
+            WHERE token != ''
+            "#,
+        )
+        .fetch_all(client)
+        .await?;
+
+        let mut tx = client.begin().await?;
+        for (id, secret) in &plaintext {
+            sqlx::query(
+                r#"
+                UPDATE user_tokens
+                SET token = '', token_prefix = $2, token_hash = $3
+                WHERE id = $1
+                "#,
+            )
+            .bind(id)
+            .bind(token_prefix(secret))
+            .bind(hash_token(secret))
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(plaintext.len() as u64)
+    }
+
+    async fn delete_by_id_and_user_id(
         id: UserTokenId,
         user_id: UserId,
         client: &SqliteClient,
-    ) -> Result<(), sqlx::Error> {
-        sqlx::query(
+    ) -> Result<bool, sqlx::Error> {
+        let res = sqlx::query(
             r#"
             DELETE FROM user_tokens
             WHERE id = $1 AND user_id = $2
@@ -185,8 +392,132 @@ impl UserToken {
         .execute(client)
         .await?;
 
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn delete_by_id(
+        id: UserTokenId,
+        client: &SqliteClient,
+    ) -> Result<Option<UserId>, sqlx::Error> {
+        sqlx::query_scalar::<_, UserId>(
+            r#"
+            DELETE FROM user_tokens
+            WHERE id = $1
+            RETURNING user_id
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(client)
+        .await
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum UsageKind {
+    Upload,
+    MatchCreate,
+    TournamentCreate,
+}
+
+/// A user action counted towards their usage, shown in the admin area.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, FromRow)]
+pub struct UsageEvent {
+    pub id: i64,
+    pub user_id: UserId,
+    pub kind: UsageKind,
+    pub detail: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl UsageEvent {
+    pub async fn record(
+        user_id: UserId,
+        kind: UsageKind,
+        detail: Option<&str>,
+        client: &SqliteClient,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO usage_events (user_id, kind, detail, created_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(user_id)
+        .bind(kind)
+        .bind(detail)
+        .bind(Utc::now())
+        .execute(client)
+        .await?;
+
         Ok(())
     }
+
+    /// Most recent events, optionally only those of one user.
+    pub async fn recent(
+        user_id: Option<UserId>,
+        limit: i64,
+        client: &SqliteClient,
+    ) -> Result<Vec<UsageEvent>, sqlx::Error> {
+        sqlx::query_as::<_, UsageEvent>(
+            r#"
+            SELECT id, user_id, kind, detail, created_at
+            FROM usage_events
+            WHERE $1 IS NULL OR user_id = $1
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(client)
+        .await
+    }
+}
+
+/// A user with counts of what they have done, for the admin user list.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, FromRow)]
+pub struct UserUsage {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub user: User,
+    pub uploads: i64,
+    pub matches_created: i64,
+    pub uploads_last_day: i64,
+    pub matches_created_last_day: i64,
+    pub tokens: i64,
+    pub last_active_at: Option<DateTime<Utc>>,
+}
+
+impl UserUsage {
+    pub async fn all(client: &SqliteClient) -> Result<Vec<UserUsage>, sqlx::Error> {
+        sqlx::query_as::<_, UserUsage>(
+            r#"
+            SELECT users.id, users.discord_id, users.username, users.avatar_url, users.role,
+                   users.banned_at, users.ban_reason,
+                   (SELECT COUNT(*) FROM usage_events e
+                    WHERE e.user_id = users.id AND e.kind = 'upload') AS uploads,
+                   (SELECT COUNT(*) FROM usage_events e
+                    WHERE e.user_id = users.id AND e.kind = 'match_create') AS matches_created,
+                   (SELECT COUNT(*) FROM usage_events e
+                    WHERE e.user_id = users.id AND e.kind = 'upload' AND e.created_at > $1) AS uploads_last_day,
+                   (SELECT COUNT(*) FROM usage_events e
+                    WHERE e.user_id = users.id AND e.kind = 'match_create' AND e.created_at > $1) AS matches_created_last_day,
+                   (SELECT COUNT(*) FROM user_tokens t WHERE t.user_id = users.id) AS tokens,
+                   (SELECT MAX(at) FROM (
+                       SELECT e.created_at AS at FROM usage_events e WHERE e.user_id = users.id
+                       UNION ALL
+                       SELECT t.last_used_at FROM user_tokens t WHERE t.user_id = users.id
+                   )) AS last_active_at
+            FROM users
+            ORDER BY users.username
+            "#,
+        )
+        .bind(Utc::now() - chrono::Duration::days(1))
+        .fetch_all(client)
+        .await
+    }
 }
 
 pub trait ResultExt<T, E> {
@@ -198,3 +529,151 @@ impl<T, E> ResultExt<T, E> for Result<T, E> {
         self.map_err(|_| ApiErrors::NotFound(format!("{} not found", entity_name).into()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Once;
+
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+    use super::*;
+    use crate::scope::Scope;
+
+    /// A migrated in-memory database with one user.
+    async fn db() -> (SqliteClient, UserId) {
+        static ENV: Once = Once::new();
+        ENV.call_once(|| {
+            // SAFETY: set once, before any test reads the environment
+            unsafe { env::set_var("TOKEN_HASH_KEY", "test") }
+        });
+
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(SqliteConnectOptions::new().in_memory(true))
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&db).await.unwrap();
+        let user = User::upsert_by_discord_id("1", "user", "", &db)
+            .await
+            .unwrap();
+
+        (db, user.id)
+    }
+
+    async fn insert(
+        user_id: UserId,
+        expires_at: Option<DateTime<Utc>>,
+        db: &SqliteClient,
+    ) -> NewUserToken {
+        let scopes = Scopes(vec![Scope::PilotsRead]);
+        UserToken::insert_user_token("test".into(), user_id, expires_at, &scopes, db)
+            .await
+            .unwrap()
+    }
+
+    #[rocket::async_test]
+    async fn tokens_are_stored_hashed() {
+        let (db, user_id) = db().await;
+        let new = insert(user_id, None, &db).await;
+
+        let (token, prefix, hash) = sqlx::query_as::<_, (String, String, String)>(
+            "SELECT token, token_prefix, token_hash FROM user_tokens",
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(token, "");
+        assert_eq!(prefix, &new.secret[..TOKEN_PREFIX_LEN]);
+        assert_eq!(hash, hash_token(&new.secret));
+
+        let found = UserToken::get_by_secret(&new.secret, &db).await.unwrap();
+        assert_eq!(found.id, new.token.id);
+        assert!(found.last_used_at.is_some());
+    }
+
+    #[rocket::async_test]
+    async fn get_by_secret_checks_the_whole_secret() {
+        let (db, user_id) = db().await;
+        let a = insert(user_id, None, &db).await;
+        let b = insert(user_id, None, &db).await;
+
+        // Give b a secret sharing a's prefix, so both are candidates for either
+        let prefix = token_prefix(&a.secret).to_string();
+        let b_secret = format!("{}{}", prefix, "b".repeat(36));
+        sqlx::query("UPDATE user_tokens SET token_prefix = $2, token_hash = $3 WHERE id = $1")
+            .bind(b.token.id)
+            .bind(&prefix)
+            .bind(hash_token(&b_secret))
+            .execute(&db)
+            .await
+            .unwrap();
+
+        let found = UserToken::get_by_secret(&a.secret, &db).await.unwrap();
+        assert_eq!(found.id, a.token.id);
+        let found = UserToken::get_by_secret(&b_secret, &db).await.unwrap();
+        assert_eq!(found.id, b.token.id);
+
+        for wrong in [
+            format!("{}{}", prefix, "c".repeat(36)),
+            prefix.clone(),
+            a.secret[..a.secret.len() - 1].to_string(),
+        ] {
+            assert!(
+                matches!(
+                    UserToken::get_by_secret(&wrong, &db).await,
+                    Err(ApiErrors::NotFound(_))
+                ),
+                "{}",
+                wrong
+            );
+        }
+    }
+
+    #[rocket::async_test]
+    async fn get_by_secret_skips_expired_tokens() {
+        let (db, user_id) = db().await;
+        let expired = insert(user_id, Some(Utc::now() - chrono::Duration::hours(1)), &db).await;
+
+        assert!(matches!(
+            UserToken::get_by_secret(&expired.secret, &db).await,
+            Err(ApiErrors::NotFound(_))
+        ));
+    }
+
+    #[rocket::async_test]
+    async fn hashes_plaintext_tokens() {
+        let (db, user_id) = db().await;
+        let fresh = insert(user_id, None, &db).await;
+
+        // As stored before tokens were hashed
+        let legacy = format!("aip_{}", "0123456789abcdef".repeat(3));
+        sqlx::query(
+            "INSERT INTO user_tokens (user_id, token, name, scopes) VALUES ($1, $2, 'old', 'pilots:read')",
+        )
+        .bind(user_id)
+        .bind(&legacy)
+        .execute(&db)
+        .await
+        .unwrap();
+        assert!(UserToken::get_by_secret(&legacy, &db).await.is_err());
+
+        assert_eq!(UserToken::hash_plaintext_tokens(&db).await.unwrap(), 1);
+        let plaintext: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM user_tokens WHERE token != ''")
+                .fetch_one(&db)
+                .await
+                .unwrap();
+        assert_eq!(plaintext, 0);
+
+        let found = UserToken::get_by_secret(&legacy, &db).await.unwrap();
+        assert_eq!(found.name, "old");
+        assert_eq!(found.token_prefix, "aip_01234567");
+        let found = UserToken::get_by_secret(&fresh.secret, &db).await.unwrap();
+        assert_eq!(found.id, fresh.token.id);
+
+        // Nothing left to do on the next start
+        assert_eq!(UserToken::hash_plaintext_tokens(&db).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,239 @@
+use std::{env, fmt, marker::PhantomData, str::FromStr};
+
+use chrono::Utc;
+use rocket::{
+    Request, State,
+    http::Status,
+    request::{FromRequest, Outcome},
+};
+use rocket_okapi::{
+    r#gen::OpenApiGenerator,
+    request::{OpenApiFromRequest, RequestHeaderInput},
+};
+
+use crate::{SqliteClient, cookie::ApiUser};
+
+/// Action with its own rate limit bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    MatchCreate,
+    PilotUpload,
+}
+
+impl Action {
+    pub const ALL: [Action; 2] = [Action::MatchCreate, Action::PilotUpload];
+
+    /// Environment variable overriding the quota, `<count>/<seconds>` or `off`.
+    fn env_var(&self) -> &'static str {
+        match self {
+            Action::MatchCreate => "RATE_LIMIT_MATCH_CREATE",
+            Action::PilotUpload => "RATE_LIMIT_PILOT_UPLOAD",
+        }
+    }
+
+    fn default_quota(&self) -> Quota {
+        match self {
+            Action::MatchCreate => Quota {
+                limit: 30,
+                window_secs: 60 * 60,
+            },
+            Action::PilotUpload => Quota {
+                limit: 10,
+                window_secs: 60 * 60,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::MatchCreate => write!(f, "match:create"),
+            Action::PilotUpload => write!(f, "pilot:upload"),
+        }
+    }
+}
+
+/// At most `limit` requests every `window_secs` seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    pub limit: u32,
+    pub window_secs: u32,
+}
+
+impl FromStr for Quota {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (limit, window) = s
+            .split_once('/')
+            .ok_or_else(|| format!("Expected <count>/<seconds>, got {}", s))?;
+        let limit = limit
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid count {}", limit))?;
+        let window_secs = window
+            .trim()
+            .parse()
+            .ok()
+            .filter(|w| *w > 0)
+            .ok_or_else(|| format!("Invalid window {}", window))?;
+
+        Ok(Quota { limit, window_secs })
+    }
+}
+
+/// Quotas per action, read from the environment at startup.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    quotas: Vec<(Action, Quota)>,
+}
+
+impl RateLimiter {
+    pub fn from_env() -> Self {
+        let quotas = Action::ALL
+            .iter()
+            .filter_map(|action| {
+                let quota = match env::var(action.env_var()) {
+                    Ok(value) if value == "off" => return None,
+                    Ok(value) => value.parse().unwrap_or_else(|e| {
+                        log::error!("Invalid {}: {}", action.env_var(), e);
+                        action.default_quota()
+                    }),
+                    Err(_) => action.default_quota(),
+                };
+                log::info!(
+                    "Rate limiting {} to {} per {}s",
+                    action,
+                    quota.limit,
+                    quota.window_secs
+                );
+                Some((*action, quota))
+            })
+            .collect();
+
+        RateLimiter { quotas }
+    }
+
+    pub fn quota(&self, action: Action) -> Option<Quota> {
+        self.quotas
+            .iter()
+            .find(|(a, _)| *a == action)
+            .map(|(_, quota)| *quota)
+    }
+
+    /// Counts a request by `subject`, returns the seconds until the next one is allowed if
+    /// the quota is used up.
+    pub async fn hit(
+        &self,
+        action: Action,
+        subject: &str,
+        client: &SqliteClient,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        let Some(quota) = self.quota(action) else {
+            return Ok(None);
+        };
+        let now = Utc::now().timestamp();
+
+        let (window_start, count) = sqlx::query_as::<_, (i64, i64)>(
+            r#"
+            INSERT INTO rate_limits (action, subject, window_start, count)
+            VALUES ($1, $2, $3, 1)
+            ON CONFLICT (action, subject) DO UPDATE SET
+                count = CASE WHEN window_start + $4 <= $3 THEN 1 ELSE count + 1 END,
+                window_start = CASE WHEN window_start + $4 <= $3 THEN $3 ELSE window_start END
+            RETURNING window_start, count
+            "#,
+        )
+        .bind(action.to_string())
+        .bind(subject)
+        .bind(now)
+        .bind(quota.window_secs)
+        .fetch_one(client)
+        .await?;
+
+        if count > quota.limit as i64 {
+            Ok(Some((window_start + quota.window_secs as i64 - now).max(1)))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Seconds until a rate limited request may be retried, left for the 429 catcher.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryAfter(pub i64);
+
+/// Action a route is rate limited under.
+pub trait LimitedAction: Send + Sync + 'static {
+    const ACTION: Action;
+}
+
+macro_rules! limited_action {
+    ($name:ident) => {
+        pub struct $name;
+
+        impl LimitedAction for $name {
+            const ACTION: Action = Action::$name;
+        }
+    };
+}
+
+limited_action!(MatchCreate);
+limited_action!(PilotUpload);
+
+/// Counts the request against the quota of `A`, keyed by the signed in user or the client IP.
+/// Fails with 429 once the quota is used up. Place it after the auth guard so rejected
+/// requests are not counted.
+pub struct RateLimited<A: LimitedAction>(PhantomData<A>);
+
+#[async_trait]
+impl<'r, A: LimitedAction> FromRequest<'r> for RateLimited<A> {
+    type Error = String;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let (Outcome::Success(limiter), Outcome::Success(client)) = (
+            request.guard::<&State<RateLimiter>>().await,
+            request.guard::<&State<SqliteClient>>().await,
+        ) else {
+            return Outcome::Error((
+                Status::InternalServerError,
+                "Rate limiter not configured".to_string(),
+            ));
+        };
+
+        let subject = match request.guard::<ApiUser>().await {
+            Outcome::Success(user) => format!("user:{}", user.id),
+            _ => match request.client_ip() {
+                Some(ip) => format!("ip:{}", ip),
+                None => "ip:unknown".to_string(),
+            },
+        };
+
+        match limiter.hit(A::ACTION, &subject, client).await {
+            Ok(None) => Outcome::Success(RateLimited(PhantomData)),
+            Ok(Some(retry_after)) => {
+                request.local_cache(|| RetryAfter(retry_after));
+                Outcome::Error((
+                    Status::TooManyRequests,
+                    format!("Rate limit for {} exceeded", A::ACTION),
+                ))
+            }
+            Err(e) => {
+                // Don't lock everyone out because the counter couldn't be written
+                log::error!("Failed to check rate limit for {}: {}", subject, e);
+                Outcome::Success(RateLimited(PhantomData))
+            }
+        }
+    }
+}
+
+impl<'a, A: LimitedAction> OpenApiFromRequest<'a> for RateLimited<A> {
+    fn from_request_input(
+        _gene: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::None)
+    }
+}
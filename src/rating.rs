@@ -0,0 +1,308 @@
+use std::{collections::HashMap, f64::consts::PI};
+
+use client::models::{MatchResult, match_result::Winner};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+// Glicko-2 system constants, see http://www.glicko.net/glicko/glicko2.pdf
+const DEFAULT_RATING: f64 = 1500.0;
+const DEFAULT_DEVIATION: f64 = 350.0;
+const DEFAULT_VOLATILITY: f64 = 0.06;
+const TAU: f64 = 0.5;
+const SCALE: f64 = 173.7178;
+const CONVERGENCE_TOLERANCE: f64 = 0.000001;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+    pub matches: u32,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Rating {
+            rating: DEFAULT_RATING,
+            deviation: DEFAULT_DEVIATION,
+            volatility: DEFAULT_VOLATILITY,
+            matches: 0,
+        }
+    }
+}
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt()
+}
+
+fn expected_score(mu: f64, mu_j: f64, phi_j: f64) -> f64 {
+    1.0 / (1.0 + (-g(phi_j) * (mu - mu_j)).exp())
+}
+
+impl Rating {
+    /// Rating with the deviation subtracted twice, i.e. the value we are ~95% sure the
+    /// pilot is at least as strong as. Used for ranking so new pilots don't top the list.
+    pub fn conservative(&self) -> f64 {
+        self.rating - 2.0 * self.deviation
+    }
+
+    /// Applies a single game against `opponent` as its own rating period.
+    /// `score` is 1.0 for a win and 0.0 for a loss.
+    fn update(&self, opponent: &Rating, score: f64) -> Rating {
+        self.update_period(&[(*opponent, score)])
+    }
+
+    /// Applies every game of one rating period at once, as `(opponent, score)` pairs
+    /// rated against the opponents' ratings at the start of the period.
+    fn update_period(&self, games: &[(Rating, f64)]) -> Rating {
+        let mu = (self.rating - DEFAULT_RATING) / SCALE;
+        let phi = self.deviation / SCALE;
+
+        let (mut inverse_v, mut improvement) = (0.0, 0.0);
+        for (opponent, score) in games {
+            let mu_j = (opponent.rating - DEFAULT_RATING) / SCALE;
+            let phi_j = opponent.deviation / SCALE;
+            let g_j = g(phi_j);
+            let e = expected_score(mu, mu_j, phi_j);
+            inverse_v += g_j * g_j * e * (1.0 - e);
+            improvement += g_j * (score - e);
+        }
+        let v = 1.0 / inverse_v;
+        let delta = v * improvement;
+
+        let volatility = self.next_volatility(phi, v, delta);
+
+        let phi_star = (phi * phi + volatility * volatility).sqrt();
+        let phi_prime = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+        let mu_prime = mu + phi_prime * phi_prime * improvement;
+
+        Rating {
+            rating: mu_prime * SCALE + DEFAULT_RATING,
+            deviation: phi_prime * SCALE,
+            volatility,
+            matches: self.matches + games.len() as u32,
+        }
+    }
+
+    /// Iterative volatility update (step 5 of the Glicko-2 paper, Illinois algorithm).
+    fn next_volatility(&self, phi: f64, v: f64, delta: f64) -> f64 {
+        let a = (self.volatility * self.volatility).ln();
+        let f = |x: f64| {
+            let ex = x.exp();
+            let denom = phi * phi + v + ex;
+            ex * (delta * delta - phi * phi - v - ex) / (2.0 * denom * denom)
+                - (x - a) / (TAU * TAU)
+        };
+
+        let mut big_a = a;
+        let mut big_b = if delta * delta > phi * phi + v {
+            (delta * delta - phi * phi - v).ln()
+        } else {
+            let mut k = 1.0;
+            while f(a - k * TAU) < 0.0 {
+                k += 1.0;
+            }
+            a - k * TAU
+        };
+
+        let mut f_a = f(big_a);
+        let mut f_b = f(big_b);
+        while (big_b - big_a).abs() > CONVERGENCE_TOLERANCE {
+            let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+            let f_c = f(big_c);
+            if f_c * f_b <= 0.0 {
+                big_a = big_b;
+                f_a = f_b;
+            } else {
+                f_a /= 2.0;
+            }
+            big_b = big_c;
+            f_b = f_c;
+        }
+
+        (big_a / 2.0).exp()
+    }
+}
+
+/// Ratings for every pilot and pilot version, built by replaying match history.
+#[derive(Debug, Clone, Default)]
+pub struct Ratings {
+    pilots: HashMap<Uuid, Rating>,
+    versions: HashMap<(Uuid, i32), Rating>,
+}
+
+impl Ratings {
+    /// Replays `matches` oldest first. Matches with an unknown winner are skipped.
+    pub fn from_matches(matches: &[MatchResult]) -> Self {
+        let mut ratings = Ratings::default();
+        ratings.extend(matches);
+        ratings
+    }
+
+    /// Applies `matches` oldest first, as if they were all played after the ones already
+    /// rated. Matches with an unknown winner are skipped, a pilot playing itself only
+    /// rates its versions.
+    pub fn extend(&mut self, matches: &[MatchResult]) {
+        let mut ordered: Vec<_> = matches.iter().collect();
+        ordered.sort_by_key(|m| m.created_at);
+
+        for m in ordered {
+            let score_a = match m.winner {
+                Winner::TeamA => 1.0,
+                Winner::TeamB => 0.0,
+                Winner::Unknown => continue,
+            };
+
+            let a = m.team_a.aip_id;
+            let b = m.team_b.aip_id;
+            // Two versions of one pilot say nothing about the pilot, only about the versions
+            if a != b {
+                Self::apply(&mut self.pilots, a, b, score_a);
+            }
+            Self::apply(
+                &mut self.versions,
+                (a, m.team_a.version),
+                (b, m.team_b.version),
+                score_a,
+            );
+        }
+    }
+
+    fn apply<K: std::hash::Hash + Eq + Copy>(
+        table: &mut HashMap<K, Rating>,
+        a: K,
+        b: K,
+        score_a: f64,
+    ) {
+        let rating_a = table.get(&a).copied().unwrap_or_default();
+        let rating_b = table.get(&b).copied().unwrap_or_default();
+        table.insert(a, rating_a.update(&rating_b, score_a));
+        table.insert(b, rating_b.update(&rating_a, 1.0 - score_a));
+    }
+
+    pub fn pilot(&self, pilot_id: &Uuid) -> Rating {
+        self.pilots.get(pilot_id).copied().unwrap_or_default()
+    }
+
+    pub fn version(&self, pilot_id: &Uuid, version: i32) -> Rating {
+        self.versions
+            .get(&(*pilot_id, version))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// All rated versions of a pilot, newest version first.
+    pub fn versions_of(&self, pilot_id: &Uuid) -> Vec<(i32, Rating)> {
+        let mut versions: Vec<_> = self
+            .versions
+            .iter()
+            .filter(|((id, _), _)| id == pilot_id)
+            .map(|((_, version), rating)| (*version, *rating))
+            .collect();
+        versions.sort_by_key(|(version, _)| -version);
+        versions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use client::models::TeamInfo;
+
+    use super::*;
+
+    fn rating(rating: f64, deviation: f64) -> Rating {
+        Rating {
+            rating,
+            deviation,
+            ..Default::default()
+        }
+    }
+
+    fn fight(a: Uuid, b: Uuid, winner: Winner, created_at: i64) -> MatchResult {
+        MatchResult {
+            id: Uuid::new_v4(),
+            team_a: TeamInfo::new(a, 1),
+            team_b: TeamInfo::new(b, 1),
+            winner,
+            created_at,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn glickman_example() {
+        // Worked example from section 3 of the Glicko-2 paper
+        let player = rating(1500.0, 200.0);
+        let updated = player.update_period(&[
+            (rating(1400.0, 30.0), 1.0),
+            (rating(1550.0, 100.0), 0.0),
+            (rating(1700.0, 300.0), 0.0),
+        ]);
+
+        assert!((updated.rating - 1464.06).abs() < 0.01, "{:?}", updated);
+        assert!((updated.deviation - 151.52).abs() < 0.01, "{:?}", updated);
+        assert!(
+            (updated.volatility - 0.05999).abs() < 0.00001,
+            "{:?}",
+            updated
+        );
+        assert_eq!(updated.matches, 3);
+    }
+
+    #[test]
+    fn single_game() {
+        let winner = Rating::default().update(&Rating::default(), 1.0);
+        let loser = Rating::default().update(&Rating::default(), 0.0);
+
+        assert!(winner.rating > DEFAULT_RATING);
+        assert!((winner.rating - DEFAULT_RATING + loser.rating - DEFAULT_RATING).abs() < 1e-9);
+        assert!(winner.deviation < DEFAULT_DEVIATION);
+    }
+
+    #[test]
+    fn unknown_winner_skipped() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let ratings = Ratings::from_matches(&[
+            fight(a, b, Winner::Unknown, 1),
+            fight(a, b, Winner::Unknown, 2),
+        ]);
+        assert_eq!(ratings.pilot(&a), Rating::default());
+        assert_eq!(ratings.version(&b, 1), Rating::default());
+
+        let decided = Ratings::from_matches(&[fight(a, b, Winner::TeamA, 1)]);
+        let mixed = Ratings::from_matches(&[
+            fight(a, b, Winner::Unknown, 1),
+            fight(a, b, Winner::TeamA, 2),
+            fight(a, b, Winner::Unknown, 3),
+        ]);
+        assert_eq!(mixed.pilot(&a), decided.pilot(&a));
+        assert_eq!(mixed.pilot(&a).matches, 1);
+    }
+
+    #[test]
+    fn versions_of_one_pilot() {
+        let a = Uuid::new_v4();
+        let mut m = fight(a, a, Winner::TeamB, 1);
+        m.team_b.version = 2;
+        let ratings = Ratings::from_matches(&[m]);
+
+        assert_eq!(ratings.pilot(&a), Rating::default());
+        assert!(ratings.version(&a, 2).rating > DEFAULT_RATING);
+        assert!(ratings.version(&a, 1).rating < DEFAULT_RATING);
+    }
+
+    #[test]
+    fn extend_continues_replay() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let matches = [
+            fight(a, b, Winner::TeamA, 1),
+            fight(a, b, Winner::TeamB, 2),
+            fight(a, b, Winner::TeamA, 3),
+        ];
+
+        let mut ratings = Ratings::from_matches(&matches[..1]);
+        ratings.extend(&matches[1..]);
+        assert_eq!(ratings.pilot(&a), Ratings::from_matches(&matches).pilot(&a));
+    }
+}
@@ -0,0 +1,101 @@
+use std::{marker::PhantomData, ops::Deref};
+
+use rocket::{
+    Request,
+    http::Status,
+    outcome::try_outcome,
+    request::{FromRequest, Outcome},
+};
+use rocket_okapi::{
+    r#gen::OpenApiGenerator,
+    request::{OpenApiFromRequest, RequestHeaderInput},
+};
+
+use crate::cookie::ApiUser;
+
+pub use aip_types::scope::{Scope, Scopes};
+
+/// Scope a route requires, resolved per request so it can depend on route parameters.
+pub trait RequiredScope: Send + Sync + 'static {
+    fn required(request: &Request<'_>) -> Option<Scope>;
+}
+
+macro_rules! fixed_scope {
+    ($name:ident) => {
+        pub struct $name;
+
+        impl RequiredScope for $name {
+            fn required(_request: &Request<'_>) -> Option<Scope> {
+                Some(Scope::$name)
+            }
+        }
+    };
+}
+
+fixed_scope!(PilotsRead);
+fixed_scope!(MatchesRead);
+fixed_scope!(MatchesCreate);
+fixed_scope!(TournamentsRead);
+fixed_scope!(TournamentsManage);
+fixed_scope!(TokensManage);
+fixed_scope!(WebhooksManage);
+
+/// Upload rights for the pilot in the `name` query parameter.
+pub struct PilotUpload;
+
+impl RequiredScope for PilotUpload {
+    fn required(request: &Request<'_>) -> Option<Scope> {
+        let name = request.query_value::<&str>("name")?.ok()?;
+        Some(Scope::PilotUpload(name.to_string()))
+    }
+}
+
+/// An [`ApiUser`] whose token carries the scope required by `S`.
+/// Fails with 403 if the token lacks it.
+pub struct Scoped<S: RequiredScope> {
+    pub user: ApiUser,
+    _scope: PhantomData<S>,
+}
+
+impl<S: RequiredScope> Deref for Scoped<S> {
+    type Target = ApiUser;
+
+    fn deref(&self) -> &Self::Target {
+        &self.user
+    }
+}
+
+#[async_trait]
+impl<'r, S: RequiredScope> FromRequest<'r> for Scoped<S> {
+    type Error = String;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let user = try_outcome!(request.guard::<ApiUser>().await);
+
+        let Some(required) = S::required(request) else {
+            return Outcome::Error((Status::BadRequest, "Missing scope parameter".to_string()));
+        };
+
+        if !user.has_scope(&required) {
+            return Outcome::Error((
+                Status::Forbidden,
+                format!("Token is missing the {} scope", required),
+            ));
+        }
+
+        Outcome::Success(Scoped {
+            user,
+            _scope: PhantomData,
+        })
+    }
+}
+
+impl<'a, S: RequiredScope> OpenApiFromRequest<'a> for Scoped<S> {
+    fn from_request_input(
+        gene: &mut OpenApiGenerator,
+        name: String,
+        required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        ApiUser::from_request_input(gene, name, required)
+    }
+}
@@ -1,85 +1,36 @@
-use std::{env, time::Duration};
+use std::{sync::Arc, time::Duration};
 
-use log::error;
 use moka::future::Cache;
-use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
-pub struct DiscordUserInfo {
-    pub id: String,
-    pub username: String,
-    pub avatar: String,
-}
+use crate::{identity::IdentityProvider, metrics};
+
+pub use aip_types::api::DiscordUserInfo;
 
-#[derive(Debug, Clone)]
+/// Cached profile lookups through the configured [`IdentityProvider`].
+#[derive(Clone)]
 pub struct SSOClient {
-    client: reqwest::Client,
+    provider: Arc<dyn IdentityProvider>,
     cache: Cache<String, DiscordUserInfo>,
-    own_base_url: String,
 }
 
 impl SSOClient {
-    pub fn new() -> Self {
+    pub fn new(provider: Arc<dyn IdentityProvider>) -> Self {
         let cache = Cache::builder()
             .max_capacity(2048)
             .time_to_live(Duration::from_secs(60 * 60 * 24))
             .build();
-        let client = reqwest::Client::new();
-        let own_base_url = env::var("BASE_URL").expect("BASE_URL must be set");
 
-        SSOClient {
-            client,
-            cache,
-            own_base_url,
-        }
+        SSOClient { provider, cache }
     }
 
     pub async fn get_user(&self, discord_id: &str) -> Option<DiscordUserInfo> {
-        self.cache
-            .optionally_get_with(discord_id.to_string(), self.fetch_discord_user(discord_id))
-            .await
-    }
-
-    async fn fetch_discord_user(&self, discord_id: &str) -> Option<DiscordUserInfo> {
-        self.client
-            .get(format!("https://sso.isan.to/uinfo/{}", discord_id))
-            .send()
-            .await
-            .ok()?
-            .json::<DiscordUserInfo>()
-            .await
-            .ok()
-    }
-
-    pub fn get_redirect_url(&self, next: Option<&str>) -> String {
-        let next = next.unwrap_or("/");
-        let callback = format!("{}/login_callback{}", self.own_base_url, next);
-        format!("https://sso.isan.to/login?service={}", callback)
-    }
-
-    pub async fn get_user_oauth(&self, code: &str) -> Option<DiscordUserInfo> {
-        let res = self
-            .client
-            .get(format!("https://sso.isan.to/getuser/{}", code))
-            .send()
+        let entry = self
+            .cache
+            .entry(discord_id.to_string())
+            .or_optionally_insert_with(self.provider.lookup_user(discord_id))
             .await;
+        metrics::cache_lookup("sso_user", entry.as_ref().is_some_and(|e| !e.is_fresh()));
 
-        let res = match res {
-            Ok(response) => response,
-            Err(e) => {
-                error!("Failed to get user data: {}", e);
-                return None;
-            }
-        };
-
-        let data = match res.json::<DiscordUserInfo>().await {
-            Ok(user_info) => user_info,
-            Err(e) => {
-                error!("Failed to parse user data: {}", e);
-                return None;
-            }
-        };
-
-        Some(data)
+        entry.map(|e| e.into_value())
     }
 }
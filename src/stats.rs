@@ -0,0 +1,469 @@
+use std::{cmp::Reverse, collections::HashMap, sync::Arc};
+
+use client::models::{AiPilot, MatchResult, TeamInfo, match_result::Winner};
+use moka::future::Cache;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    SqliteClient,
+    api_error::ApiErrors,
+    metrics, mirror,
+    rating::{Rating, Ratings},
+};
+
+// Win and loss bookkeeping shared by every page and API showing a record.
+// Matches upstream couldn't decide, a crash or a timeout, count as played but are
+// neither a win nor a loss, and are left out of win rates.
+// Per pilot, version, opponent and owner statistics are computed together in `Stats`,
+// which is cached until the mirror changes and serialized as is for templates and the API.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Win,
+    Loss,
+    /// Upstream reported [`Winner::Unknown`]
+    Undecided,
+}
+
+impl Outcome {
+    /// Result of `m` for `pilot_id`, `None` if the pilot didn't play in it.
+    pub fn of(m: &MatchResult, pilot_id: &Uuid) -> Option<Outcome> {
+        Self::of_any(m, std::slice::from_ref(pilot_id))
+    }
+
+    /// Result of `m` for a group of pilots, such as all of a user's, which win when any
+    /// of them was on the winning side.
+    pub fn of_any(m: &MatchResult, pilot_ids: &[Uuid]) -> Option<Outcome> {
+        let on_a = pilot_ids.contains(&m.team_a.aip_id);
+        let on_b = pilot_ids.contains(&m.team_b.aip_id);
+        if !on_a && !on_b {
+            return None;
+        }
+
+        Some(match m.winner {
+            Winner::Unknown => Outcome::Undecided,
+            Winner::TeamA if on_a => Outcome::Win,
+            Winner::TeamB if on_b => Outcome::Win,
+            _ => Outcome::Loss,
+        })
+    }
+}
+
+/// Wins, losses and undecided matches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Record {
+    pub wins: usize,
+    pub losses: usize,
+    pub undecided: usize,
+}
+
+impl Record {
+    pub fn add(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Win => self.wins += 1,
+            Outcome::Loss => self.losses += 1,
+            Outcome::Undecided => self.undecided += 1,
+        }
+    }
+
+    /// Every match played, undecided ones included.
+    pub fn total(&self) -> usize {
+        self.wins + self.losses + self.undecided
+    }
+
+    pub fn decided(&self) -> usize {
+        self.wins + self.losses
+    }
+
+    /// Percentage of decided matches won, 0 without any.
+    pub fn win_rate(&self) -> f64 {
+        if self.decided() > 0 {
+            self.wins as f64 / self.decided() as f64 * 100.0
+        } else {
+            0.0
+        }
+    }
+}
+
+impl FromIterator<Outcome> for Record {
+    fn from_iter<I: IntoIterator<Item = Outcome>>(outcomes: I) -> Self {
+        let mut record = Record::default();
+        for outcome in outcomes {
+            record.add(outcome);
+        }
+        record
+    }
+}
+
+/// Whether a version did better than the one before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Trend {
+    Up,
+    Down,
+    Neutral,
+}
+
+/// Record of a pilot, or one of its versions, against one opponent.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OpponentStats {
+    pub pilot_id: Uuid,
+    /// The opponent's id when it no longer exists upstream
+    pub name: String,
+    #[serde(flatten)]
+    pub record: Record,
+    pub matches: usize,
+    /// Percentage of decided matches won
+    pub win_rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionStats {
+    pub version: i32,
+    #[serde(flatten)]
+    pub record: Record,
+    pub matches: usize,
+    /// Percentage of decided matches won
+    pub win_rate: f64,
+    pub rating: Rating,
+    /// Win rate compared with the previous version that played
+    pub trend: Trend,
+    /// Most played first
+    pub opponents: Vec<OpponentStats>,
+}
+
+impl VersionStats {
+    /// Stats of a version without any matches yet.
+    pub fn unplayed(version: i32) -> Self {
+        VersionStats {
+            version,
+            record: Record::default(),
+            matches: 0,
+            win_rate: 0.0,
+            rating: Rating::default(),
+            trend: Trend::Neutral,
+            opponents: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PilotStats {
+    pub pilot_id: Uuid,
+    pub name: String,
+    pub owner_id: String,
+    pub current_version: i32,
+    #[serde(flatten)]
+    pub record: Record,
+    pub matches: usize,
+    /// Percentage of decided matches won
+    pub win_rate: f64,
+    pub rating: Rating,
+    /// Versions that played, newest first
+    pub versions: Vec<VersionStats>,
+    /// Most played first
+    pub opponents: Vec<OpponentStats>,
+}
+
+impl PilotStats {
+    pub fn version(&self, version: i32) -> Option<&VersionStats> {
+        self.versions.iter().find(|v| v.version == version)
+    }
+}
+
+/// Combined record of everything one user uploaded. A match between two of their own
+/// pilots counts once, as a win unless it was undecided.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnerStats {
+    pub owner_id: String,
+    /// Pilot names, alphabetically
+    pub pilots: Vec<String>,
+    #[serde(flatten)]
+    pub record: Record,
+    pub matches: usize,
+    /// Percentage of decided matches won
+    pub win_rate: f64,
+    /// Rating of the user's highest rated pilot
+    pub best_rating: Rating,
+}
+
+/// Records of `pilots`, or only of their current versions, in one pass over `matches`.
+/// Pilots without matches are left out.
+pub fn records(
+    pilots: &[AiPilot],
+    matches: &[MatchResult],
+    current_only: bool,
+) -> HashMap<Uuid, Record> {
+    let current: HashMap<_, _> = pilots.iter().map(|p| (p.id, p.current.version)).collect();
+    let plays = |team: &TeamInfo| {
+        current
+            .get(&team.aip_id)
+            .is_some_and(|version| !current_only || team.version == *version)
+    };
+
+    let mut records: HashMap<Uuid, Record> = HashMap::new();
+    for m in matches {
+        // A pilot playing itself only counts once
+        let mut ids: Vec<Uuid> = Vec::with_capacity(2);
+        for team in [&m.team_a, &m.team_b] {
+            if plays(team) && !ids.contains(&team.aip_id) {
+                ids.push(team.aip_id);
+            }
+        }
+        for id in ids {
+            if let Some(outcome) = Outcome::of(m, &id) {
+                records.entry(id).or_default().add(outcome);
+            }
+        }
+    }
+    records
+}
+
+/// Everything the pilot and user pages show, computed in one pass over a set of matches.
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    pilots: Vec<PilotStats>,
+    owners: Vec<OwnerStats>,
+    ratings: Ratings,
+}
+
+#[derive(Default)]
+struct PilotTally {
+    record: Record,
+    opponents: HashMap<Uuid, Record>,
+    versions: HashMap<i32, (Record, HashMap<Uuid, Record>)>,
+}
+
+fn opponent_stats(
+    opponents: HashMap<Uuid, Record>,
+    names: &HashMap<Uuid, &str>,
+) -> Vec<OpponentStats> {
+    let mut opponents: Vec<_> = opponents
+        .into_iter()
+        .map(|(pilot_id, record)| OpponentStats {
+            pilot_id,
+            name: names
+                .get(&pilot_id)
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| pilot_id.to_string()),
+            record,
+            matches: record.total(),
+            win_rate: record.win_rate(),
+        })
+        .collect();
+    opponents.sort_by(|a, b| b.matches.cmp(&a.matches).then_with(|| a.name.cmp(&b.name)));
+    opponents
+}
+
+impl Stats {
+    /// `pilots` are kept in the given order, matches of pilots not among them only count
+    /// for their opponents.
+    pub fn new(pilots: &[AiPilot], matches: &[MatchResult]) -> Self {
+        let ratings = Ratings::from_matches(matches);
+        let names: HashMap<_, _> = pilots.iter().map(|p| (p.id, p.name.as_str())).collect();
+        let owners: HashMap<_, _> = pilots.iter().map(|p| (p.id, p.owner_id.as_str())).collect();
+
+        let mut pilot_tallies: HashMap<Uuid, PilotTally> = HashMap::new();
+        let mut owner_records: HashMap<&str, Record> = HashMap::new();
+        for m in matches {
+            let sides = [(&m.team_a, &m.team_b), (&m.team_b, &m.team_a)];
+            // A pilot playing itself only counts once
+            let sides = if m.team_a.aip_id == m.team_b.aip_id {
+                &sides[..1]
+            } else {
+                &sides[..]
+            };
+
+            for (own, opponent) in sides {
+                if !names.contains_key(&own.aip_id) {
+                    continue;
+                }
+                let Some(outcome) = Outcome::of(m, &own.aip_id) else {
+                    continue;
+                };
+
+                let tally = pilot_tallies.entry(own.aip_id).or_default();
+                tally.record.add(outcome);
+                tally
+                    .opponents
+                    .entry(opponent.aip_id)
+                    .or_default()
+                    .add(outcome);
+                let (record, opponents) = tally.versions.entry(own.version).or_default();
+                record.add(outcome);
+                opponents.entry(opponent.aip_id).or_default().add(outcome);
+            }
+
+            let mut match_owners: Vec<_> = [&m.team_a.aip_id, &m.team_b.aip_id]
+                .into_iter()
+                .filter_map(|id| owners.get(id).copied())
+                .collect();
+            match_owners.dedup();
+            for owner_id in match_owners {
+                let owned: Vec<_> = [m.team_a.aip_id, m.team_b.aip_id]
+                    .into_iter()
+                    .filter(|id| owners.get(id) == Some(&owner_id))
+                    .collect();
+                if let Some(outcome) = Outcome::of_any(m, &owned) {
+                    owner_records.entry(owner_id).or_default().add(outcome);
+                }
+            }
+        }
+
+        let pilot_stats: Vec<_> = pilots
+            .iter()
+            .map(|pilot| {
+                let tally = pilot_tallies.remove(&pilot.id).unwrap_or_default();
+
+                let mut versions: Vec<_> = tally.versions.into_iter().collect();
+                versions.sort_by_key(|(version, _)| Reverse(*version));
+                let win_rates: Vec<_> = versions.iter().map(|(_, (r, _))| r.win_rate()).collect();
+                let versions = versions
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, (version, (record, opponents)))| VersionStats {
+                        version,
+                        record,
+                        matches: record.total(),
+                        win_rate: record.win_rate(),
+                        rating: ratings.version(&pilot.id, version),
+                        trend: match win_rates.get(i + 1) {
+                            Some(previous) if win_rates[i] > *previous => Trend::Up,
+                            Some(previous) if win_rates[i] < *previous => Trend::Down,
+                            _ => Trend::Neutral,
+                        },
+                        opponents: opponent_stats(opponents, &names),
+                    })
+                    .collect();
+
+                PilotStats {
+                    pilot_id: pilot.id,
+                    name: pilot.name.clone(),
+                    owner_id: pilot.owner_id.clone(),
+                    current_version: pilot.current.version,
+                    record: tally.record,
+                    matches: tally.record.total(),
+                    win_rate: tally.record.win_rate(),
+                    rating: ratings.pilot(&pilot.id),
+                    versions,
+                    opponents: opponent_stats(tally.opponents, &names),
+                }
+            })
+            .collect();
+
+        let mut owner_stats: Vec<OwnerStats> = Vec::new();
+        for pilot in &pilot_stats {
+            match owner_stats
+                .iter_mut()
+                .find(|o| o.owner_id == pilot.owner_id)
+            {
+                Some(owner) => {
+                    owner.pilots.push(pilot.name.clone());
+                    if pilot.rating.rating > owner.best_rating.rating {
+                        owner.best_rating = pilot.rating;
+                    }
+                }
+                None => {
+                    let record = owner_records
+                        .get(pilot.owner_id.as_str())
+                        .copied()
+                        .unwrap_or_default();
+                    owner_stats.push(OwnerStats {
+                        owner_id: pilot.owner_id.clone(),
+                        pilots: vec![pilot.name.clone()],
+                        record,
+                        matches: record.total(),
+                        win_rate: record.win_rate(),
+                        best_rating: pilot.rating,
+                    });
+                }
+            }
+        }
+        for owner in &mut owner_stats {
+            owner.pilots.sort();
+        }
+
+        Stats {
+            pilots: pilot_stats,
+            owners: owner_stats,
+            ratings,
+        }
+    }
+
+    pub fn pilot(&self, pilot_id: &Uuid) -> Option<&PilotStats> {
+        self.pilots.iter().find(|p| &p.pilot_id == pilot_id)
+    }
+
+    pub fn pilots(&self) -> &[PilotStats] {
+        &self.pilots
+    }
+
+    /// Pilots of `owner_id`, most played first.
+    pub fn pilots_of(&self, owner_id: &str) -> Vec<&PilotStats> {
+        let mut pilots: Vec<_> = self
+            .pilots
+            .iter()
+            .filter(|p| p.owner_id == owner_id)
+            .collect();
+        pilots.sort_by_key(|p| Reverse(p.matches));
+        pilots
+    }
+
+    pub fn owner(&self, owner_id: &str) -> Option<&OwnerStats> {
+        self.owners.iter().find(|o| o.owner_id == owner_id)
+    }
+
+    /// Owners in the order their first pilot appeared in.
+    pub fn owners(&self) -> &[OwnerStats] {
+        &self.owners
+    }
+
+    /// Ratings of every pilot and version that played, including removed ones.
+    pub fn ratings(&self) -> &Ratings {
+        &self.ratings
+    }
+}
+
+/// [`Stats`] over the whole mirror, only recomputed once the mirror changed.
+#[derive(Clone)]
+pub struct StatsCache {
+    cache: Cache<u64, Arc<Stats>>,
+}
+
+impl Default for StatsCache {
+    fn default() -> Self {
+        StatsCache::new()
+    }
+}
+
+impl StatsCache {
+    pub fn new() -> Self {
+        // Keyed by mirror generation, only the latest one is ever asked for again
+        let cache = Cache::builder().max_capacity(2).build();
+        StatsCache { cache }
+    }
+
+    pub async fn get(&self, client: &SqliteClient) -> Result<Arc<Stats>, ApiErrors> {
+        let generation = mirror::generation();
+        let entry = self
+            .cache
+            .entry(generation)
+            .or_try_insert_with(async {
+                let pilots = mirror::get_pilots(client).await?;
+                let matches = mirror::get_matches(None, None, client).await?;
+                Ok::<_, ApiErrors>(Arc::new(Stats::new(&pilots, &matches)))
+            })
+            .await
+            // The mirror already logged what went wrong
+            .map_err(|_| ApiErrors::InternalError("Failed to compute statistics".into()))?;
+        metrics::cache_lookup("stats", !entry.is_fresh());
+
+        Ok(entry.into_value())
+    }
+}
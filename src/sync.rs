@@ -0,0 +1,231 @@
+use std::{env, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use client::models::MatchResult;
+use rocket::tokio::time::sleep;
+use serde::{Deserialize, Serialize};
+use sqlx::prelude::FromRow;
+
+use crate::{
+    SqliteClient, api_client::ApiClient, api_error::ApiErrors, events::Broadcaster, mirror,
+    stats::StatsCache, webhook,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncState {
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    pub last_success_at: Option<DateTime<Utc>>,
+    pub last_full_sync_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+impl SyncState {
+    pub async fn get(client: &SqliteClient) -> Result<SyncState, ApiErrors> {
+        let res = sqlx::query_as::<_, SyncState>(
+            r#"
+            SELECT last_attempt_at, last_success_at, last_full_sync_at, last_error
+            FROM sync_state
+            WHERE id = 1
+            "#,
+        )
+        .fetch_one(client)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to fetch sync state: {}", e);
+            ApiErrors::InternalError("Failed to fetch sync state".into())
+        })?;
+
+        Ok(res)
+    }
+
+    async fn record_success(full: bool, client: &SqliteClient) -> Result<(), sqlx::Error> {
+        let now = Utc::now();
+        sqlx::query(
+            r#"
+            UPDATE sync_state
+            SET last_attempt_at = $1,
+                last_success_at = $1,
+                last_full_sync_at = CASE WHEN $2 THEN $1 ELSE last_full_sync_at END,
+                last_error = NULL
+            WHERE id = 1
+            "#,
+        )
+        .bind(now)
+        .bind(full)
+        .execute(client)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn record_failure(error: &str, client: &SqliteClient) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE sync_state
+            SET last_attempt_at = $1, last_error = $2
+            WHERE id = 1
+            "#,
+        )
+        .bind(Utc::now())
+        .bind(error)
+        .execute(client)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Outcome of a single sync run.
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub pilots: usize,
+    pub new_matches: Vec<MatchResult>,
+}
+
+fn env_duration(key: &str, default_secs: u64) -> Duration {
+    let secs = env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default_secs);
+    Duration::from_secs(secs)
+}
+
+#[derive(Clone)]
+pub struct SyncWorker {
+    api_client: Arc<dyn ApiClient>,
+    client: SqliteClient,
+    broadcaster: Broadcaster,
+    stats: StatsCache,
+    interval: Duration,
+    full_sync_interval: Duration,
+}
+
+impl SyncWorker {
+    pub fn new(
+        api_client: Arc<dyn ApiClient>,
+        client: SqliteClient,
+        broadcaster: Broadcaster,
+        stats: StatsCache,
+    ) -> Self {
+        SyncWorker {
+            api_client,
+            client,
+            broadcaster,
+            stats,
+            interval: env_duration("SYNC_INTERVAL_SECS", 30),
+            full_sync_interval: env_duration("SYNC_FULL_INTERVAL_SECS", 60 * 60),
+        }
+    }
+
+    /// Runs forever, syncing every `SYNC_INTERVAL_SECS`.
+    pub async fn run(self) {
+        loop {
+            if let Err(e) = self.sync_once().await {
+                log::error!("Upstream sync failed: {}", e);
+                if let Err(e) = SyncState::record_failure(&e, &self.client).await {
+                    log::error!("Failed to record sync failure: {}", e);
+                }
+            }
+
+            sleep(self.interval).await;
+        }
+    }
+
+    async fn needs_full_sync(&self) -> bool {
+        let Ok(state) = SyncState::get(&self.client).await else {
+            return true;
+        };
+
+        match state.last_full_sync_at {
+            Some(last) => Utc::now()
+                .signed_duration_since(last)
+                .to_std()
+                .map(|elapsed| elapsed >= self.full_sync_interval)
+                .unwrap_or(true),
+            None => true,
+        }
+    }
+
+    /// Copies pilots and matches into SQLite. Every run lists all matches upstream, but
+    /// between full syncs only stores those not mirrored yet or still waiting for a replay.
+    /// Full syncs store everything again and drop pilots deleted upstream.
+    pub async fn sync_once(&self) -> Result<SyncReport, String> {
+        let full = self.needs_full_sync().await;
+        // The first sync copies the whole history, which should not be announced to webhooks
+        let initial = SyncState::get(&self.client)
+            .await
+            .map(|state| state.last_success_at.is_none())
+            .unwrap_or(true);
+        let baseline = if initial {
+            None
+        } else {
+            webhook::rating_baseline(&self.stats, &self.client).await
+        };
+
+        let pilots = self
+            .api_client
+            .get_pilots()
+            .await
+            .map_err(|e| e.to_string())?;
+        let new_versions = mirror::upsert_pilots(&pilots, &self.client)
+            .await
+            .map_err(|e| format!("Failed to store pilots: {}", e))?;
+
+        let mut matches = self
+            .api_client
+            .get_matches(None, None)
+            .await
+            .map_err(|e| e.to_string())?;
+        if !full {
+            let settled = mirror::get_settled_match_ids(&self.client)
+                .await
+                .map_err(|e| format!("Failed to fetch mirrored matches: {}", e))?;
+            matches.retain(|m| !settled.contains(&m.id));
+        }
+
+        let new_matches = mirror::upsert_matches(&matches, &self.client)
+            .await
+            .map_err(|e| format!("Failed to store matches: {}", e))?;
+
+        // Pilots deleted upstream are dropped on full syncs. An empty listing is more likely
+        // an upstream hiccup than every pilot being gone, so it is left alone.
+        let pruned = if full && !pilots.is_empty() {
+            mirror::prune_pilots(&pilots, &self.client)
+                .await
+                .map_err(|e| format!("Failed to prune pilots: {}", e))?
+        } else {
+            0
+        };
+        if pruned > 0 {
+            log::info!("Removed {} pilots no longer listed upstream", pruned);
+        }
+
+        SyncState::record_success(full, &self.client)
+            .await
+            .map_err(|e| format!("Failed to record sync state: {}", e))?;
+
+        if !initial {
+            webhook::dispatch_new_versions(&new_versions, &self.client).await;
+            webhook::dispatch_new_matches(&new_matches, baseline, &self.stats, &self.client).await;
+            self.broadcaster.versions_uploaded(&new_versions);
+            self.broadcaster
+                .matches_finished(&new_matches, &self.client)
+                .await;
+        }
+
+        if !new_matches.is_empty() {
+            log::info!(
+                "Synced {} pilots and {} new matches{}",
+                pilots.len(),
+                new_matches.len(),
+                if full { " (full sync)" } else { "" }
+            );
+        }
+
+        Ok(SyncReport {
+            pilots: pilots.len(),
+            new_matches,
+        })
+    }
+}
@@ -0,0 +1,1228 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use client::models::match_result::Winner;
+use rocket::tokio::time::sleep;
+use serde::{Deserialize, Serialize};
+use sqlx::{Sqlite, Transaction, prelude::FromRow};
+use uuid::Uuid;
+
+use crate::{SqliteClient, api_client::ApiClient, api_error::ApiErrors, mirror, model::UserId};
+
+pub type TournamentId = i64;
+pub type EntrantId = i64;
+pub type GameId = i64;
+
+/// How often an undecided single elimination game is replayed before the higher seed advances.
+const MAX_ATTEMPTS: i64 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum TournamentFormat {
+    RoundRobin,
+    Swiss,
+    SingleElimination,
+}
+
+impl TournamentFormat {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TournamentFormat::RoundRobin => "Round Robin",
+            TournamentFormat::Swiss => "Swiss",
+            TournamentFormat::SingleElimination => "Single Elimination",
+        }
+    }
+
+    /// Rounds needed to finish a tournament of `entrants` pilots.
+    /// Swiss defaults to `ceil(log2(entrants))` unless a round count is given.
+    pub fn total_rounds(&self, entrants: usize, swiss_rounds: Option<i64>) -> i64 {
+        let log2 = entrants.max(2).next_power_of_two().trailing_zeros() as i64;
+        match self {
+            TournamentFormat::RoundRobin => {
+                if entrants.is_multiple_of(2) {
+                    entrants as i64 - 1
+                } else {
+                    entrants as i64
+                }
+            }
+            TournamentFormat::Swiss => swiss_rounds.unwrap_or(log2),
+            TournamentFormat::SingleElimination => log2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum TournamentStatus {
+    Pending,
+    Running,
+    Finished,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum GameStatus {
+    /// Waiting to be sent to the upstream server
+    Pending,
+    /// Match queued upstream, waiting for the result
+    Scheduled,
+    Finished,
+    /// No winner, counted as a draw
+    Void,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Tournament {
+    pub id: TournamentId,
+    pub name: String,
+    pub format: TournamentFormat,
+    pub status: TournamentStatus,
+    pub total_rounds: i64,
+    pub current_round: i64,
+    pub created_by: UserId,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Entrant {
+    pub id: EntrantId,
+    pub tournament_id: TournamentId,
+    pub pilot_id: String,
+    pub pilot_version: i32,
+    pub seed: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Game {
+    pub id: GameId,
+    pub tournament_id: TournamentId,
+    pub round: i64,
+    pub position: i64,
+    pub entrant_a: EntrantId,
+    pub entrant_b: Option<EntrantId>,
+    pub match_id: Option<String>,
+    pub status: GameStatus,
+    pub winner: Option<EntrantId>,
+    pub attempts: i64,
+    pub note: Option<String>,
+}
+
+impl Game {
+    fn is_settled(&self) -> bool {
+        matches!(self.status, GameStatus::Finished | GameStatus::Void)
+    }
+}
+
+/// A pairing for a new round, `None` as the second entrant is a bye.
+type Pairing = (EntrantId, Option<EntrantId>);
+
+impl Tournament {
+    /// Creates a pending tournament. `entrants` are given in seed order.
+    pub async fn insert(
+        name: &str,
+        format: TournamentFormat,
+        total_rounds: i64,
+        created_by: UserId,
+        entrants: &[(Uuid, i32)],
+        client: &SqliteClient,
+    ) -> Result<Tournament, sqlx::Error> {
+        let mut tx = client.begin().await?;
+
+        let tournament = sqlx::query_as::<_, Tournament>(
+            r#"
+            INSERT INTO tournaments (name, format, total_rounds, created_by, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, name, format, status, total_rounds, current_round, created_by, created_at
+            "#,
+        )
+        .bind(name)
+        .bind(format)
+        .bind(total_rounds)
+        .bind(created_by)
+        .bind(Utc::now())
+        .fetch_one(&mut *tx)
+        .await?;
+
+        for (seed, (pilot_id, version)) in entrants.iter().enumerate() {
+            sqlx::query(
+                r#"
+                INSERT INTO tournament_entrants (tournament_id, pilot_id, pilot_version, seed)
+                VALUES ($1, $2, $3, $4)
+                "#,
+            )
+            .bind(tournament.id)
+            .bind(pilot_id.to_string())
+            .bind(version)
+            .bind(seed as i64 + 1)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(tournament)
+    }
+
+    pub async fn all(client: &SqliteClient) -> Result<Vec<Tournament>, sqlx::Error> {
+        sqlx::query_as::<_, Tournament>(
+            r#"
+            SELECT id, name, format, status, total_rounds, current_round, created_by, created_at
+            FROM tournaments
+            ORDER BY created_at DESC
+            "#,
+        )
+        .fetch_all(client)
+        .await
+    }
+
+    pub async fn get_by_id(id: TournamentId, client: &SqliteClient) -> Result<Tournament, sqlx::Error> {
+        sqlx::query_as::<_, Tournament>(
+            r#"
+            SELECT id, name, format, status, total_rounds, current_round, created_by, created_at
+            FROM tournaments
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_one(client)
+        .await
+    }
+
+    async fn get_running(client: &SqliteClient) -> Result<Vec<Tournament>, sqlx::Error> {
+        sqlx::query_as::<_, Tournament>(
+            r#"
+            SELECT id, name, format, status, total_rounds, current_round, created_by, created_at
+            FROM tournaments
+            WHERE status = $1
+            "#,
+        )
+        .bind(TournamentStatus::Running)
+        .fetch_all(client)
+        .await
+    }
+
+    /// Moves the tournament on from the status and round it was loaded with. Returns false
+    /// if it already moved on, such as when it is started twice at once.
+    async fn set_progress(
+        &self,
+        status: TournamentStatus,
+        current_round: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> Result<bool, sqlx::Error> {
+        let res = sqlx::query(
+            r#"
+            UPDATE tournaments
+            SET status = $2, current_round = $3
+            WHERE id = $1 AND status = $4 AND current_round = $5
+            "#,
+        )
+        .bind(self.id)
+        .bind(status)
+        .bind(current_round)
+        .bind(self.status)
+        .bind(self.current_round)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Pairs and stores the next round, or marks the tournament finished if all rounds
+    /// have been played. The new games are scheduled upstream by [`TournamentRunner`].
+    /// Returns false if the tournament had moved on since it was loaded.
+    pub async fn advance_round(&self, client: &SqliteClient) -> Result<bool, sqlx::Error> {
+        let entrants = Entrant::get_by_tournament(self.id, client).await?;
+        let games = Game::get_by_tournament(self.id, client).await?;
+        let mut tx = client.begin().await?;
+
+        let finished = self.current_round >= self.total_rounds
+            || (self.format == TournamentFormat::SingleElimination
+                && self.current_round > 0
+                && games.iter().filter(|g| g.round == self.current_round).count() <= 1);
+        if finished {
+            let advanced = self
+                .set_progress(TournamentStatus::Finished, self.current_round, &mut tx)
+                .await?;
+            tx.commit().await?;
+            return Ok(advanced);
+        }
+
+        let round = self.current_round + 1;
+        let pairings = match self.format {
+            TournamentFormat::RoundRobin => round_robin_pairings(&entrants, round),
+            TournamentFormat::Swiss => swiss_pairings(&entrants, &games),
+            TournamentFormat::SingleElimination => {
+                single_elimination_pairings(&entrants, &games, round)
+            }
+        };
+
+        if !self
+            .set_progress(TournamentStatus::Running, round, &mut tx)
+            .await?
+        {
+            return Ok(false);
+        }
+        Game::insert_round(self.id, round, &pairings, &mut tx).await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+}
+
+impl Entrant {
+    pub async fn get_by_tournament(
+        tournament_id: TournamentId,
+        client: &SqliteClient,
+    ) -> Result<Vec<Entrant>, sqlx::Error> {
+        sqlx::query_as::<_, Entrant>(
+            r#"
+            SELECT id, tournament_id, pilot_id, pilot_version, seed
+            FROM tournament_entrants
+            WHERE tournament_id = $1
+            ORDER BY seed
+            "#,
+        )
+        .bind(tournament_id)
+        .fetch_all(client)
+        .await
+    }
+}
+
+impl Game {
+    pub async fn get_by_tournament(
+        tournament_id: TournamentId,
+        client: &SqliteClient,
+    ) -> Result<Vec<Game>, sqlx::Error> {
+        sqlx::query_as::<_, Game>(
+            r#"
+            SELECT id, tournament_id, round, position, entrant_a, entrant_b, match_id,
+                   status, winner, attempts, note
+            FROM tournament_games
+            WHERE tournament_id = $1
+            ORDER BY round, position
+            "#,
+        )
+        .bind(tournament_id)
+        .fetch_all(client)
+        .await
+    }
+
+    async fn insert_round(
+        tournament_id: TournamentId,
+        round: i64,
+        pairings: &[Pairing],
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> Result<(), sqlx::Error> {
+        for (position, (a, b)) in pairings.iter().enumerate() {
+            // Byes are decided immediately
+            let (status, winner) = match b {
+                Some(_) => (GameStatus::Pending, None),
+                None => (GameStatus::Finished, Some(*a)),
+            };
+
+            sqlx::query(
+                r#"
+                INSERT INTO tournament_games (tournament_id, round, position, entrant_a, entrant_b, status, winner)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                "#,
+            )
+            .bind(tournament_id)
+            .bind(round)
+            .bind(position as i64)
+            .bind(a)
+            .bind(b)
+            .bind(status)
+            .bind(winner)
+            .execute(&mut **tx)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn mark_scheduled(&self, match_id: &str, client: &SqliteClient) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE tournament_games
+            SET status = $2, match_id = $3, attempts = attempts + 1, note = NULL
+            WHERE id = $1
+            "#,
+        )
+        .bind(self.id)
+        .bind(GameStatus::Scheduled)
+        .bind(match_id)
+        .execute(client)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn mark_settled(
+        &self,
+        status: GameStatus,
+        winner: Option<EntrantId>,
+        note: Option<&str>,
+        client: &SqliteClient,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE tournament_games
+            SET status = $2, winner = $3, note = $4
+            WHERE id = $1
+            "#,
+        )
+        .bind(self.id)
+        .bind(status)
+        .bind(winner)
+        .bind(note)
+        .execute(client)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn set_note(&self, note: &str, client: &SqliteClient) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE tournament_games
+            SET note = $2
+            WHERE id = $1
+            "#,
+        )
+        .bind(self.id)
+        .bind(note)
+        .execute(client)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Circle method: the first seed stays fixed while everyone else rotates one step per round.
+fn round_robin_pairings(entrants: &[Entrant], round: i64) -> Vec<Pairing> {
+    let mut slots: Vec<Option<EntrantId>> = entrants.iter().map(|e| Some(e.id)).collect();
+    if !slots.len().is_multiple_of(2) {
+        slots.push(None);
+    }
+    if slots.len() < 2 {
+        return Vec::new();
+    }
+
+    let mut rotating = slots.split_off(1);
+    let shift = (round - 1) as usize % rotating.len();
+    rotating.rotate_right(shift);
+    slots.extend(rotating);
+
+    let n = slots.len();
+    (0..n / 2)
+        .filter_map(|i| match (slots[i], slots[n - 1 - i]) {
+            (Some(a), b) => Some((a, b)),
+            (None, Some(b)) => Some((b, None)),
+            (None, None) => None,
+        })
+        .collect()
+}
+
+/// Pairs entrants with similar scores, with as few rematches as possible.
+/// With an odd field the lowest ranked entrant without a bye sits out.
+fn swiss_pairings(entrants: &[Entrant], games: &[Game]) -> Vec<Pairing> {
+    let ranked: Vec<EntrantId> = standings(entrants, games, &HashMap::new())
+        .into_iter()
+        .map(|s| s.entrant_id)
+        .collect();
+
+    let mut played: HashSet<(EntrantId, EntrantId)> = HashSet::new();
+    let mut had_bye: HashSet<EntrantId> = HashSet::new();
+    for g in games {
+        match g.entrant_b {
+            Some(b) => {
+                played.insert((g.entrant_a, b));
+                played.insert((b, g.entrant_a));
+            }
+            None => {
+                had_bye.insert(g.entrant_a);
+            }
+        }
+    }
+
+    let mut pool = ranked.clone();
+    let mut bye = None;
+    if !pool.len().is_multiple_of(2) {
+        let idx = pool
+            .iter()
+            .rposition(|id| !had_bye.contains(id))
+            .unwrap_or(pool.len() - 1);
+        bye = Some(pool.remove(idx));
+    }
+
+    let mut pairings = pair_fewest_rematches(&pool, &played);
+
+    if let Some(bye) = bye {
+        pairings.push((bye, None));
+    }
+    pairings
+}
+
+/// Search steps before Swiss pairing settles for the best pairing found so far.
+const PAIRING_SEARCH_BUDGET: usize = 10_000;
+
+/// Pairs `pool` with as few rematches as the search finds, each entrant with the nearest
+/// opponent in the standings that allows it. The search is bounded, so a large field that
+/// can't avoid rematches may get more of them than strictly needed.
+fn pair_fewest_rematches(
+    pool: &[EntrantId],
+    played: &HashSet<(EntrantId, EntrantId)>,
+) -> Vec<Pairing> {
+    let mut search = PairingSearch {
+        played,
+        budget: PAIRING_SEARCH_BUDGET,
+        current: Vec::new(),
+        rematches: 0,
+        best: None,
+    };
+    search.visit(pool);
+
+    search
+        .best
+        .map(|(_, pairings)| pairings)
+        .unwrap_or_default()
+}
+
+struct PairingSearch<'a> {
+    played: &'a HashSet<(EntrantId, EntrantId)>,
+    budget: usize,
+    current: Vec<Pairing>,
+    rematches: usize,
+    best: Option<(usize, Vec<Pairing>)>,
+}
+
+impl PairingSearch<'_> {
+    /// Depth first, trying new opponents before rematches. The first pairing is always
+    /// completed, after that branches stop once they can't beat the best or the budget ran out.
+    fn visit(&mut self, pool: &[EntrantId]) {
+        self.budget = self.budget.saturating_sub(1);
+
+        let Some((&first, rest)) = pool.split_first() else {
+            if self
+                .best
+                .as_ref()
+                .is_none_or(|(best, _)| self.rematches < *best)
+            {
+                self.best = Some((self.rematches, self.current.clone()));
+            }
+            return;
+        };
+
+        let mut order: Vec<usize> = (0..rest.len()).collect();
+        order.sort_by_key(|&i| self.played.contains(&(first, rest[i])));
+
+        for i in order {
+            let rematch = self.played.contains(&(first, rest[i])) as usize;
+            let out_of_reach = match &self.best {
+                Some((best, _)) => self.budget == 0 || self.rematches + rematch >= *best,
+                None => false,
+            };
+            if out_of_reach {
+                break;
+            }
+
+            let remaining: Vec<_> = rest
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, id)| *id)
+                .collect();
+
+            self.current.push((first, Some(rest[i])));
+            self.rematches += rematch;
+            self.visit(&remaining);
+            self.rematches -= rematch;
+            self.current.pop();
+        }
+    }
+}
+
+/// Standard bracket order, e.g. `[1, 8, 4, 5, 2, 7, 3, 6]` for 8 slots, so the top
+/// seeds can only meet in the final.
+fn bracket_order(size: usize) -> Vec<usize> {
+    let mut order = vec![1];
+    while order.len() < size {
+        let len = order.len() * 2;
+        order = order.iter().flat_map(|&s| [s, len + 1 - s]).collect();
+    }
+    order
+}
+
+fn single_elimination_pairings(entrants: &[Entrant], games: &[Game], round: i64) -> Vec<Pairing> {
+    if round == 1 {
+        let size = entrants.len().max(2).next_power_of_two();
+        let by_seed = |seed: usize| entrants.get(seed - 1).map(|e| e.id);
+
+        return bracket_order(size)
+            .chunks(2)
+            .filter_map(|slot| match (by_seed(slot[0]), by_seed(slot[1])) {
+                (Some(a), b) => Some((a, b)),
+                (None, Some(b)) => Some((b, None)),
+                (None, None) => None,
+            })
+            .collect();
+    }
+
+    let previous: Vec<_> = games.iter().filter(|g| g.round == round - 1).collect();
+    previous
+        .chunks(2)
+        .filter_map(|pair| {
+            let a = pair[0].winner?;
+            let b = pair.get(1).and_then(|g| g.winner);
+            Some((a, b))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Standing {
+    pub rank: usize,
+    pub entrant_id: EntrantId,
+    pub pilot_id: String,
+    pub pilot_name: String,
+    pub pilot_version: i32,
+    pub seed: i64,
+    pub played: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    pub byes: u32,
+    pub points: f64,
+    /// Sum of the opponents' points
+    pub buchholz: f64,
+    /// Sum of the points of beaten opponents plus half of those drawn against
+    pub sonneborn_berger: f64,
+}
+
+/// Ranks entrants by points, then Buchholz, then Sonneborn-Berger, then seed.
+/// Wins and byes are worth a point, void games half a point.
+pub fn standings(
+    entrants: &[Entrant],
+    games: &[Game],
+    names: &HashMap<Uuid, String>,
+) -> Vec<Standing> {
+    let mut table: HashMap<EntrantId, Standing> = entrants
+        .iter()
+        .map(|e| {
+            let pilot_name = Uuid::parse_str(&e.pilot_id)
+                .ok()
+                .and_then(|id| names.get(&id).cloned())
+                .unwrap_or_else(|| e.pilot_id.clone());
+
+            (
+                e.id,
+                Standing {
+                    rank: 0,
+                    entrant_id: e.id,
+                    pilot_id: e.pilot_id.clone(),
+                    pilot_name,
+                    pilot_version: e.pilot_version,
+                    seed: e.seed,
+                    played: 0,
+                    wins: 0,
+                    draws: 0,
+                    losses: 0,
+                    byes: 0,
+                    points: 0.0,
+                    buchholz: 0.0,
+                    sonneborn_berger: 0.0,
+                },
+            )
+        })
+        .collect();
+
+    let settled: Vec<_> = games.iter().filter(|g| g.is_settled()).collect();
+
+    for g in &settled {
+        let Some(b) = g.entrant_b else {
+            if let Some(s) = table.get_mut(&g.entrant_a) {
+                s.byes += 1;
+                s.points += 1.0;
+            }
+            continue;
+        };
+
+        for (me, _) in [(g.entrant_a, b), (b, g.entrant_a)] {
+            let Some(s) = table.get_mut(&me) else {
+                continue;
+            };
+            s.played += 1;
+            match g.winner {
+                Some(w) if w == me => {
+                    s.wins += 1;
+                    s.points += 1.0;
+                }
+                Some(_) => s.losses += 1,
+                None => {
+                    s.draws += 1;
+                    s.points += 0.5;
+                }
+            }
+        }
+    }
+
+    let points: HashMap<EntrantId, f64> = table.iter().map(|(id, s)| (*id, s.points)).collect();
+    for g in &settled {
+        let Some(b) = g.entrant_b else {
+            continue;
+        };
+
+        for (me, opponent) in [(g.entrant_a, b), (b, g.entrant_a)] {
+            let opponent_points = points.get(&opponent).copied().unwrap_or_default();
+            let Some(s) = table.get_mut(&me) else {
+                continue;
+            };
+            s.buchholz += opponent_points;
+            match g.winner {
+                Some(w) if w == me => s.sonneborn_berger += opponent_points,
+                Some(_) => {}
+                None => s.sonneborn_berger += opponent_points / 2.0,
+            }
+        }
+    }
+
+    let mut standings: Vec<_> = table.into_values().collect();
+    standings.sort_by(|a, b| {
+        b.points
+            .total_cmp(&a.points)
+            .then(b.buchholz.total_cmp(&a.buchholz))
+            .then(b.sonneborn_berger.total_cmp(&a.sonneborn_berger))
+            .then(a.seed.cmp(&b.seed))
+    });
+    for (i, s) in standings.iter_mut().enumerate() {
+        s.rank = i + 1;
+    }
+    standings
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GameView {
+    pub id: GameId,
+    pub position: i64,
+    pub pilot_a: String,
+    pub pilot_b: Option<String>,
+    pub entrant_a: EntrantId,
+    pub entrant_b: Option<EntrantId>,
+    pub status: GameStatus,
+    pub winner: Option<EntrantId>,
+    pub winner_name: Option<String>,
+    pub match_id: Option<String>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RoundView {
+    pub round: i64,
+    pub games: Vec<GameView>,
+}
+
+/// Everything shown on a tournament page: standings and every round played so far.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TournamentDetail {
+    pub tournament: Tournament,
+    pub format_label: String,
+    pub standings: Vec<Standing>,
+    pub rounds: Vec<RoundView>,
+}
+
+impl TournamentDetail {
+    pub async fn load(id: TournamentId, client: &SqliteClient) -> Result<Self, ApiErrors> {
+        let tournament = Tournament::get_by_id(id, client)
+            .await
+            .map_err(|_| ApiErrors::NotFound("Tournament not found".into()))?;
+        let (entrants, games) = rocket::tokio::try_join!(
+            Entrant::get_by_tournament(id, client),
+            Game::get_by_tournament(id, client)
+        )
+        .map_err(|e| {
+            log::error!("Failed to fetch tournament games: {}", e);
+            ApiErrors::InternalError("Failed to fetch tournament games".into())
+        })?;
+        let names = mirror::get_pilot_names(client).await?;
+
+        let standings = standings(&entrants, &games, &names);
+        let entrant_names: HashMap<EntrantId, String> = standings
+            .iter()
+            .map(|s| (s.entrant_id, format!("{} v{}", s.pilot_name, s.pilot_version)))
+            .collect();
+        let name_of = |id: EntrantId| entrant_names.get(&id).cloned().unwrap_or_default();
+
+        let mut rounds: Vec<RoundView> = Vec::new();
+        for g in games {
+            let view = GameView {
+                id: g.id,
+                position: g.position,
+                pilot_a: name_of(g.entrant_a),
+                pilot_b: g.entrant_b.map(name_of),
+                entrant_a: g.entrant_a,
+                entrant_b: g.entrant_b,
+                status: g.status,
+                winner: g.winner,
+                winner_name: g.winner.map(name_of),
+                match_id: g.match_id,
+                note: g.note,
+            };
+
+            match rounds.last_mut() {
+                Some(r) if r.round == g.round => r.games.push(view),
+                _ => rounds.push(RoundView {
+                    round: g.round,
+                    games: vec![view],
+                }),
+            }
+        }
+
+        Ok(TournamentDetail {
+            format_label: tournament.format.label().to_string(),
+            tournament,
+            standings,
+            rounds,
+        })
+    }
+}
+
+/// Background task that queues pending tournament games upstream, collects results
+/// by match id and pairs the next round once the current one is settled.
+#[derive(Clone)]
+pub struct TournamentRunner {
+    api_client: Arc<dyn ApiClient>,
+    client: SqliteClient,
+    interval: Duration,
+}
+
+impl TournamentRunner {
+    pub fn new(api_client: Arc<dyn ApiClient>, client: SqliteClient) -> Self {
+        let interval = env::var("TOURNAMENT_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(15);
+
+        TournamentRunner {
+            api_client,
+            client,
+            interval: Duration::from_secs(interval),
+        }
+    }
+
+    pub async fn run(self) {
+        loop {
+            match Tournament::get_running(&self.client).await {
+                Ok(tournaments) => {
+                    for t in tournaments {
+                        if let Err(e) = self.advance(&t).await {
+                            log::error!("Failed to advance tournament {}: {}", t.id, e);
+                        }
+                    }
+                }
+                Err(e) => log::error!("Failed to fetch running tournaments: {}", e),
+            }
+
+            sleep(self.interval).await;
+        }
+    }
+
+    async fn advance(&self, tournament: &Tournament) -> Result<(), sqlx::Error> {
+        let entrants: HashMap<EntrantId, Entrant> =
+            Entrant::get_by_tournament(tournament.id, &self.client)
+                .await?
+                .into_iter()
+                .map(|e| (e.id, e))
+                .collect();
+
+        let round_games: Vec<_> = Game::get_by_tournament(tournament.id, &self.client)
+            .await?
+            .into_iter()
+            .filter(|g| g.round == tournament.current_round)
+            .collect();
+
+        let mut settled = true;
+        for game in &round_games {
+            let done = match game.status {
+                GameStatus::Pending => self.schedule(tournament, game, &entrants).await?,
+                GameStatus::Scheduled => self.collect(tournament, game, &entrants).await?,
+                GameStatus::Finished | GameStatus::Void => true,
+            };
+            settled &= done;
+        }
+
+        if settled {
+            tournament.advance_round(&self.client).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Queues the game upstream. Entrants whose pilot moved past the pinned version
+    /// forfeit, since the upstream server always fights the current version.
+    /// Returns whether the game is settled.
+    async fn schedule(
+        &self,
+        tournament: &Tournament,
+        game: &Game,
+        entrants: &HashMap<EntrantId, Entrant>,
+    ) -> Result<bool, sqlx::Error> {
+        let (Some(a), Some(b)) = (
+            entrants.get(&game.entrant_a),
+            game.entrant_b.and_then(|id| entrants.get(&id)),
+        ) else {
+            return Ok(true);
+        };
+
+        let (a_current, b_current) = (self.on_pinned_version(a).await, self.on_pinned_version(b).await);
+
+        match (a_current, b_current) {
+            (true, true) => match self.api_client.create_match(&a.pilot_id, &b.pilot_id).await {
+                Ok(match_id) => {
+                    game.mark_scheduled(&match_id, &self.client).await?;
+                    Ok(false)
+                }
+                Err(e) => {
+                    log::error!("Failed to schedule tournament game {}: {}", game.id, e);
+                    game.set_note(&format!("Failed to schedule: {}", e), &self.client)
+                        .await?;
+                    Ok(false)
+                }
+            },
+            (true, false) => {
+                game.mark_settled(
+                    GameStatus::Finished,
+                    Some(a.id),
+                    Some("Forfeit: opponent is no longer on the pinned version"),
+                    &self.client,
+                )
+                .await?;
+                Ok(true)
+            }
+            (false, true) => {
+                game.mark_settled(
+                    GameStatus::Finished,
+                    Some(b.id),
+                    Some("Forfeit: opponent is no longer on the pinned version"),
+                    &self.client,
+                )
+                .await?;
+                Ok(true)
+            }
+            (false, false) => {
+                self.settle_undecided(
+                    tournament,
+                    game,
+                    a,
+                    b,
+                    "Neither pilot is on the pinned version",
+                )
+                .await?;
+                Ok(true)
+            }
+        }
+    }
+
+    async fn on_pinned_version(&self, entrant: &Entrant) -> bool {
+        let Ok(pilot_id) = Uuid::parse_str(&entrant.pilot_id) else {
+            return false;
+        };
+
+        match mirror::get_pilot(&pilot_id, &self.client).await {
+            Ok(Some(pilot)) => pilot.current.version == entrant.pilot_version,
+            _ => false,
+        }
+    }
+
+    /// Looks up the result of a scheduled game. Returns whether the game is settled.
+    async fn collect(
+        &self,
+        tournament: &Tournament,
+        game: &Game,
+        entrants: &HashMap<EntrantId, Entrant>,
+    ) -> Result<bool, sqlx::Error> {
+        let Some(match_id) = game.match_id.as_deref() else {
+            return Ok(false);
+        };
+        let (Some(a), Some(b)) = (
+            entrants.get(&game.entrant_a),
+            game.entrant_b.and_then(|id| entrants.get(&id)),
+        ) else {
+            return Ok(true);
+        };
+
+        let result = match mirror::get_match(match_id, &self.client).await {
+            Ok(Some(m)) => Some(m),
+            _ => self.api_client.get_match(match_id).await.ok().flatten(),
+        };
+        let Some(result) = result else {
+            return Ok(false);
+        };
+
+        let a_is_team_a = result.team_a.aip_id.to_string() == a.pilot_id;
+        let winner = match (result.winner, a_is_team_a) {
+            (Winner::TeamA, true) | (Winner::TeamB, false) => Some(a),
+            (Winner::TeamB, true) | (Winner::TeamA, false) => Some(b),
+            (Winner::Unknown, _) => None,
+        };
+
+        match winner {
+            Some(winner) => {
+                game.mark_settled(GameStatus::Finished, Some(winner.id), None, &self.client)
+                    .await?;
+                Ok(true)
+            }
+            None => self
+                .settle_undecided(tournament, game, a, b, "Undecided result")
+                .await,
+        }
+    }
+
+    /// Round robin and Swiss games without a winner are void. Single elimination needs
+    /// a winner, so the game is replayed and after [`MAX_ATTEMPTS`] the higher seed advances.
+    async fn settle_undecided(
+        &self,
+        tournament: &Tournament,
+        game: &Game,
+        a: &Entrant,
+        b: &Entrant,
+        reason: &str,
+    ) -> Result<bool, sqlx::Error> {
+        if tournament.format != TournamentFormat::SingleElimination {
+            game.mark_settled(GameStatus::Void, None, Some(reason), &self.client)
+                .await?;
+            return Ok(true);
+        }
+
+        if game.match_id.is_some() && game.attempts < MAX_ATTEMPTS {
+            game.mark_settled(
+                GameStatus::Pending,
+                None,
+                Some(&format!("{}, replaying", reason)),
+                &self.client,
+            )
+            .await?;
+            return Ok(false);
+        }
+
+        let higher_seed = if a.seed <= b.seed { a } else { b };
+        game.mark_settled(
+            GameStatus::Finished,
+            Some(higher_seed.id),
+            Some(&format!("{}, higher seed advances", reason)),
+            &self.client,
+        )
+        .await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Entrants seeded 1 to `count`, with ids that differ from their seeds.
+    fn entrants(count: i64) -> Vec<Entrant> {
+        (1..=count)
+            .map(|seed| Entrant {
+                id: 100 + seed,
+                tournament_id: 1,
+                pilot_id: Uuid::new_v4().to_string(),
+                pilot_version: 1,
+                seed,
+            })
+            .collect()
+    }
+
+    fn finished(round: i64, (a, b): Pairing, winner: Option<EntrantId>) -> Game {
+        Game {
+            id: 0,
+            tournament_id: 1,
+            round,
+            position: 0,
+            entrant_a: a,
+            entrant_b: b,
+            match_id: None,
+            status: GameStatus::Finished,
+            winner: winner.or(b.is_none().then_some(a)),
+            attempts: 0,
+            note: None,
+        }
+    }
+
+    fn key(a: EntrantId, b: EntrantId) -> (EntrantId, EntrantId) {
+        (a.min(b), a.max(b))
+    }
+
+    /// Every entrant plays every other exactly once and, with an odd field, sits out once.
+    fn assert_round_robin(count: i64) {
+        let entrants = entrants(count);
+        let rounds = TournamentFormat::RoundRobin.total_rounds(entrants.len(), None);
+
+        let mut met = HashSet::new();
+        let mut byes = HashMap::new();
+        for round in 1..=rounds {
+            let pairings = round_robin_pairings(&entrants, round);
+            let mut seen = HashSet::new();
+            for (a, b) in pairings {
+                assert!(seen.insert(a), "{} twice in round {}", a, round);
+                match b {
+                    Some(b) => {
+                        assert!(seen.insert(b), "{} twice in round {}", b, round);
+                        assert!(met.insert(key(a, b)), "{} met {} again", a, b);
+                    }
+                    None => *byes.entry(a).or_insert(0) += 1,
+                }
+            }
+            assert_eq!(seen.len(), entrants.len());
+        }
+
+        let n = count as usize;
+        assert_eq!(met.len(), n * (n - 1) / 2);
+        if count % 2 == 1 {
+            assert_eq!(byes.len(), n);
+            assert!(byes.values().all(|&byes| byes == 1));
+        } else {
+            assert!(byes.is_empty());
+        }
+    }
+
+    #[test]
+    fn round_robin_even() {
+        assert_round_robin(4);
+        assert_round_robin(6);
+    }
+
+    #[test]
+    fn round_robin_odd() {
+        assert_round_robin(3);
+        assert_round_robin(5);
+        assert_round_robin(7);
+    }
+
+    #[test]
+    fn swiss_avoids_rematches() {
+        let entrants = entrants(4);
+        let [a, b, c, d] = [101, 102, 103, 104];
+        // After two rounds the standings are a, b, c, d, but a already beat b
+        let games = vec![
+            finished(1, (a, Some(b)), Some(a)),
+            finished(1, (c, Some(d)), Some(c)),
+            finished(2, (a, Some(c)), Some(a)),
+            finished(2, (b, Some(d)), Some(b)),
+        ];
+
+        let pairings = swiss_pairings(&entrants, &games);
+        assert_eq!(pairings, vec![(a, Some(d)), (b, Some(c))]);
+    }
+
+    #[test]
+    fn swiss_bye_rotates() {
+        let entrants = entrants(5);
+        let mut games = Vec::new();
+        let mut byes = HashSet::new();
+
+        for round in 1..=3 {
+            let pairings = swiss_pairings(&entrants, &games);
+            assert_eq!(pairings.len(), 3);
+            let byes_this_round: Vec<_> = pairings.iter().filter(|(_, b)| b.is_none()).collect();
+            assert_eq!(byes_this_round.len(), 1);
+            assert!(
+                byes.insert(byes_this_round[0].0),
+                "second bye in round {}",
+                round
+            );
+
+            // The first entrant of each game wins
+            games.extend(pairings.into_iter().map(|p| finished(round, p, Some(p.0))));
+        }
+    }
+
+    #[test]
+    fn swiss_falls_back_when_everyone_met() {
+        let entrants = entrants(2);
+        let games = vec![finished(1, (101, Some(102)), Some(101))];
+
+        assert_eq!(swiss_pairings(&entrants, &games), vec![(101, Some(102))]);
+    }
+
+    #[test]
+    fn swiss_fewest_rematches() {
+        // a met everyone and c met d, so one rematch is unavoidable
+        let [a, b, c, d] = [101, 102, 103, 104];
+        let played: HashSet<_> = [(a, b), (a, c), (a, d), (c, d)]
+            .into_iter()
+            .flat_map(|(x, y)| [(x, y), (y, x)])
+            .collect();
+
+        assert_eq!(
+            pair_fewest_rematches(&[a, b, c, d], &played),
+            vec![(a, Some(c)), (b, Some(d))]
+        );
+    }
+
+    #[test]
+    fn swiss_search_is_bounded() {
+        // The bottom three met everyone but each other, so one of them must have a
+        // rematch and an exhaustive search would try every way to pair the rest first
+        let pool: Vec<EntrantId> = (1..=40).collect();
+        let stuck = [38, 39, 40];
+        let played: HashSet<_> = stuck
+            .iter()
+            .flat_map(|&x| (1..=37).flat_map(move |y| [(x, y), (y, x)]))
+            .collect();
+
+        let pairings = pair_fewest_rematches(&pool, &played);
+        assert_eq!(pairings.len(), 20);
+        let mut seen: Vec<_> = pairings
+            .iter()
+            .flat_map(|&(a, b)| [a, b.unwrap()])
+            .collect();
+        seen.sort();
+        assert_eq!(seen, pool);
+
+        let rematches = pairings
+            .iter()
+            .filter(|&&(a, b)| played.contains(&(a, b.unwrap())))
+            .count();
+        assert_eq!(rematches, 1);
+    }
+
+    #[test]
+    fn bracket_seeding() {
+        assert_eq!(bracket_order(2), vec![1, 2]);
+        assert_eq!(bracket_order(4), vec![1, 4, 2, 3]);
+        assert_eq!(bracket_order(8), vec![1, 8, 4, 5, 2, 7, 3, 6]);
+    }
+
+    #[test]
+    fn single_elimination_byes() {
+        // Five entrants fill a bracket of eight, the top three seeds get byes
+        let entrants = entrants(5);
+        let first = single_elimination_pairings(&entrants, &[], 1);
+        assert_eq!(
+            first,
+            vec![(101, None), (104, Some(105)), (102, None), (103, None)]
+        );
+
+        let games: Vec<_> = first
+            .iter()
+            .map(|&p| finished(1, p, Some(p.1.unwrap_or(p.0))))
+            .collect();
+        let second = single_elimination_pairings(&entrants, &games, 2);
+        assert_eq!(second, vec![(101, Some(105)), (102, Some(103))]);
+    }
+}
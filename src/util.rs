@@ -1,3 +1,4 @@
+use rocket_dyn_templates::handlebars::{Handlebars, handlebars_helper};
 use serde::Serialize;
 
 pub fn format_date_relative(date: &chrono::DateTime<chrono::Utc>) -> String {
@@ -26,6 +27,13 @@ pub fn discord_avatar_url(discord_id: &str, avatar_hash: &str) -> String {
     )
 }
 
+/// Download link of a replay on the upstream server, `None` when there is no replay.
+pub fn replay_url(api_base_url: &str, replay_id: Option<&str>) -> Option<String> {
+    replay_id
+        .filter(|r| !r.trim().is_empty())
+        .map(|r| format!("{}/replay?replayId={}", api_base_url, r))
+}
+
 pub fn format_bytes(bytes: i64) -> String {
     if bytes < 1024 {
         format!("{} B", bytes)
@@ -73,3 +81,10 @@ pub fn build_info_ctx() -> BuildInfo {
             .map_or("unknown".to_string(), format_date_time),
     }
 }
+
+// `{{round win_rate}}`, or `{{round win_rate digits=1}}` to keep a decimal
+handlebars_helper!(round: |value: f64, {digits: u64 = 0}| format!("{:.*}", digits as usize, value));
+
+pub fn register_helpers(handlebars: &mut Handlebars<'static>) {
+    handlebars.register_helper("round", Box::new(round));
+}
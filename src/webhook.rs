@@ -0,0 +1,833 @@
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use client::models::{AiPilot, MatchResult};
+use hmac::{Hmac, Mac};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use rocket::{
+    futures::{StreamExt, stream},
+    tokio::{net::lookup_host, time::sleep},
+};
+use schemars::{JsonSchema, r#gen::SchemaGenerator, schema::Schema};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::Sha256;
+use sqlx::prelude::FromRow;
+use uuid::Uuid;
+
+use crate::{
+    SqliteClient,
+    model::UserId,
+    rating::Rating,
+    stats::{Stats, StatsCache},
+};
+
+pub type WebhookId = i64;
+pub type DeliveryId = i64;
+
+/// Attempts before a delivery is given up on. Retries back off exponentially from
+/// [`RETRY_BASE`], so the last one happens a bit over an hour after the event.
+const MAX_ATTEMPTS: i64 = 8;
+const RETRY_BASE: Duration = Duration::from_secs(30);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WebhookEvent {
+    MatchCompleted,
+    PilotVersionUploaded,
+    PilotRatingChanged,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 3] = [
+        WebhookEvent::MatchCompleted,
+        WebhookEvent::PilotVersionUploaded,
+        WebhookEvent::PilotRatingChanged,
+    ];
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            WebhookEvent::MatchCompleted => "A match finished",
+            WebhookEvent::PilotVersionUploaded => "A new pilot version was uploaded",
+            WebhookEvent::PilotRatingChanged => "A pilot's rating changed after a match",
+        }
+    }
+}
+
+impl fmt::Display for WebhookEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebhookEvent::MatchCompleted => write!(f, "match.completed"),
+            WebhookEvent::PilotVersionUploaded => write!(f, "pilot.version_uploaded"),
+            WebhookEvent::PilotRatingChanged => write!(f, "pilot.rating_changed"),
+        }
+    }
+}
+
+impl FromStr for WebhookEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        WebhookEvent::ALL
+            .into_iter()
+            .find(|event| event.to_string() == s)
+            .ok_or_else(|| format!("Unknown webhook event {}", s))
+    }
+}
+
+impl Serialize for WebhookEvent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for WebhookEvent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl JsonSchema for WebhookEvent {
+    fn schema_name() -> String {
+        "WebhookEvent".to_string()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        String::json_schema(generator)
+    }
+}
+
+/// Space separated list of events as stored in the `webhooks.events` column.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct WebhookEvents(pub Vec<WebhookEvent>);
+
+impl fmt::Display for WebhookEvents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let joined: Vec<_> = self.0.iter().map(WebhookEvent::to_string).collect();
+        write!(f, "{}", joined.join(" "))
+    }
+}
+
+impl TryFrom<String> for WebhookEvents {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value
+            .split_whitespace()
+            .map(WebhookEvent::from_str)
+            .collect::<Result<_, _>>()
+            .map(WebhookEvents)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Webhook {
+    pub id: WebhookId,
+    pub user_id: UserId,
+    pub url: String,
+    /// Key for the `X-AIP-Signature` header
+    pub secret: String,
+    #[sqlx(try_from = "String")]
+    pub events: WebhookEvents,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Webhook {
+    pub async fn insert(
+        user_id: UserId,
+        url: &str,
+        events: &WebhookEvents,
+        client: &SqliteClient,
+    ) -> Result<Webhook, sqlx::Error> {
+        let secret = format!("whsec_{}", hex::encode(rand::random::<[u8; 24]>()));
+
+        sqlx::query_as::<_, Webhook>(
+            r#"
+            INSERT INTO webhooks (user_id, url, secret, events, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, user_id, url, secret, events, created_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(url)
+        .bind(secret)
+        .bind(events.to_string())
+        .bind(Utc::now())
+        .fetch_one(client)
+        .await
+    }
+
+    pub async fn get_by_user_id(
+        user_id: UserId,
+        client: &SqliteClient,
+    ) -> Result<Vec<Webhook>, sqlx::Error> {
+        sqlx::query_as::<_, Webhook>(
+            r#"
+            SELECT id, user_id, url, secret, events, created_at
+            FROM webhooks
+            WHERE user_id = $1
+            ORDER BY created_at
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(client)
+        .await
+    }
+
+    async fn get_subscribed(
+        event: WebhookEvent,
+        client: &SqliteClient,
+    ) -> Result<Vec<Webhook>, sqlx::Error> {
+        let webhooks = sqlx::query_as::<_, Webhook>(
+            r#"
+            SELECT id, user_id, url, secret, events, created_at
+            FROM webhooks
+            "#,
+        )
+        .fetch_all(client)
+        .await?;
+
+        Ok(webhooks
+            .into_iter()
+            .filter(|w| w.events.0.contains(&event))
+            .collect())
+    }
+
+    /// Deletes the webhook and its delivery log, returns whether it existed.
+    pub async fn delete_by_id_and_user_id(
+        id: WebhookId,
+        user_id: UserId,
+        client: &SqliteClient,
+    ) -> Result<bool, sqlx::Error> {
+        let res = sqlx::query(
+            r#"
+            DELETE FROM webhooks
+            WHERE id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .execute(client)
+        .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    /// Gave up after [`MAX_ATTEMPTS`]
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Delivery {
+    pub id: DeliveryId,
+    pub webhook_id: WebhookId,
+    pub url: String,
+    pub event: String,
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: i64,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub last_status_code: Option<i64>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+impl Delivery {
+    /// Most recent deliveries across all of the user's webhooks.
+    pub async fn get_by_user_id(
+        user_id: UserId,
+        limit: i64,
+        client: &SqliteClient,
+    ) -> Result<Vec<Delivery>, sqlx::Error> {
+        sqlx::query_as::<_, Delivery>(
+            r#"
+            SELECT d.id, d.webhook_id, w.url, d.event, d.payload, d.status, d.attempts,
+                   d.next_attempt_at, d.last_status_code, d.last_error, d.created_at, d.delivered_at
+            FROM webhook_deliveries d
+            INNER JOIN webhooks w ON w.id = d.webhook_id
+            WHERE w.user_id = $1
+            ORDER BY d.created_at DESC, d.id DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(client)
+        .await
+    }
+
+    async fn get_due(client: &SqliteClient) -> Result<Vec<(Delivery, String)>, sqlx::Error> {
+        #[derive(FromRow)]
+        struct DueRow {
+            #[sqlx(flatten)]
+            delivery: Delivery,
+            secret: String,
+        }
+
+        let rows = sqlx::query_as::<_, DueRow>(
+            r#"
+            SELECT d.id, d.webhook_id, w.url, w.secret, d.event, d.payload, d.status, d.attempts,
+                   d.next_attempt_at, d.last_status_code, d.last_error, d.created_at, d.delivered_at
+            FROM webhook_deliveries d
+            INNER JOIN webhooks w ON w.id = d.webhook_id
+            WHERE d.status = $1 AND d.next_attempt_at <= $2
+            ORDER BY d.next_attempt_at
+            LIMIT 50
+            "#,
+        )
+        .bind(DeliveryStatus::Pending)
+        .bind(Utc::now())
+        .fetch_all(client)
+        .await?;
+
+        Ok(rows.into_iter().map(|r| (r.delivery, r.secret)).collect())
+    }
+
+    async fn record_attempt(
+        &self,
+        status_code: Option<u16>,
+        error: Option<&str>,
+        client: &SqliteClient,
+    ) -> Result<(), sqlx::Error> {
+        let attempts = self.attempts + 1;
+        let delivered = error.is_none();
+        let now = Utc::now();
+
+        let (status, next_attempt_at) = if delivered {
+            (DeliveryStatus::Delivered, None)
+        } else if attempts >= MAX_ATTEMPTS {
+            (DeliveryStatus::Failed, None)
+        } else {
+            let backoff = RETRY_BASE * 2u32.pow(attempts as u32 - 1);
+            (
+                DeliveryStatus::Pending,
+                Some(now + chrono::Duration::from_std(backoff).unwrap_or_default()),
+            )
+        };
+
+        sqlx::query(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = $2, attempts = $3, next_attempt_at = $4, last_status_code = $5,
+                last_error = $6, delivered_at = $7
+            WHERE id = $1
+            "#,
+        )
+        .bind(self.id)
+        .bind(status)
+        .bind(attempts)
+        .bind(next_attempt_at)
+        .bind(status_code.map(i64::from))
+        .bind(error)
+        .bind(delivered.then_some(now))
+        .execute(client)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Body of every webhook request.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Envelope<'a, T: Serialize> {
+    event: WebhookEvent,
+    created_at: DateTime<Utc>,
+    data: &'a T,
+}
+
+/// Queues a delivery of `data` to every webhook subscribed to `event`.
+pub async fn dispatch<T: Serialize>(
+    event: WebhookEvent,
+    data: &T,
+    client: &SqliteClient,
+) -> Result<(), sqlx::Error> {
+    let webhooks = Webhook::get_subscribed(event, client).await?;
+    if webhooks.is_empty() {
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    let payload = serde_json::to_string(&Envelope {
+        event,
+        created_at: now,
+        data,
+    })
+    .expect("Webhook payloads are always serializable");
+
+    let mut tx = client.begin().await?;
+    for webhook in webhooks {
+        sqlx::query(
+            r#"
+            INSERT INTO webhook_deliveries (webhook_id, event, payload, status, next_attempt_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $5)
+            "#,
+        )
+        .bind(webhook.id)
+        .bind(event.to_string())
+        .bind(&payload)
+        .bind(DeliveryStatus::Pending)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct VersionUploaded<'a> {
+    pilot: &'a AiPilot,
+    version: i32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RatingChanged {
+    pilot_id: Uuid,
+    previous: Rating,
+    current: Rating,
+}
+
+/// Stats to compare ratings with once new matches are stored, so take them before writing.
+/// `None` when nobody is subscribed to `pilot.rating_changed`.
+pub async fn rating_baseline(stats: &StatsCache, client: &SqliteClient) -> Option<Arc<Stats>> {
+    match Webhook::get_subscribed(WebhookEvent::PilotRatingChanged, client).await {
+        Ok(webhooks) if !webhooks.is_empty() => stats.get(client).await.ok(),
+        Ok(_) => None,
+        Err(e) => {
+            log::error!("Failed to fetch pilot.rating_changed webhooks: {}", e);
+            None
+        }
+    }
+}
+
+/// Queues `match.completed` for freshly mirrored matches and `pilot.rating_changed`
+/// for everyone who played in them, compared with `baseline` from [`rating_baseline`].
+/// Expects `matches` to already be stored.
+pub async fn dispatch_new_matches(
+    matches: &[MatchResult],
+    baseline: Option<Arc<Stats>>,
+    stats: &StatsCache,
+    client: &SqliteClient,
+) {
+    if matches.is_empty() {
+        return;
+    }
+
+    for m in matches {
+        if let Err(e) = dispatch(WebhookEvent::MatchCompleted, m, client).await {
+            log::error!("Failed to queue match.completed webhooks: {}", e);
+        }
+    }
+
+    let Some(baseline) = baseline else {
+        return;
+    };
+    let Ok(current) = stats.get(client).await else {
+        return;
+    };
+
+    let mut pilots: Vec<Uuid> = matches
+        .iter()
+        .flat_map(|m| [m.team_a.aip_id, m.team_b.aip_id])
+        .collect();
+    pilots.sort();
+    pilots.dedup();
+
+    for pilot_id in pilots {
+        let change = RatingChanged {
+            pilot_id,
+            previous: baseline.ratings().pilot(&pilot_id),
+            current: current.ratings().pilot(&pilot_id),
+        };
+        if change.previous == change.current {
+            continue;
+        }
+
+        if let Err(e) = dispatch(WebhookEvent::PilotRatingChanged, &change, client).await {
+            log::error!("Failed to queue pilot.rating_changed webhooks: {}", e);
+        }
+    }
+}
+
+/// Queues `pilot.version_uploaded` for freshly mirrored versions.
+pub async fn dispatch_new_versions(versions: &[(AiPilot, i32)], client: &SqliteClient) {
+    for (pilot, version) in versions {
+        let data = VersionUploaded {
+            pilot,
+            version: *version,
+        };
+        if let Err(e) = dispatch(WebhookEvent::PilotVersionUploaded, &data, client).await {
+            log::error!("Failed to queue pilot.version_uploaded webhooks: {}", e);
+        }
+    }
+}
+
+/// Hex encoded HMAC-SHA256 of `"{timestamp}.{payload}"`, sent as `X-AIP-Signature: sha256=...`.
+/// Including the timestamp lets receivers reject replayed requests.
+pub fn sign(secret: &str, timestamp: i64, payload: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}", timestamp, payload).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// IPv4 special-purpose ranges from the IANA registry, plus multicast and the reserved
+/// 240.0.0.0/4 which ends in the broadcast address.
+const SPECIAL_V4: [(Ipv4Addr, u8); 18] = [
+    (Ipv4Addr::new(0, 0, 0, 0), 8),
+    (Ipv4Addr::new(10, 0, 0, 0), 8),
+    (Ipv4Addr::new(100, 64, 0, 0), 10),
+    (Ipv4Addr::new(127, 0, 0, 0), 8),
+    (Ipv4Addr::new(169, 254, 0, 0), 16),
+    (Ipv4Addr::new(172, 16, 0, 0), 12),
+    (Ipv4Addr::new(192, 0, 0, 0), 24),
+    (Ipv4Addr::new(192, 0, 2, 0), 24),
+    (Ipv4Addr::new(192, 31, 196, 0), 24),
+    (Ipv4Addr::new(192, 52, 193, 0), 24),
+    (Ipv4Addr::new(192, 88, 99, 0), 24),
+    (Ipv4Addr::new(192, 168, 0, 0), 16),
+    (Ipv4Addr::new(192, 175, 48, 0), 24),
+    (Ipv4Addr::new(198, 18, 0, 0), 15),
+    (Ipv4Addr::new(198, 51, 100, 0), 24),
+    (Ipv4Addr::new(203, 0, 113, 0), 24),
+    (Ipv4Addr::new(224, 0, 0, 0), 4),
+    (Ipv4Addr::new(240, 0, 0, 0), 4),
+];
+
+/// IPv6 special-purpose ranges from the IANA registry, plus multicast and the deprecated
+/// site-local range. NAT64 and 6to4 addresses are judged by the IPv4 address they embed.
+const SPECIAL_V6: [(Ipv6Addr, u8); 12] = [
+    // Unspecified, loopback and the deprecated IPv4-compatible addresses
+    (Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0), 96),
+    (Ipv6Addr::new(0x64, 0xff9b, 1, 0, 0, 0, 0, 0), 48),
+    (Ipv6Addr::new(0x100, 0, 0, 0, 0, 0, 0, 0), 64),
+    // IETF protocol assignments, Teredo, benchmarking and ORCHID among them
+    (Ipv6Addr::new(0x2001, 0, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0), 32),
+    (Ipv6Addr::new(0x2620, 0x4f, 0x8000, 0, 0, 0, 0, 0), 48),
+    (Ipv6Addr::new(0x3fff, 0, 0, 0, 0, 0, 0, 0), 20),
+    (Ipv6Addr::new(0x5f00, 0, 0, 0, 0, 0, 0, 0), 16),
+    (Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0), 7),
+    (Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0), 10),
+    (Ipv6Addr::new(0xfec0, 0, 0, 0, 0, 0, 0, 0), 10),
+    (Ipv6Addr::new(0xff00, 0, 0, 0, 0, 0, 0, 0), 8),
+];
+
+const NAT64: (Ipv6Addr, u8) = (Ipv6Addr::new(0x64, 0xff9b, 0, 0, 0, 0, 0, 0), 96);
+const SIX_TO_FOUR: (Ipv6Addr, u8) = (Ipv6Addr::new(0x2002, 0, 0, 0, 0, 0, 0, 0), 16);
+
+fn in_v4(ip: Ipv4Addr, (net, len): (Ipv4Addr, u8)) -> bool {
+    let mask = u32::MAX.checked_shl(32 - len as u32).unwrap_or(0);
+    u32::from(ip) & mask == u32::from(net) & mask
+}
+
+fn in_v6(ip: Ipv6Addr, (net, len): (Ipv6Addr, u8)) -> bool {
+    let mask = u128::MAX.checked_shl(128 - len as u32).unwrap_or(0);
+    u128::from(ip) & mask == u128::from(net) & mask
+}
+
+/// The IPv4 address a v4-mapped, NAT64 or 6to4 address reaches.
+fn embedded_v4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let bits = u128::from(ip);
+    if let Some(ip) = ip.to_ipv4_mapped() {
+        Some(ip)
+    } else if in_v6(ip, NAT64) {
+        Some(Ipv4Addr::from(bits as u32))
+    } else if in_v6(ip, SIX_TO_FOUR) {
+        Some(Ipv4Addr::from((bits >> 80) as u32))
+    } else {
+        None
+    }
+}
+
+/// Whether deliveries may be sent to `ip`. Anything on the server's own network or
+/// otherwise reserved is off limits, or a webhook could be pointed at internal services.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => !SPECIAL_V4.iter().any(|range| in_v4(ip, *range)),
+        IpAddr::V6(ip) => match embedded_v4(ip) {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => !SPECIAL_V6.iter().any(|range| in_v6(ip, *range)),
+        },
+    }
+}
+
+/// Resolves `host`, failing unless every address it has is public.
+async fn resolve_public(host: &str, port: u16) -> Result<Vec<SocketAddr>, String> {
+    let addrs: Vec<_> = lookup_host((host, port))
+        .await
+        .map_err(|e| format!("Failed to resolve {}: {}", host, e))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("Failed to resolve {}", host));
+    }
+    if addrs.iter().any(|addr| !is_public(addr.ip())) {
+        return Err(format!("{} does not resolve to a public address", host));
+    }
+    Ok(addrs)
+}
+
+/// Parses a webhook URL, which has to be http or https and only point at public addresses.
+/// Checked when a webhook is created and again before every delivery.
+pub async fn check_url(url: &str) -> Result<reqwest::Url, String> {
+    let url = reqwest::Url::parse(url).map_err(|_| "Invalid webhook URL".to_string())?;
+    let host = match url.host_str() {
+        Some(host) if matches!(url.scheme(), "http" | "https") => host,
+        _ => return Err("Webhook URL must be http or https".into()),
+    };
+
+    // IPv6 hosts keep their brackets
+    match host.trim_matches(['[', ']']).parse::<IpAddr>() {
+        Ok(ip) if is_public(ip) => {}
+        Ok(_) => return Err(format!("{} is not a public address", host)),
+        Err(_) => {
+            let port = url.port_or_known_default().unwrap_or(80);
+            resolve_public(host, port).await?;
+        }
+    }
+    Ok(url)
+}
+
+/// DNS for the delivery client, so a host can't switch to a private address between
+/// [`check_url`] and the request.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = resolve_public(name.as_str(), 0).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Deliveries sent at once, so a few slow endpoints can't hold up everyone else's.
+const MAX_CONCURRENT_DELIVERIES: usize = 8;
+
+/// Background task that sends queued deliveries and reschedules failed ones with backoff.
+#[derive(Debug, Clone)]
+pub struct DeliveryWorker {
+    client: SqliteClient,
+    http: reqwest::Client,
+    interval: Duration,
+}
+
+impl DeliveryWorker {
+    pub fn new(client: SqliteClient) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .dns_resolver(Arc::new(PublicResolver))
+            .user_agent("aip-front-webhooks/1.0")
+            .build()
+            .expect("Failed to build webhook HTTP client");
+
+        let interval = std::env::var("WEBHOOK_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(Duration::from_secs(5));
+
+        DeliveryWorker {
+            client,
+            http,
+            interval,
+        }
+    }
+
+    pub async fn run(self) {
+        loop {
+            match Delivery::get_due(&self.client).await {
+                Ok(due) => {
+                    let worker = &self;
+                    stream::iter(due)
+                        .for_each_concurrent(MAX_CONCURRENT_DELIVERIES, |(delivery, secret)| {
+                            async move { worker.deliver(&delivery, &secret).await }
+                        })
+                        .await;
+                }
+                Err(e) => log::error!("Failed to fetch due webhook deliveries: {}", e),
+            }
+
+            sleep(self.interval).await;
+        }
+    }
+
+    async fn deliver(&self, delivery: &Delivery, secret: &str) {
+        let (status_code, error) = match check_url(&delivery.url).await {
+            Ok(url) => self.send(url, delivery, secret).await,
+            Err(e) => (None, Some(e)),
+        };
+
+        if let Err(e) = delivery
+            .record_attempt(status_code, error.as_deref(), &self.client)
+            .await
+        {
+            log::error!("Failed to record webhook delivery {}: {}", delivery.id, e);
+        }
+    }
+
+    /// Posts the delivery, returning the status code and what went wrong if it failed.
+    async fn send(
+        &self,
+        url: reqwest::Url,
+        delivery: &Delivery,
+        secret: &str,
+    ) -> (Option<u16>, Option<String>) {
+        let timestamp = Utc::now().timestamp();
+        let res = self
+            .http
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header("X-AIP-Event", &delivery.event)
+            .header("X-AIP-Delivery", delivery.id.to_string())
+            .header("X-AIP-Timestamp", timestamp.to_string())
+            .header(
+                "X-AIP-Signature",
+                format!("sha256={}", sign(secret, timestamp, &delivery.payload)),
+            )
+            .body(delivery.payload.clone())
+            .send()
+            .await;
+
+        match res {
+            Ok(response) if response.status().is_success() => {
+                (Some(response.status().as_u16()), None)
+            }
+            // Response bodies stay out of the delivery log, its owner can read it
+            Ok(response) => (
+                Some(response.status().as_u16()),
+                Some(format!("HTTP {}", response.status())),
+            ),
+            Err(e) => (None, Some(e.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public(ip: &str) -> bool {
+        is_public(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn public_addresses() {
+        for ip in [
+            "1.1.1.1",
+            "8.8.8.8",
+            "100.63.255.255",
+            "100.128.0.0",
+            "198.17.255.255",
+            "198.20.0.0",
+            "223.255.255.255",
+            "2606:4700:4700::1111",
+            "2a00:1450:4001::200e",
+        ] {
+            assert!(public(ip), "{} should be public", ip);
+        }
+    }
+
+    #[test]
+    fn special_v4_ranges() {
+        // First and last address of every range
+        for ip in [
+            "0.0.0.0",
+            "0.255.255.255",
+            "10.0.0.0",
+            "10.255.255.255",
+            "100.64.0.0",
+            "100.127.255.255",
+            "127.0.0.1",
+            "127.255.255.255",
+            "169.254.0.0",
+            "169.254.169.254",
+            "172.16.0.0",
+            "172.31.255.255",
+            "192.0.0.0",
+            "192.0.0.255",
+            "192.0.2.1",
+            "192.31.196.1",
+            "192.52.193.1",
+            "192.88.99.1",
+            "192.168.0.1",
+            "192.168.255.255",
+            "192.175.48.1",
+            "198.18.0.0",
+            "198.19.255.255",
+            "198.51.100.1",
+            "203.0.113.1",
+            "224.0.0.1",
+            "239.255.255.255",
+            "240.0.0.1",
+            "255.255.255.255",
+        ] {
+            assert!(!public(ip), "{} should not be public", ip);
+        }
+    }
+
+    #[test]
+    fn special_v6_ranges() {
+        for ip in [
+            "::",
+            "::1",
+            "::10.0.0.1",
+            "64:ff9b:1::1",
+            "100::1",
+            "2001::1",
+            "2001:1ff:ffff::1",
+            "2001:db8::1",
+            "2620:4f:8000::1",
+            "3fff::1",
+            "5f00::1",
+            "fc00::1",
+            "fdff:ffff::1",
+            "fe80::1",
+            "febf::1",
+            "fec0::1",
+            "ff02::1",
+        ] {
+            assert!(!public(ip), "{} should not be public", ip);
+        }
+    }
+
+    #[test]
+    fn embedded_v4_addresses() {
+        for (ip, expected) in [
+            ("::ffff:127.0.0.1", false),
+            ("::ffff:10.1.2.3", false),
+            ("::ffff:8.8.8.8", true),
+            ("64:ff9b::127.0.0.1", false),
+            ("64:ff9b::a9fe:a9fe", false),
+            ("64:ff9b::100.64.0.1", false),
+            ("64:ff9b::8.8.8.8", true),
+            ("2002:7f00:1::", false),
+            ("2002:c0a8:101::1", false),
+            ("2002:a9fe:a9fe::", false),
+            ("2002:808:808::1", true),
+        ] {
+            assert_eq!(public(ip), expected, "{}", ip);
+        }
+    }
+}
@@ -0,0 +1,1032 @@
+mod common;
+
+use aip_front::{mirror, scope::Scope, tournament::Tournament};
+use client::models::{AiPilot, AipVersion, match_result::Winner};
+use common::{ALICE, BOB, TestApp, location};
+use rocket::http::{ContentType, Header, Status};
+use serde_json::json;
+use uuid::Uuid;
+
+#[rocket::async_test]
+async fn health_check() {
+    let app = TestApp::new("random").await;
+
+    let response = app.client.get("/api/healthz").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().await.unwrap(), "OK");
+}
+
+#[rocket::async_test]
+async fn requires_auth() {
+    let app = TestApp::new("random").await;
+
+    let response = app.client.get("/api/aipilots").dispatch().await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(location(&response), Some("/login?next=/api/aipilots"));
+
+    let response = app
+        .client
+        .get("/api/aipilots")
+        .header(Header::new("x-auth-token", "aip_wrong"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+}
+
+#[rocket::async_test]
+async fn lists_pilots_with_creators() {
+    let app = TestApp::new("random").await;
+    app.sync().await;
+    let token = app.token(ALICE).await;
+
+    let (status, body) = app.get_json("/api/aipilots", &token).await;
+    assert_eq!(status, Status::Ok);
+    let pilots = body["pilots"].as_array().unwrap();
+    assert_eq!(pilots.len(), 2);
+
+    let alpha = pilots.iter().find(|p| p["name"] == "alpha").unwrap();
+    assert_eq!(alpha["ownerId"], ALICE);
+    assert_eq!(alpha["current"]["version"], 1);
+    assert_eq!(alpha["creator"]["username"], "alice");
+
+    let (status, body) = app.get_json("/api/aipilots?name=beta", &token).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["pilots"][0]["creator"]["username"], "bob");
+
+    let (status, body) = app.get_json("/api/aipilots?name=gamma", &token).await;
+    assert_eq!(status, Status::NotFound);
+    assert_eq!(body["message"], "Pilot not found");
+}
+
+#[rocket::async_test]
+async fn lists_and_filters_matches() {
+    let app = TestApp::new("a,b,a").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+    app.fight("beta", "alpha").await;
+    let token = app.token(BOB).await;
+
+    let (status, body) = app.get_json("/api/matches", &token).await;
+    assert_eq!(status, Status::Ok);
+    let matches = body["matches"].as_array().unwrap();
+    assert_eq!(matches.len(), 3);
+    assert!(body["nextCursor"].is_null());
+
+    let (_, body) = app.get_json("/api/matches?winner=alpha", &token).await;
+    assert_eq!(body["matches"].as_array().unwrap().len(), 1);
+
+    let (_, body) = app.get_json("/api/matches?winner=beta", &token).await;
+    assert_eq!(body["matches"].as_array().unwrap().len(), 2);
+
+    let (_, body) = app.get_json("/api/matches?limit=2", &token).await;
+    assert_eq!(body["matches"].as_array().unwrap().len(), 2);
+    let cursor = body["nextCursor"].as_str().unwrap();
+    let (_, body) = app
+        .get_json(&format!("/api/matches?limit=2&cursor={}", cursor), &token)
+        .await;
+    assert_eq!(body["matches"].as_array().unwrap().len(), 1);
+}
+
+#[rocket::async_test]
+async fn prunes_removed_pilots() {
+    let app = TestApp::new("a").await;
+    app.fight("alpha", "beta").await;
+
+    let pilots = mirror::get_pilots(&app.db).await.unwrap();
+    let alpha: Vec<_> = pilots.into_iter().filter(|p| p.name == "alpha").collect();
+    assert_eq!(mirror::prune_pilots(&alpha, &app.db).await.unwrap(), 1);
+
+    let pilots = mirror::get_pilots(&app.db).await.unwrap();
+    assert_eq!(pilots.len(), 1);
+    assert_eq!(pilots[0].name, "alpha");
+    assert_eq!(
+        mirror::get_matches(None, None, &app.db)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(mirror::prune_pilots(&alpha, &app.db).await.unwrap(), 0);
+}
+
+#[rocket::async_test]
+async fn syncs_new_matches_between_full_syncs() {
+    let app = TestApp::new("a,b").await;
+    app.sync().await;
+    let token = app.token(BOB).await;
+
+    // Not a full sync, which only comes around every hour
+    app.fight("alpha", "beta").await;
+    let (_, body) = app.get_json("/api/sync_status", &token).await;
+    let last_full_sync = body["lastFullSyncAt"].clone();
+    app.fight("beta", "alpha").await;
+    let (_, body) = app.get_json("/api/sync_status", &token).await;
+    assert_eq!(body["lastFullSyncAt"], last_full_sync);
+
+    let matches = mirror::get_matches(None, None, &app.db).await.unwrap();
+    assert_eq!(matches.len(), 2);
+
+    // Matches without a replay yet are fetched again until upstream fills it in
+    sqlx::query("UPDATE matches SET replay_id = NULL")
+        .execute(&app.db)
+        .await
+        .unwrap();
+    app.sync().await;
+    let matches = mirror::get_matches(None, None, &app.db).await.unwrap();
+    assert!(matches.iter().all(|m| m.replay_id.is_some()));
+}
+
+#[rocket::async_test]
+async fn creates_matches() {
+    let app = TestApp::new("b").await;
+    app.sync().await;
+    let token = app.token(ALICE).await;
+    let alpha = app.pilot_id("alpha").await;
+    let beta = app.pilot_id("beta").await;
+
+    let response = app
+        .client
+        .post(format!("/api/matches?pilot_a={}&pilot_b={}", alpha, beta))
+        .header(Header::new("x-auth-token", token.clone()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let match_id = response.into_string().await.unwrap();
+
+    app.sync().await;
+    let (_, body) = app.get_json("/api/matches", &token).await;
+    assert_eq!(body["matches"][0]["id"], match_id.as_str());
+    assert_eq!(body["matches"][0]["winner"], 1);
+}
+
+#[rocket::async_test]
+async fn creates_matches_by_name() {
+    let app = TestApp::new("a").await;
+    app.sync().await;
+    let token = app.token(ALICE).await;
+
+    let response = app
+        .client
+        .post("/api/matches?pilot_a=beta&pilot_b=alpha")
+        .header(Header::new("x-auth-token", token.clone()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    app.sync().await;
+    let (_, body) = app.get_json("/api/matches", &token).await;
+    let beta = app.pilot_id("beta").await.to_string();
+    assert_eq!(body["matches"][0]["teamA"]["aipId"], beta.as_str());
+}
+
+#[rocket::async_test]
+async fn match_creation_needs_known_pilots() {
+    let app = TestApp::new("random").await;
+    app.sync().await;
+    let token = app.token(ALICE).await;
+
+    let response = app
+        .client
+        .post("/api/matches?pilot_a=alpha&pilot_b=gamma")
+        .header(Header::new("x-auth-token", token.clone()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+    let body: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(body["message"], "Pilot gamma not found");
+}
+
+#[rocket::async_test]
+async fn uploads_pilots() {
+    let app = TestApp::new("random").await;
+    app.sync().await;
+    let token = app.token(BOB).await;
+
+    let response = app
+        .client
+        .post("/api/aipilot/upload?name=gamma")
+        .header(Header::new("x-auth-token", token.clone()))
+        .header(ContentType::Binary)
+        .body("pilot")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(body["version"], 1);
+
+    // Visible right away, without waiting for a sync
+    let (_, body) = app.get_json("/api/aipilots?name=gamma", &token).await;
+    assert_eq!(body["pilots"][0]["ownerId"], BOB);
+
+    let response = app
+        .client
+        .post("/api/aipilot/upload?name=no")
+        .header(Header::new("x-auth-token", token))
+        .body("pilot")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::BadRequest);
+    let body: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(body["message"], "Invalid name format");
+}
+
+#[rocket::async_test]
+async fn upstream_unavailable() {
+    let app = TestApp::offline().await;
+    let token = app.token(ALICE).await;
+
+    // Mirrored before the upstream went away
+    let pilots = [("alpha", ALICE), ("beta", BOB)].map(|(name, owner)| {
+        AiPilot::new(
+            Uuid::new_v4(),
+            name.into(),
+            owner.into(),
+            AipVersion::default(),
+            Vec::new(),
+        )
+    });
+    mirror::upsert_pilots(&pilots, &app.db).await.unwrap();
+
+    let response = app
+        .client
+        .post(format!(
+            "/api/matches?pilot_a={}&pilot_b={}",
+            pilots[0].id, pilots[1].id
+        ))
+        .header(Header::new("x-auth-token", token.clone()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    let body: serde_json::Value = response.into_json().await.unwrap();
+    assert!(body["message"].as_str().unwrap().contains("unavailable"));
+
+    // Data already mirrored stays readable
+    let (status, body) = app.get_json("/api/matches", &token).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["matches"], serde_json::json!([]));
+}
+
+#[rocket::async_test]
+async fn exports_matches() {
+    let app = TestApp::new("a,b,a").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+    app.fight("beta", "alpha").await;
+    let token = app.token(BOB).await;
+
+    let response = app
+        .client
+        .get("/api/export/matches?sort=oldest")
+        .header(Header::new("x-auth-token", token.clone()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::CSV));
+    let body = response.into_string().await.unwrap();
+    let lines: Vec<_> = body.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("id,created_at,pilot_a,"));
+    assert!(lines[1].contains(",alpha,") && lines[1].contains(",beta,"));
+
+    let response = app
+        .client
+        .get("/api/export/pilots/alpha?format=ndjson&winner=beta")
+        .header(Header::new("x-auth-token", token.clone()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let body = response.into_string().await.unwrap();
+    let rows: Vec<serde_json::Value> = body
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(rows.len(), 2);
+    assert!(rows.iter().all(|r| r["winner"] == "beta"));
+    assert_eq!(rows[0]["manual"], true);
+
+    let (status, _) = app.get_json("/api/export/pilots/gamma", &token).await;
+    assert_eq!(status, Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn leaderboard() {
+    let app = TestApp::new("a,b,a").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+    app.insert_match("beta", "alpha", Winner::TeamA, 10).await;
+    app.insert_match("beta", "alpha", Winner::TeamA, 10).await;
+    let token = app.token(BOB).await;
+
+    let (status, body) = app
+        .get_json("/api/leaderboard?window=7d&min_games=1", &token)
+        .await;
+    assert_eq!(status, Status::Ok);
+    let entries = body["entries"].as_array().unwrap();
+    assert_eq!(entries[0]["name"], "alpha");
+    assert_eq!(entries[0]["wins"], 2);
+    assert_eq!(entries[0]["previousRank"], 2);
+    assert_eq!(entries[1]["name"], "beta");
+    assert_eq!(entries[1]["previousRank"], 1);
+
+    let (_, body) = app.get_json("/api/leaderboard", &token).await;
+    let entries = body["entries"].as_array().unwrap();
+    assert_eq!(entries[0]["name"], "beta");
+    assert_eq!(entries[0]["matches"], 5);
+    assert!(entries[0]["previousRank"].is_null());
+
+    // Three games this week, short of the default minimum
+    let (_, body) = app.get_json("/api/leaderboard?window=7d", &token).await;
+    assert_eq!(body["entries"], serde_json::json!([]));
+
+    let (_, body) = app
+        .get_json("/api/leaderboard?manual=false&min_games=1", &token)
+        .await;
+    let entries = body["entries"].as_array().unwrap();
+    assert_eq!(entries[0]["name"], "beta");
+    assert_eq!(entries[0]["winRate"], 100.0);
+
+    let (_, body) = app
+        .get_json("/api/leaderboard?window=current&sort=rating", &token)
+        .await;
+    assert_eq!(body["entries"][0]["version"], 1);
+}
+
+#[rocket::async_test]
+async fn leaderboard_undecided() {
+    let app = TestApp::new("a,unknown").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+    let token = app.token(BOB).await;
+
+    let (_, body) = app.get_json("/api/leaderboard?min_games=1", &token).await;
+    let entry = &body["entries"][0];
+    assert_eq!(entry["name"], "alpha");
+    assert_eq!(entry["matches"], 2);
+    assert_eq!(entry["undecided"], 1);
+    assert_eq!(entry["winRate"], 100.0);
+
+    // One win and a void are a single decided match
+    let (_, body) = app.get_json("/api/leaderboard?min_games=2", &token).await;
+    assert_eq!(body["entries"], serde_json::json!([]));
+}
+
+#[rocket::async_test]
+async fn pilot_stats() {
+    let app = TestApp::new("a,b,unknown").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+    let token = app.token(BOB).await;
+
+    let (status, body) = app.get_json("/api/pilots/alpha/stats", &token).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["name"], "alpha");
+    assert_eq!(body["matches"], 3);
+    assert_eq!(body["wins"], 1);
+    assert_eq!(body["losses"], 1);
+    assert_eq!(body["undecided"], 1);
+    assert_eq!(body["winRate"], 50.0);
+    assert_eq!(body["versions"][0]["version"], 1);
+    assert_eq!(body["versions"][0]["trend"], "neutral");
+    assert_eq!(body["opponents"][0]["name"], "beta");
+    assert_eq!(body["opponents"][0]["matches"], 3);
+
+    // The same numbers from the other side, looked up by id
+    let id = body["opponents"][0]["pilotId"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let (_, body) = app
+        .get_json(&format!("/api/pilots/{}/stats", id), &token)
+        .await;
+    assert_eq!(body["wins"], 1);
+    assert_eq!(body["losses"], 1);
+
+    let (status, _) = app.get_json("/api/pilots/gamma/stats", &token).await;
+    assert_eq!(status, Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn page_data() {
+    let app = TestApp::new("a,b,a").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+    let token = app.token(BOB).await;
+
+    let (status, body) = app
+        .get_json("/api/pilots/alpha/versions/1/stats", &token)
+        .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["version"], 1);
+    assert_eq!(body["wins"], 2);
+    assert_eq!(body["opponents"][0]["name"], "beta");
+    assert_eq!(body["recentMatches"].as_array().unwrap().len(), 3);
+    let (status, _) = app
+        .get_json("/api/pilots/alpha/versions/9/stats", &token)
+        .await;
+    assert_eq!(status, Status::NotFound);
+
+    let (_, body) = app.get_json("/api/pilots/alpha/stats", &token).await;
+    let latest = &body["recentMatches"][0];
+    assert_eq!(latest["teamA"]["name"], "alpha");
+    assert_eq!(latest["teamA"]["winner"], true);
+    assert!(
+        latest["replayUrl"]
+            .as_str()
+            .unwrap()
+            .contains("/replay?replayId=")
+    );
+
+    let id = latest["id"].as_str().unwrap().to_string();
+    let (status, body) = app.get_json(&format!("/api/match/{}", id), &token).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["teamB"]["name"], "beta");
+    assert_eq!(body["teamB"]["winner"], false);
+    let (status, page) = app.get_html(&format!("/match/{}", id)).await;
+    assert_eq!(status, Status::Ok);
+    assert!(page.contains("beta v1"));
+    let replay_id = body["replayUrl"].as_str().unwrap().split('=').next_back();
+    assert!(page.contains(replay_id.unwrap()));
+
+    let (_, body) = app.get_json("/api/users", &token).await;
+    let users = body["users"].as_array().unwrap();
+    assert_eq!(users.len(), 2);
+    assert_eq!(users[0]["username"], "alice");
+    assert_eq!(users[0]["pilots"], serde_json::json!(["alpha"]));
+    assert_eq!(users[0]["wins"], 2);
+
+    let (status, body) = app.get_json(&format!("/api/user/{}", BOB), &token).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["username"], "bob");
+    assert_eq!(body["losses"], 2);
+    assert_eq!(body["pilotStats"][0]["name"], "beta");
+    assert_eq!(body["recentMatches"].as_array().unwrap().len(), 3);
+    let (status, _) = app.get_json("/api/user/333", &token).await;
+    assert_eq!(status, Status::NotFound);
+
+    let (_, spec) = app.get_json("/api/openapi.json", &token).await;
+    for path in [
+        "/pilots/{name}/stats",
+        "/pilots/{name}/versions/{version}/stats",
+        "/users",
+        "/user/{owner_id}",
+        "/match/{match_id}",
+    ] {
+        assert!(
+            spec["paths"][path].is_object(),
+            "{} missing from spec",
+            path
+        );
+    }
+}
+
+#[rocket::async_test]
+async fn webhooks_only_reach_public_addresses() {
+    let app = TestApp::new("random").await;
+    let token = app.token(BOB).await;
+
+    let internal = [
+        "http://127.0.0.1:8000/hook",
+        "http://localhost/hook",
+        "http://10.0.0.5/hook",
+        "http://192.168.1.1/hook",
+        "http://169.254.169.254/latest/meta-data/",
+        "http://0.0.0.0/hook",
+        "http://[::1]/hook",
+        "http://[::ffff:127.0.0.1]/hook",
+        "http://[fd00::1]/hook",
+        "ftp://93.184.215.14/hook",
+    ];
+    for url in internal {
+        let (status, _) = app
+            .post_json(
+                "/api/webhooks",
+                &token,
+                serde_json::json!({ "url": url, "events": ["match.completed"] }),
+            )
+            .await;
+        assert_eq!(status, Status::BadRequest, "{}", url);
+    }
+
+    let (status, body) = app
+        .post_json(
+            "/api/webhooks",
+            &token,
+            serde_json::json!({ "url": "https://93.184.215.14/hook", "events": ["match.completed"] }),
+        )
+        .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["url"], "https://93.184.215.14/hook");
+}
+
+#[rocket::async_test]
+async fn tournaments() {
+    let app = TestApp::new("a").await;
+    app.sync().await;
+    let alice = app.token(ALICE).await;
+    let bob = app.token(BOB).await;
+
+    let (status, body) = app
+        .post_json(
+            "/api/tournaments",
+            &alice,
+            json!({
+                "name": "Cup",
+                "format": "round_robin",
+                "entrants": [{ "pilot": "alpha" }, { "pilot": "beta", "version": 1 }],
+            }),
+        )
+        .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["status"], "pending");
+    assert_eq!(body["totalRounds"], 1);
+    let id = body["id"].as_i64().unwrap();
+
+    let (_, body) = app.get_json("/api/tournaments", &bob).await;
+    assert_eq!(body[0]["name"], "Cup");
+
+    // Only the creator may start it, and only once
+    let uri = format!("/api/tournament/{}/start", id);
+    let (status, _) = app.post_json(&uri, &bob, json!(null)).await;
+    assert_eq!(status, Status::Forbidden);
+    let (status, body) = app.post_json(&uri, &alice, json!(null)).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["tournament"]["status"], "running");
+    assert_eq!(body["rounds"][0]["games"].as_array().unwrap().len(), 1);
+    let (status, _) = app.post_json(&uri, &alice, json!(null)).await;
+    assert_eq!(status, Status::BadRequest);
+
+    let (status, body) = app.get_json(&format!("/api/tournament/{}", id), &bob).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["standings"].as_array().unwrap().len(), 2);
+    let (status, _) = app.get_json("/api/tournament/999", &bob).await;
+    assert_eq!(status, Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn tournaments_start_once() {
+    let app = TestApp::new("a").await;
+    app.sync().await;
+    let token = app.token(ALICE).await;
+    let create = || {
+        app.post_json(
+            "/api/tournaments",
+            &token,
+            json!({
+                "name": "Cup",
+                "format": "round_robin",
+                "entrants": [{ "pilot": "alpha" }, { "pilot": "beta" }],
+            }),
+        )
+    };
+    let rounds = async |id: i64| {
+        sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM tournament_games WHERE tournament_id = $1",
+        )
+        .bind(id)
+        .fetch_one(&app.db)
+        .await
+        .unwrap()
+    };
+
+    // A double click
+    let (_, body) = create().await;
+    let id = body["id"].as_i64().unwrap();
+    let uri = format!("/api/tournament/{}/start", id);
+    let ((first, _), (second, _)) = rocket::tokio::join!(
+        app.post_json(&uri, &token, json!(null)),
+        app.post_json(&uri, &token, json!(null)),
+    );
+    let mut statuses = [first, second];
+    statuses.sort_by_key(|s| s.code);
+    assert_eq!(statuses, [Status::Ok, Status::BadRequest]);
+    assert_eq!(rounds(id).await, 1);
+
+    // Both loaded before either started
+    let (_, body) = create().await;
+    let id = body["id"].as_i64().unwrap();
+    let stale = Tournament::get_by_id(id, &app.db).await.unwrap();
+    let tournament = Tournament::get_by_id(id, &app.db).await.unwrap();
+    assert!(tournament.advance_round(&app.db).await.unwrap());
+    assert!(!stale.advance_round(&app.db).await.unwrap());
+    assert_eq!(rounds(id).await, 1);
+}
+
+#[rocket::async_test]
+async fn tournaments_reject_bad_entrants() {
+    let app = TestApp::new("a").await;
+    app.sync().await;
+    let token = app.token(ALICE).await;
+
+    for (entrants, message) in [
+        (
+            json!([{ "pilot": "alpha" }]),
+            "A tournament needs at least two entrants",
+        ),
+        (
+            json!([{ "pilot": "alpha" }, { "pilot": "gamma" }]),
+            "Unknown pilot gamma",
+        ),
+        (
+            json!([{ "pilot": "alpha" }, { "pilot": "beta", "version": 9 }]),
+            "Pilot beta has no version 9",
+        ),
+        (
+            json!([{ "pilot": "alpha" }, { "pilot": "alpha" }]),
+            "Pilot alpha is entered more than once",
+        ),
+    ] {
+        let (status, body) = app
+            .post_json(
+                "/api/tournaments",
+                &token,
+                json!({ "name": "Cup", "format": "swiss", "entrants": entrants }),
+            )
+            .await;
+        assert_eq!(status, Status::BadRequest);
+        assert_eq!(body["message"], message);
+    }
+
+    let (_, body) = app.get_json("/api/tournaments", &token).await;
+    assert_eq!(body, json!([]));
+}
+
+#[rocket::async_test]
+async fn user_tokens() {
+    let app = TestApp::new("random").await;
+    app.sync().await;
+    let alice = app.token(ALICE).await;
+    let bob = app.token(BOB).await;
+
+    let (status, body) = app
+        .post_json(
+            "/api/user_token",
+            &alice,
+            json!({ "name": "ci", "scopes": ["pilots:read"] }),
+        )
+        .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["scopes"], json!(["pilots:read"]));
+    let secret = body["secret"].as_str().unwrap().to_string();
+    assert!(secret.starts_with(body["token_prefix"].as_str().unwrap()));
+    let id = body["id"].as_i64().unwrap();
+
+    let (status, _) = app.get_json("/api/aipilots", &secret).await;
+    assert_eq!(status, Status::Ok);
+
+    // Someone else's token is as good as missing
+    let uri = format!("/api/user_token/{}", id);
+    let (status, _) = app.delete(&uri, &bob).await;
+    assert_eq!(status, Status::NotFound);
+    let (status, _) = app.delete(&uri, &alice).await;
+    assert_eq!(status, Status::NoContent);
+    let (status, _) = app.delete(&uri, &alice).await;
+    assert_eq!(status, Status::NotFound);
+
+    let (status, _) = app.get_json("/api/aipilots", &secret).await;
+    assert_eq!(status, Status::SeeOther);
+}
+
+#[rocket::async_test]
+async fn user_tokens_cannot_grant_more() {
+    let app = TestApp::new("random").await;
+    let token = app.token(ALICE).await;
+
+    for (scopes, message) in [
+        (json!([]), "A token needs at least one scope"),
+        (json!(["admin"]), "Cannot grant the admin scope"),
+    ] {
+        let (status, body) = app
+            .post_json(
+                "/api/user_token",
+                &token,
+                json!({ "name": "ci", "scopes": scopes }),
+            )
+            .await;
+        assert_eq!(status, Status::BadRequest);
+        assert_eq!(body["message"], message);
+    }
+}
+
+#[rocket::async_test]
+async fn webhooks() {
+    let app = TestApp::new("random").await;
+    let alice = app.token(ALICE).await;
+    let bob = app.token(BOB).await;
+
+    let (status, body) = app
+        .post_json(
+            "/api/webhooks",
+            &bob,
+            json!({
+                "url": "https://93.184.215.14/hook",
+                "events": ["match.completed", "pilot.rating_changed", "match.completed"],
+            }),
+        )
+        .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        body["events"],
+        json!(["match.completed", "pilot.rating_changed"])
+    );
+    assert!(!body["secret"].as_str().unwrap().is_empty());
+    let id = body["id"].as_i64().unwrap();
+
+    let (_, body) = app.get_json("/api/webhooks", &bob).await;
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    let (_, body) = app.get_json("/api/webhooks", &alice).await;
+    assert_eq!(body, json!([]));
+    let (status, body) = app.get_json("/api/webhook/deliveries", &bob).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body, json!([]));
+
+    let uri = format!("/api/webhook/{}", id);
+    let (status, _) = app.delete(&uri, &alice).await;
+    assert_eq!(status, Status::NotFound);
+    let (status, _) = app.delete(&uri, &bob).await;
+    assert_eq!(status, Status::NoContent);
+    let (status, _) = app.delete(&uri, &bob).await;
+    assert_eq!(status, Status::NotFound);
+    let (_, body) = app.get_json("/api/webhooks", &bob).await;
+    assert_eq!(body, json!([]));
+}
+
+#[rocket::async_test]
+async fn webhooks_need_events() {
+    let app = TestApp::new("random").await;
+    let token = app.token(BOB).await;
+
+    let (status, body) = app
+        .post_json(
+            "/api/webhooks",
+            &token,
+            json!({ "url": "https://93.184.215.14/hook", "events": [] }),
+        )
+        .await;
+    assert_eq!(status, Status::BadRequest);
+    assert_eq!(body["message"], "A webhook needs at least one event");
+
+    let (status, _) = app
+        .post_json(
+            "/api/webhooks",
+            &token,
+            json!({ "url": "https://93.184.215.14/hook", "events": ["match.deleted"] }),
+        )
+        .await;
+    assert_eq!(status, Status::UnprocessableEntity);
+}
+
+#[rocket::async_test]
+async fn head_to_head() {
+    let app = TestApp::new("a,b,a").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+    let token = app.token(BOB).await;
+
+    let (status, body) = app.get_json("/api/h2h?a=alpha&b=beta", &token).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["total"], 3);
+    assert_eq!(body["pilotA"]["wins"], 2);
+    assert_eq!(body["pilotB"]["wins"], 1);
+    assert_eq!(body["pilotA"]["streak"], 1);
+    assert_eq!(body["meetings"].as_array().unwrap().len(), 3);
+    assert_eq!(body["meetings"][0]["winner"], "a");
+
+    let (_, body) = app.get_json("/api/h2h?a=beta&b=alpha", &token).await;
+    assert_eq!(body["pilotA"]["name"], "beta");
+    assert_eq!(body["pilotA"]["wins"], 1);
+}
+
+#[rocket::async_test]
+async fn head_to_head_needs_two_pilots() {
+    let app = TestApp::new("random").await;
+    app.sync().await;
+    let token = app.token(BOB).await;
+
+    let (status, body) = app.get_json("/api/h2h?a=alpha&b=alpha", &token).await;
+    assert_eq!(status, Status::BadRequest);
+    assert_eq!(body["message"], "Cannot compare a pilot with itself");
+
+    let (status, body) = app.get_json("/api/h2h?a=alpha&b=gamma", &token).await;
+    assert_eq!(status, Status::NotFound);
+    assert_eq!(body["message"], "Pilot gamma not found");
+}
+
+#[rocket::async_test]
+async fn rate_limits_uploads() {
+    let app = TestApp::new("random").await;
+    let alice = app.token(ALICE).await;
+    let bob = app.token(BOB).await;
+
+    // The default quota is ten uploads an hour
+    for _ in 0..10 {
+        let (status, _) = app.upload("gamma", &bob).await;
+        assert_eq!(status, Status::Ok);
+    }
+    let (status, body) = app.upload("gamma", &bob).await;
+    assert_eq!(status, Status::TooManyRequests);
+    assert!(
+        body["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Too many requests")
+    );
+
+    // Counted per user
+    let (status, _) = app.upload("delta", &alice).await;
+    assert_eq!(status, Status::Ok);
+}
+
+#[rocket::async_test]
+async fn admin_bans() {
+    let app = TestApp::new("random").await;
+    app.sync().await;
+    let admin = app.scoped_token(ALICE, &[Scope::Admin]).await;
+    let bob = app.token(BOB).await;
+    let bob_id = app.user(BOB).await.id;
+    let alice_id = app.user(ALICE).await.id;
+
+    let ban = format!("/api/admin/user/{}/ban", bob_id);
+    let (status, body) = app
+        .post_json(&ban, &admin, json!({ "reason": " spam " }))
+        .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["ban_reason"], "spam");
+
+    let (status, body) = app.upload("beta", &bob).await;
+    assert_eq!(status, Status::Forbidden);
+    assert!(body["message"].as_str().unwrap().ends_with(": spam"));
+
+    let (status, body) = app.delete(&ban, &admin).await;
+    assert_eq!(status, Status::Ok);
+    assert!(body["ban_reason"].is_null());
+    let (status, _) = app.upload("beta", &bob).await;
+    assert_eq!(status, Status::Ok);
+
+    // Revoking someone's token
+    let (_, tokens) = app
+        .get_json(&format!("/api/admin/user/{}/tokens", bob_id), &admin)
+        .await;
+    let uri = format!("/api/admin/user_token/{}", tokens[0]["id"]);
+    let (status, _) = app.delete(&uri, &admin).await;
+    assert_eq!(status, Status::NoContent);
+    let (status, _) = app.delete(&uri, &admin).await;
+    assert_eq!(status, Status::NotFound);
+    let (status, _) = app.get_json("/api/aipilots", &bob).await;
+    assert_eq!(status, Status::SeeOther);
+
+    let (status, _) = app
+        .post_json(
+            &format!("/api/admin/user/{}/ban", alice_id),
+            &admin,
+            json!({ "reason": "oops" }),
+        )
+        .await;
+    assert_eq!(status, Status::BadRequest);
+    let (status, body) = app.post_json(&ban, &admin, json!({ "reason": " " })).await;
+    assert_eq!(status, Status::BadRequest);
+    assert_eq!(body["message"], "A ban needs a reason");
+    let (status, _) = app
+        .post_json(
+            "/api/admin/user/999/ban",
+            &admin,
+            json!({ "reason": "spam" }),
+        )
+        .await;
+    assert_eq!(status, Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn admin_api_needs_admin() {
+    let app = TestApp::new("random").await;
+    let bob_id = app.user(BOB).await.id;
+    let ban = format!("/api/admin/user/{}/ban", bob_id);
+
+    // Not an admin, even with the scope
+    let bob = app.scoped_token(BOB, &[Scope::Admin]).await;
+    let (status, _) = app
+        .post_json(
+            &format!("/api/admin/user/{}/ban", app.user(ALICE).await.id),
+            &bob,
+            json!({ "reason": "spam" }),
+        )
+        .await;
+    assert_eq!(status, Status::Forbidden);
+    let (status, _) = app.get_json("/api/admin/users", &bob).await;
+    assert_eq!(status, Status::Forbidden);
+
+    // An admin, but the token lacks the scope
+    let alice = app.token(ALICE).await;
+    let (status, _) = app
+        .post_json(&ban, &alice, json!({ "reason": "spam" }))
+        .await;
+    assert_eq!(status, Status::Forbidden);
+    let (status, _) = app.delete(&ban, &alice).await;
+    assert_eq!(status, Status::Forbidden);
+
+    assert!(app.user(BOB).await.banned_at.is_none());
+}
+
+#[rocket::async_test]
+async fn tokens_need_the_route_scope() {
+    let app = TestApp::new("random").await;
+    app.sync().await;
+    let token = app.scoped_token(BOB, &[Scope::PilotsRead]).await;
+
+    let (status, _) = app.get_json("/api/aipilots", &token).await;
+    assert_eq!(status, Status::Ok);
+
+    let (status, _) = app.get_json("/api/matches", &token).await;
+    assert_eq!(status, Status::Forbidden);
+    let response = app
+        .client
+        .post(format!(
+            "/api/matches?pilot_a={}&pilot_b={}",
+            app.pilot_id("alpha").await,
+            app.pilot_id("beta").await
+        ))
+        .header(Header::new("x-auth-token", token.clone()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+    let (status, _) = app.get_json("/api/webhooks", &token).await;
+    assert_eq!(status, Status::Forbidden);
+    let (status, _) = app.upload("beta", &token).await;
+    assert_eq!(status, Status::Forbidden);
+}
+
+#[rocket::async_test]
+async fn pilot_upload_scope_names_one_pilot() {
+    let app = TestApp::new("random").await;
+    app.sync().await;
+    let token = app
+        .scoped_token(BOB, &[Scope::PilotUpload("beta".into())])
+        .await;
+
+    let (status, body) = app.upload("beta", &token).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["version"], 2);
+
+    for name in ["alpha", "gamma", "betamax"] {
+        let (status, _) = app.upload(name, &token).await;
+        assert_eq!(status, Status::Forbidden, "{}", name);
+    }
+
+    // Nor hand out rights to another pilot
+    let token = app
+        .scoped_token(
+            BOB,
+            &[Scope::TokensManage, Scope::PilotUpload("beta".into())],
+        )
+        .await;
+    let (status, body) = app
+        .post_json(
+            "/api/user_token",
+            &token,
+            json!({ "name": "ci", "scopes": ["pilot:alpha:upload"] }),
+        )
+        .await;
+    assert_eq!(status, Status::BadRequest);
+    assert_eq!(body["message"], "Cannot grant the pilot:alpha:upload scope");
+}
+
+#[rocket::async_test]
+async fn rate_limits_match_creation() {
+    let app = TestApp::new("random").await;
+    app.sync().await;
+    let token = app.token(ALICE).await;
+    let uri = format!(
+        "/api/matches?pilot_a={}&pilot_b={}",
+        app.pilot_id("alpha").await,
+        app.pilot_id("beta").await
+    );
+    let create = || {
+        app.client
+            .post(uri.clone())
+            .header(Header::new("x-auth-token", token.clone()))
+            .dispatch()
+    };
+
+    // One match a minute in tests
+    assert_eq!(create().await.status(), Status::Ok);
+    let response = create().await;
+    assert_eq!(response.status(), Status::TooManyRequests);
+    let retry_after: i64 = response
+        .headers()
+        .get_one("Retry-After")
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=60).contains(&retry_after), "{}", retry_after);
+
+    // Once the window has passed
+    sqlx::query("UPDATE rate_limits SET window_start = window_start - 60")
+        .execute(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(create().await.status(), Status::Ok);
+    assert_eq!(create().await.status(), Status::TooManyRequests);
+}
@@ -0,0 +1,342 @@
+#![allow(dead_code)]
+
+use std::{
+    net::TcpListener,
+    sync::{Arc, Once},
+    time::Duration,
+};
+
+use aip_front::{
+    SqliteClient,
+    api_client::{ApiClient, HttpApiClient},
+    events::Broadcaster,
+    identity::IdentityProvider,
+    mirror,
+    mock_upstream::{self, MockConfig, WinnerPolicy},
+    model::{User, UserToken, UserTokenStore},
+    scope::{Scope, Scopes},
+    sso_client::DiscordUserInfo,
+    stats::StatsCache,
+    sync::SyncWorker,
+};
+use client::models::{MatchResult, TeamInfo, match_result::Winner};
+use rocket::{
+    async_trait,
+    config::LogLevel,
+    http::{ContentType, Header, Status},
+    local::asynchronous::{Client, LocalResponse},
+};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use uuid::Uuid;
+
+/// Discord ids the stub identity provider knows, sign in with the id as the OAuth code.
+pub const ALICE: &str = "111";
+pub const BOB: &str = "222";
+
+/// Signs in whoever's Discord id is passed as the code, standing in for the SSO service.
+struct StubIdentity;
+
+fn stub_user(discord_id: &str) -> Option<DiscordUserInfo> {
+    let username = match discord_id {
+        ALICE => "alice",
+        BOB => "bob",
+        _ => return None,
+    };
+
+    Some(DiscordUserInfo {
+        id: discord_id.to_string(),
+        username: username.to_string(),
+        avatar: String::new(),
+    })
+}
+
+#[async_trait]
+impl IdentityProvider for StubIdentity {
+    fn login_url(&self, state: &str) -> String {
+        format!("/login_callback/{}?code={}", state, ALICE)
+    }
+
+    async fn exchange_code(&self, code: &str) -> Option<DiscordUserInfo> {
+        stub_user(code)
+    }
+
+    async fn lookup_user(&self, discord_id: &str) -> Option<DiscordUserInfo> {
+        stub_user(discord_id)
+    }
+}
+
+/// The site on an in-memory database, talking to a [`mock_upstream`] on a local port.
+pub struct TestApp {
+    pub client: Client,
+    pub db: SqliteClient,
+    pub api_client: Arc<dyn ApiClient>,
+    pub stats: StatsCache,
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .expect("No free port")
+}
+
+fn quiet_config(port: u16) -> rocket::Config {
+    rocket::Config {
+        port,
+        log_level: LogLevel::Off,
+        shutdown: rocket::config::Shutdown {
+            ctrlc: false,
+            ..Default::default()
+        },
+        ..rocket::Config::debug_default()
+    }
+}
+
+async fn launch_upstream(config: MockConfig) -> String {
+    let port = free_port();
+    let upstream = mock_upstream::rocket(config)
+        .configure(quiet_config(port))
+        .ignite()
+        .await
+        .expect("Failed to ignite mock upstream");
+    rocket::tokio::spawn(upstream.launch());
+
+    let url = format!("http://127.0.0.1:{}", port);
+    for _ in 0..100 {
+        if reqwest::get(format!("{}/aipilot", url)).await.is_ok() {
+            return url;
+        }
+        rocket::tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("Mock upstream did not start");
+}
+
+impl TestApp {
+    /// Starts with pilots `alpha` (owned by alice) and `beta` (owned by bob). Fights finish
+    /// immediately, won by `winners` in order.
+    pub async fn new(winners: &str) -> TestApp {
+        let upstream_url = launch_upstream(MockConfig {
+            api_key: Some("key".into()),
+            winner: winners.parse::<WinnerPolicy>().unwrap(),
+            fight_duration: Duration::ZERO,
+            pilots: vec![("alpha".into(), ALICE.into()), ("beta".into(), BOB.into())],
+        })
+        .await;
+
+        TestApp::with_upstream(upstream_url).await
+    }
+
+    /// With nothing listening where the upstream should be.
+    pub async fn offline() -> TestApp {
+        TestApp::with_upstream(format!("http://127.0.0.1:{}", free_port())).await
+    }
+
+    async fn with_upstream(upstream_url: String) -> TestApp {
+        static ENV: Once = Once::new();
+        ENV.call_once(|| {
+            // SAFETY: set once, before any test reads the environment
+            unsafe {
+                std::env::set_var("TOKEN_HASH_KEY", "test");
+                std::env::set_var("ADMIN_DISCORD_IDS", ALICE);
+                std::env::set_var("METRICS_TOKEN", "scrape");
+                // Low enough to hit in a test, every app has its own counters
+                std::env::set_var("RATE_LIMIT_MATCH_CREATE", "1/60");
+            }
+        });
+
+        let api_client: Arc<dyn ApiClient> =
+            Arc::new(HttpApiClient::with_base_url(upstream_url, "key".into()));
+
+        // Every connection to `:memory:` is its own database, so keep exactly one alive
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(SqliteConnectOptions::new().in_memory(true))
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&db).await.unwrap();
+
+        let stats = StatsCache::new();
+        let rocket = aip_front::build(
+            db.clone(),
+            Arc::new(StubIdentity),
+            api_client.clone(),
+            Broadcaster::new(),
+            stats.clone(),
+        )
+        .configure(quiet_config(0));
+        let client = Client::tracked(rocket).await.unwrap();
+
+        TestApp {
+            client,
+            db,
+            api_client,
+            stats,
+        }
+    }
+
+    /// Mirrors the upstream into SQLite, as the sync worker would.
+    pub async fn sync(&self) {
+        SyncWorker::new(
+            self.api_client.clone(),
+            self.db.clone(),
+            Broadcaster::new(),
+            self.stats.clone(),
+        )
+        .sync_once()
+        .await
+        .unwrap();
+    }
+
+    /// Id of the mirrored pilot `name`.
+    pub async fn pilot_id(&self, name: &str) -> Uuid {
+        mirror::get_pilot_by_name(name, &self.db)
+            .await
+            .unwrap()
+            .unwrap()
+            .id
+    }
+
+    /// Fights pilots `a` against `b` upstream and mirrors the result.
+    pub async fn fight(&self, a: &str, b: &str) {
+        self.sync().await;
+        let a = self.pilot_id(a).await.to_string();
+        let b = self.pilot_id(b).await.to_string();
+        self.api_client.create_match(&a, &b).await.unwrap();
+        self.sync().await;
+    }
+
+    /// Mirrors a finished automatic match between `a` and `b` directly, `days_ago` days old.
+    pub async fn insert_match(&self, a: &str, b: &str, winner: Winner, days_ago: i64) {
+        self.sync().await;
+        let a = mirror::get_pilot_by_name(a, &self.db)
+            .await
+            .unwrap()
+            .unwrap();
+        let b = mirror::get_pilot_by_name(b, &self.db)
+            .await
+            .unwrap()
+            .unwrap();
+        let created_at = (chrono::Utc::now() - chrono::Duration::days(days_ago)).timestamp_millis();
+
+        let m = MatchResult::new(
+            Uuid::new_v4(),
+            TeamInfo::new(a.id, a.current.version),
+            TeamInfo::new(b.id, b.current.version),
+            winner,
+            false,
+            created_at,
+            format!("{} vs {}", a.name, b.name),
+            None,
+        );
+        mirror::upsert_matches(&[m], &self.db).await.unwrap();
+    }
+
+    /// Signs in through `/login` and the callback, the session cookie is kept for later requests.
+    pub async fn login(&self, discord_id: &str) {
+        let response = self.client.get("/login").dispatch().await;
+        let (callback, _) = location(&response).unwrap().split_once('?').unwrap();
+        let callback = callback.to_string();
+
+        let response = self
+            .client
+            .get(format!("{}?code={}", callback, discord_id))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Found);
+    }
+
+    /// The user behind `discord_id`, signing them up if needed.
+    pub async fn user(&self, discord_id: &str) -> User {
+        let info = stub_user(discord_id).unwrap();
+        User::upsert_by_discord_id(&info.id, &info.username, "", &self.db)
+            .await
+            .unwrap()
+    }
+
+    /// A token of `discord_id` with every non-admin scope, signing them up if needed.
+    pub async fn token(&self, discord_id: &str) -> String {
+        self.scoped_token(discord_id, &Scope::FIXED).await
+    }
+
+    /// A token of `discord_id` with only `scopes`, signing them up if needed.
+    pub async fn scoped_token(&self, discord_id: &str, scopes: &[Scope]) -> String {
+        let user = self.user(discord_id).await;
+
+        UserToken::insert_user_token(
+            "test".into(),
+            user.id,
+            None,
+            &Scopes(scopes.to_vec()),
+            &self.db,
+        )
+        .await
+        .unwrap()
+        .secret
+    }
+
+    pub async fn get_json(&self, uri: &str, token: &str) -> (Status, serde_json::Value) {
+        let response = self
+            .client
+            .get(uri.to_string())
+            .header(Header::new("x-auth-token", token.to_string()))
+            .dispatch()
+            .await;
+        let status = response.status();
+        (status, response.into_json().await.unwrap_or_default())
+    }
+
+    pub async fn post_json(
+        &self,
+        uri: &str,
+        token: &str,
+        body: serde_json::Value,
+    ) -> (Status, serde_json::Value) {
+        let response = self
+            .client
+            .post(uri.to_string())
+            .header(Header::new("x-auth-token", token.to_string()))
+            .header(ContentType::JSON)
+            .body(body.to_string())
+            .dispatch()
+            .await;
+        let status = response.status();
+        (status, response.into_json().await.unwrap_or_default())
+    }
+
+    pub async fn delete(&self, uri: &str, token: &str) -> (Status, serde_json::Value) {
+        let response = self
+            .client
+            .delete(uri.to_string())
+            .header(Header::new("x-auth-token", token.to_string()))
+            .dispatch()
+            .await;
+        let status = response.status();
+        (status, response.into_json().await.unwrap_or_default())
+    }
+
+    /// Uploads a new version of pilot `name`.
+    pub async fn upload(&self, name: &str, token: &str) -> (Status, serde_json::Value) {
+        let response = self
+            .client
+            .post(format!("/api/aipilot/upload?name={}", name))
+            .header(Header::new("x-auth-token", token.to_string()))
+            .header(ContentType::Binary)
+            .body("pilot")
+            .dispatch()
+            .await;
+        let status = response.status();
+        (status, response.into_json().await.unwrap_or_default())
+    }
+
+    pub async fn get_html(&self, uri: &str) -> (Status, String) {
+        let response = self.client.get(uri.to_string()).dispatch().await;
+        let status = response.status();
+        (status, response.into_string().await.unwrap_or_default())
+    }
+}
+
+pub fn location<'a>(response: &'a LocalResponse<'_>) -> Option<&'a str> {
+    response.headers().get_one("Location")
+}
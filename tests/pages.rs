@@ -0,0 +1,470 @@
+mod common;
+
+use std::time::Duration;
+
+use aip_front::scope::Scope;
+use common::{ALICE, BOB, TestApp, location};
+use rocket::{
+    http::{ContentType, Header, Status},
+    tokio::{io::AsyncReadExt, time::timeout},
+};
+use serde_json::json;
+
+#[rocket::async_test]
+async fn home_page() {
+    let app = TestApp::new("random").await;
+
+    let (status, body) = app.get_html("/").await;
+    assert_eq!(status, Status::Ok);
+    assert!(body.contains("/login"));
+
+    app.login(ALICE).await;
+    let (_, body) = app.get_html("/").await;
+    assert!(body.contains("alice"));
+}
+
+#[rocket::async_test]
+async fn login_redirects_back() {
+    let app = TestApp::new("random").await;
+
+    let response = app.client.get("/user_tokens").dispatch().await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(location(&response), Some("/login?next=/user_tokens"));
+
+    let response = app.client.get("/login?next=/user_tokens").dispatch().await;
+    let callback = location(&response).unwrap().to_string();
+    assert!(callback.starts_with("/login_callback/"));
+    assert!(callback.ends_with(&format!(":__user_tokens?code={}", ALICE)));
+
+    let response = app.client.get(callback.clone()).dispatch().await;
+    assert_eq!(response.status(), Status::Found);
+    assert_eq!(
+        location(&response),
+        Some("/login_callback_redirect?next=/user_tokens")
+    );
+
+    // The nonce is used up
+    let response = app.client.get(callback).dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let (status, _) = app.get_html("/user_tokens").await;
+    assert_eq!(status, Status::Ok);
+
+    app.client.get("/logout").dispatch().await;
+    let response = app.client.get("/user_tokens").dispatch().await;
+    assert_eq!(response.status(), Status::SeeOther);
+}
+
+#[rocket::async_test]
+async fn login_needs_own_nonce() {
+    let app = TestApp::new("random").await;
+
+    // A callback the browser didn't start, e.g. with an attacker's code
+    let response = app
+        .client
+        .get(format!("/login_callback/deadbeef:__?code={}", BOB))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    for uri in [
+        format!("/login_callback/deadbeef:__?code={}", BOB),
+        format!("/login_callback?code={}&state=deadbeef:__", BOB),
+        format!("/login_callback?code={}", BOB),
+    ] {
+        app.client.get("/login").dispatch().await;
+        let response = app.client.get(uri).dispatch().await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    let (status, _) = app.get_html("/user_tokens").await;
+    assert_ne!(status, Status::Ok);
+}
+
+#[rocket::async_test]
+async fn pilot_stats() {
+    let app = TestApp::new("a,b,a").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+
+    let (status, body) = app.get_html("/pilot/alpha").await;
+    assert_eq!(status, Status::Ok);
+    assert!(body.contains(r#"<div class="stat-value">3</div>"#));
+    assert!(body.contains(r#"<div class="stat-value success">2</div>"#));
+    assert!(body.contains(r#"<div class="stat-value danger">1</div>"#));
+    assert!(body.contains(r#"<div class="stat-value">67%</div>"#));
+
+    let (_, body) = app.get_html("/pilot/beta").await;
+    assert!(body.contains(r#"<div class="stat-value success">1</div>"#));
+    assert!(body.contains(r#"<div class="stat-value">33%</div>"#));
+}
+
+#[rocket::async_test]
+async fn undecided_matches() {
+    let app = TestApp::new("a,unknown,b").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+
+    // Left out of the win rate but still played
+    let (_, body) = app.get_html("/pilot/alpha").await;
+    assert!(body.contains(r#"<div class="stat-value">3</div>"#));
+    assert!(body.contains(r#"<div class="stat-value stat-undecided">1</div>"#));
+    assert!(body.contains(r#"<div class="stat-value">50%</div>"#));
+    assert!(body.contains(r#"<span class="stat-undecided" title="Undecided">1?</span>"#));
+    assert!(body.contains(r#"<div class="match-result-icon muted">"#));
+
+    let (_, body) = app.get_html(&format!("/user/{}", ALICE)).await;
+    assert!(body.contains(r#"<div class="stat-value stat-undecided">1</div>"#));
+    assert!(body.contains(r#"<div class="stat-value">50.0%</div>"#));
+
+    let (_, body) = app.get_html("/pilot/alpha/version/1").await;
+    assert!(body.contains(r#"<div class="stat-value stat-undecided">1</div>"#));
+    assert!(body.contains("50% win rate"));
+
+    let (_, body) = app.get_html("/users").await;
+    assert!(body.contains("1 pilot"));
+    assert!(body.contains("50.0% win rate"));
+    assert!(body.contains("1 undecided"));
+
+    let (_, body) = app.get_html("/badge/pilot/alpha.svg").await;
+    assert!(body.contains("alpha: v1 · 50% (1-1-1) · last lost"));
+}
+
+#[rocket::async_test]
+async fn admin_only_pages() {
+    let app = TestApp::new("random").await;
+
+    app.login(BOB).await;
+    let (status, body) = app.get_html("/admin").await;
+    assert_eq!(status, Status::Forbidden);
+    assert!(body.contains("Forbidden"));
+
+    app.login(ALICE).await;
+    let (status, _) = app.get_html("/admin").await;
+    assert_eq!(status, Status::Ok);
+}
+
+#[rocket::async_test]
+async fn metrics_need_token_or_admin() {
+    let app = TestApp::new("random").await;
+
+    let scrape = |token: &'static str| {
+        app.client
+            .get("/metrics")
+            .header(Header::new("Authorization", format!("Bearer {token}")))
+            .dispatch()
+    };
+    assert_eq!(scrape("scrape").await.status(), Status::Ok);
+    assert_eq!(scrape("wrong").await.status(), Status::Forbidden);
+
+    app.login(BOB).await;
+    let (status, _) = app.get_html("/metrics").await;
+    assert_eq!(status, Status::Forbidden);
+
+    app.login(ALICE).await;
+    let (status, _) = app.get_html("/metrics").await;
+    assert_eq!(status, Status::Ok);
+}
+
+#[rocket::async_test]
+async fn error_pages() {
+    let app = TestApp::new("random").await;
+
+    let (status, body) = app.get_html("/no/such/page").await;
+    assert_eq!(status, Status::NotFound);
+    assert!(body.contains("Not Found"));
+
+    let (status, _) = app.get_html("/pilot/nobody").await;
+    assert_eq!(status, Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn upstream_unavailable_page() {
+    let app = TestApp::offline().await;
+
+    let response = app
+        .client
+        .get("/match/00000000-0000-0000-0000-000000000001")
+        .header(Header::new("Accept", "text/html"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    let body = response.into_string().await.unwrap();
+    assert!(body.contains("The AI Pilot server is unavailable"));
+}
+
+#[rocket::async_test]
+async fn atom_feeds() {
+    let app = TestApp::new("a,b").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+
+    let response = app.client.get("/feeds/pilot/alpha.atom").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.content_type().unwrap().to_string(),
+        "application/atom+xml"
+    );
+    let etag = response.headers().get_one("ETag").unwrap().to_string();
+    let last_modified = response
+        .headers()
+        .get_one("Last-Modified")
+        .unwrap()
+        .to_string();
+    let body = response.into_string().await.unwrap();
+    assert_eq!(body.matches("<entry>").count(), 3);
+    assert!(body.contains("alpha v1 beat beta v1"));
+    assert!(body.contains("beta v1 beat alpha v1"));
+    assert!(body.contains("alpha v1 uploaded"));
+    assert!(body.contains("/match/"));
+
+    let response = app
+        .client
+        .get("/feeds/pilot/alpha.atom")
+        .header(Header::new("If-None-Match", etag.clone()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotModified);
+
+    let response = app
+        .client
+        .get("/feeds/pilot/alpha.atom")
+        .header(Header::new("If-Modified-Since", last_modified))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotModified);
+
+    app.fight("beta", "alpha").await;
+    let response = app
+        .client
+        .get("/feeds/pilot/alpha.atom")
+        .header(Header::new("If-None-Match", etag))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let (status, body) = app.get_html(&format!("/feeds/user/{}.atom", BOB)).await;
+    assert_eq!(status, Status::Ok);
+    assert!(body.contains("beta v1 uploaded"));
+    assert!(!body.contains("alpha v1 uploaded"));
+
+    let (status, body) = app.get_html("/feeds/matches.atom").await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body.matches("<entry>").count(), 5);
+
+    let (status, _) = app.get_html("/feeds/pilot/gamma.atom").await;
+    assert_eq!(status, Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn badges() {
+    let app = TestApp::new("a,b,a").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+
+    let response = app.client.get("/badge/pilot/alpha.svg").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::SVG));
+    assert!(response.headers().get_one("Cache-Control").is_some());
+    let body = response.into_string().await.unwrap();
+    assert!(body.contains("alpha: v1 · 67% (2-1) · last won"));
+
+    let (_, body) = app
+        .get_html(
+            "/badge/pilot/beta.svg?style=for-the-badge&label=rival&color=ff0000&metric=rating",
+        )
+        .await;
+    assert!(body.contains("RIVAL"));
+    assert!(body.contains(r##"fill="#ff0000""##));
+    assert!(body.contains("RATING · LAST LOST"));
+
+    // Anything but a color is dropped rather than ending up in the markup
+    let (_, body) = app
+        .get_html(r#"/badge/pilot/beta.svg?color=%22%2F%3E%3Cscript%3E"#)
+        .await;
+    assert!(!body.contains("<script>"));
+
+    let (_, body) = app.get_html(&format!("/badge/user/{}.svg", ALICE)).await;
+    assert!(body.contains("alice: 1 pilot · 67% (2-1) · last won"));
+
+    let (status, body) = app.get_html("/badge/pilot/gamma.svg").await;
+    assert_eq!(status, Status::NotFound);
+    assert!(body.contains("gamma: not found"));
+}
+
+#[rocket::async_test]
+async fn leaderboard_page() {
+    let app = TestApp::new("a,b,a").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+    app.fight("alpha", "beta").await;
+
+    let (status, body) = app.get_html("/leaderboard?min_games=3").await;
+    assert_eq!(status, Status::Ok);
+    assert!(body.contains("#1"));
+    assert!(body.contains("2W 1L"));
+    assert!(body.contains("67% win rate"));
+
+    let (_, body) = app.get_html("/leaderboard").await;
+    assert!(body.contains("No ranked pilots"));
+}
+
+#[rocket::async_test]
+async fn tournament_pages() {
+    let app = TestApp::new("a").await;
+    app.sync().await;
+    let token = app.token(ALICE).await;
+
+    let (_, body) = app
+        .post_json(
+            "/api/tournaments",
+            &token,
+            json!({
+                "name": "Spring Cup",
+                "format": "single_elimination",
+                "entrants": [{ "pilot": "alpha" }, { "pilot": "beta" }],
+            }),
+        )
+        .await;
+    let page = format!("/tournament/{}", body["id"]);
+
+    let (status, body) = app.get_html("/tournaments").await;
+    assert_eq!(status, Status::Ok);
+    assert!(body.contains("1 tournament<"));
+    assert!(body.contains("Spring Cup"));
+
+    // Only the creator gets to start it
+    let (status, body) = app.get_html(&page).await;
+    assert_eq!(status, Status::Ok);
+    assert!(body.contains("alpha"));
+    assert!(!body.contains("Start Tournament"));
+    app.login(BOB).await;
+    let (_, body) = app.get_html(&page).await;
+    assert!(!body.contains("Start Tournament"));
+    app.login(ALICE).await;
+    let (_, body) = app.get_html(&page).await;
+    assert!(body.contains("Start Tournament"));
+
+    let (status, _) = app.get_html("/tournament/999").await;
+    assert_eq!(status, Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn token_and_webhook_pages() {
+    let app = TestApp::new("random").await;
+    let bob = app.token(BOB).await;
+    app.post_json(
+        "/api/user_token",
+        &bob,
+        json!({ "name": "deploy bot", "scopes": ["pilots:read"] }),
+    )
+    .await;
+    app.post_json(
+        "/api/webhooks",
+        &bob,
+        json!({ "url": "https://93.184.215.14/bob", "events": ["match.completed"] }),
+    )
+    .await;
+
+    for page in ["/user_tokens", "/webhooks"] {
+        let response = app.client.get(page).dispatch().await;
+        assert_eq!(response.status(), Status::SeeOther);
+    }
+
+    app.login(BOB).await;
+    let (status, body) = app.get_html("/user_tokens").await;
+    assert_eq!(status, Status::Ok);
+    assert!(body.contains("deploy bot"));
+    let (status, body) = app.get_html("/webhooks").await;
+    assert_eq!(status, Status::Ok);
+    assert!(body.contains("https://93.184.215.14/bob"));
+
+    // Nobody else's
+    app.login(ALICE).await;
+    let (_, body) = app.get_html("/user_tokens").await;
+    assert!(!body.contains("deploy bot"));
+    let (_, body) = app.get_html("/webhooks").await;
+    assert!(!body.contains("https://93.184.215.14/bob"));
+}
+
+#[rocket::async_test]
+async fn audit_log() {
+    let app = TestApp::new("random").await;
+    let admin = app.scoped_token(ALICE, &[Scope::Admin]).await;
+    let bob = app.token(BOB).await;
+    let alice_id = app.user(ALICE).await.id;
+    let bob_id = app.user(BOB).await.id;
+
+    let (_, token) = app
+        .post_json(
+            "/api/user_token",
+            &bob,
+            json!({ "name": "ci", "scopes": ["pilots:read"] }),
+        )
+        .await;
+    let token_id = token["id"].as_i64().unwrap();
+    app.delete(&format!("/api/user_token/{}", token_id), &bob)
+        .await;
+    app.delete("/api/user_token/999", &bob).await;
+    let ban = format!("/api/admin/user/{}/ban", bob_id);
+    app.post_json(&ban, &admin, json!({ "reason": "spam" }))
+        .await;
+    app.delete(&ban, &admin).await;
+
+    let response = app.client.get("/audit").dispatch().await;
+    assert_eq!(response.status(), Status::SeeOther);
+
+    // Users only see their own events, whatever they ask for
+    app.login(BOB).await;
+    for page in ["/audit".to_string(), format!("/audit?actor={}", alice_id)] {
+        let (status, body) = app.get_html(&page).await;
+        assert_eq!(status, Status::Ok);
+        assert!(body.contains("Created a token"));
+        assert!(body.contains(&format!("→ {}</span>", token_id)));
+        assert!(body.contains("→ 999</span>"));
+        assert!(body.contains("HTTP 404"));
+        assert!(!body.contains("Banned a user"));
+    }
+
+    // Admins see everyone's, or one user's
+    app.login(ALICE).await;
+    let (_, body) = app.get_html("/audit").await;
+    assert!(body.contains("Created a token"));
+    assert!(body.contains(&format!("→ {} bob: spam</span>", bob_id)));
+    assert!(body.contains(&format!("→ {} bob</span>", bob_id)));
+    let (_, body) = app.get_html(&format!("/audit?actor={}", alice_id)).await;
+    assert!(body.contains("Unbanned a user"));
+    assert!(!body.contains("Created a token"));
+}
+
+#[rocket::async_test]
+async fn live_events() {
+    let app = TestApp::new("random").await;
+    app.sync().await;
+    let token = app.token(BOB).await;
+
+    let mut events = app.client.get("/events").dispatch().await;
+    assert_eq!(events.status(), Status::Ok);
+    assert_eq!(events.content_type(), Some(ContentType::EventStream));
+
+    // Rejected uploads aren't announced, so the first event is the second upload
+    let (status, _) = app.upload("no", &token).await;
+    assert_eq!(status, Status::BadRequest);
+    let (status, _) = app.upload("beta", &token).await;
+    assert_eq!(status, Status::Ok);
+
+    let mut received = String::new();
+    while !received.contains("\n\n") {
+        let mut buf = [0; 1024];
+        let read = timeout(Duration::from_secs(5), events.read(&mut buf))
+            .await
+            .expect("No event within 5 seconds")
+            .unwrap();
+        received.push_str(std::str::from_utf8(&buf[..read]).unwrap());
+    }
+    assert!(received.contains("event:pilot_uploaded"));
+    assert!(received.contains(r#""name":"beta","version":2"#));
+}
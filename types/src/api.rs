@@ -0,0 +1,66 @@
+use client::models::{AiPilot, AipVersion, MatchResult};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::scope::Scope;
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct DiscordUserInfo {
+    pub id: String,
+    pub username: String,
+    pub avatar: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AiPilotWithCreator {
+    pub id: Uuid,
+    pub name: String,
+    pub owner_id: String,
+    pub current: AipVersion,
+    pub versions: Vec<AipVersion>,
+    pub creator: Option<DiscordUserInfo>,
+}
+
+impl AiPilotWithCreator {
+    pub fn with_creator(pilot: AiPilot, creator: Option<DiscordUserInfo>) -> Self {
+        AiPilotWithCreator {
+            id: pilot.id,
+            name: pilot.name,
+            owner_id: pilot.owner_id,
+            current: pilot.current,
+            versions: pilot.versions,
+            creator,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GetAiPilotResponse {
+    pub pilots: Vec<AiPilotWithCreator>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GetMatchResponse {
+    pub matches: Vec<MatchResult>,
+    /// Pass as `cursor` to get the next page, missing on the last page
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PostAiPilotResponse {
+    pub upload_id: Uuid,
+    pub version: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct CreateUserToken {
+    pub name: String,
+    /// Unix timestamp in seconds
+    pub expires_at: Option<i64>,
+    pub scopes: Vec<Scope>,
+}
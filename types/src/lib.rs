@@ -0,0 +1,5 @@
+//! Request and response types of the site's JSON API, shared by the server and the `aip` CLI.
+
+pub mod api;
+pub mod model;
+pub mod scope;
@@ -0,0 +1,32 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::scope::Scopes;
+
+pub type UserId = i64;
+pub type UserTokenId = i64;
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct UserToken {
+    pub id: UserTokenId,
+    pub name: String,
+    pub user_id: UserId,
+    /// First characters of the secret, enough to recognize a token
+    pub token_prefix: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    #[cfg_attr(feature = "sqlx", sqlx(try_from = "String"))]
+    pub scopes: Scopes,
+}
+
+/// A freshly created token. This is the only time the secret is available,
+/// only its hash is stored.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct NewUserToken {
+    #[serde(flatten)]
+    pub token: UserToken,
+    pub secret: String,
+}
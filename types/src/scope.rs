@@ -0,0 +1,155 @@
+use std::{fmt, str::FromStr};
+
+use schemars::{JsonSchema, r#gen::SchemaGenerator, schema::Schema};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Permission carried by a user token. Browser sessions are not limited by scopes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Scope {
+    PilotsRead,
+    MatchesRead,
+    MatchesCreate,
+    /// Upload new versions of any pilot
+    PilotsUpload,
+    /// Upload new versions of a single pilot
+    PilotUpload(String),
+    TournamentsRead,
+    TournamentsManage,
+    TokensManage,
+    WebhooksManage,
+    /// Use the admin API, only grantable by admins
+    Admin,
+}
+
+impl Scope {
+    /// Every scope that does not name a specific pilot.
+    pub const FIXED: [Scope; 8] = [
+        Scope::PilotsRead,
+        Scope::MatchesRead,
+        Scope::MatchesCreate,
+        Scope::PilotsUpload,
+        Scope::TournamentsRead,
+        Scope::TournamentsManage,
+        Scope::TokensManage,
+        Scope::WebhooksManage,
+    ];
+
+    pub fn description(&self) -> String {
+        match self {
+            Scope::PilotsRead => "Read pilots, ratings and sync status".to_string(),
+            Scope::MatchesRead => "Read match results".to_string(),
+            Scope::MatchesCreate => "Start manual matches".to_string(),
+            Scope::PilotsUpload => "Upload any pilot".to_string(),
+            Scope::PilotUpload(name) => format!("Upload {}", name),
+            Scope::TournamentsRead => "Read tournaments".to_string(),
+            Scope::TournamentsManage => "Create and start tournaments".to_string(),
+            Scope::TokensManage => "Create and delete tokens".to_string(),
+            Scope::WebhooksManage => "Manage webhooks and read their deliveries".to_string(),
+            Scope::Admin => "Moderate users and tokens".to_string(),
+        }
+    }
+
+    /// Whether holding `self` grants `required`.
+    pub fn grants(&self, required: &Scope) -> bool {
+        match (self, required) {
+            (Scope::PilotsUpload, Scope::PilotUpload(_)) => true,
+            _ => self == required,
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::PilotsRead => write!(f, "pilots:read"),
+            Scope::MatchesRead => write!(f, "matches:read"),
+            Scope::MatchesCreate => write!(f, "matches:create"),
+            Scope::PilotsUpload => write!(f, "pilots:upload"),
+            Scope::PilotUpload(name) => write!(f, "pilot:{}:upload", name),
+            Scope::TournamentsRead => write!(f, "tournaments:read"),
+            Scope::TournamentsManage => write!(f, "tournaments:manage"),
+            Scope::TokensManage => write!(f, "tokens:manage"),
+            Scope::WebhooksManage => write!(f, "webhooks:manage"),
+            Scope::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(fixed) = Scope::FIXED.iter().find(|scope| scope.to_string() == s) {
+            return Ok(fixed.clone());
+        }
+        if s == "admin" {
+            return Ok(Scope::Admin);
+        }
+
+        match s
+            .strip_prefix("pilot:")
+            .and_then(|rest| rest.strip_suffix(":upload"))
+        {
+            Some(name)
+                if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') =>
+            {
+                Ok(Scope::PilotUpload(name.to_string()))
+            }
+            _ => Err(format!("Unknown scope {}", s)),
+        }
+    }
+}
+
+impl Serialize for Scope {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Scope {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl JsonSchema for Scope {
+    fn schema_name() -> String {
+        "Scope".to_string()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        String::json_schema(generator)
+    }
+}
+
+/// Space separated list of scopes as stored in the `user_tokens.scopes` column.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct Scopes(pub Vec<Scope>);
+
+impl Scopes {
+    pub fn grants(&self, required: &Scope) -> bool {
+        self.0.iter().any(|scope| scope.grants(required))
+    }
+}
+
+impl fmt::Display for Scopes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let joined: Vec<_> = self.0.iter().map(Scope::to_string).collect();
+        write!(f, "{}", joined.join(" "))
+    }
+}
+
+impl TryFrom<String> for Scopes {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value
+            .split_whitespace()
+            .map(Scope::from_str)
+            .collect::<Result<_, _>>()
+            .map(Scopes)
+    }
+}